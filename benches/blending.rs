@@ -0,0 +1,90 @@
+//! Reverse blending throughput: the per-pixel scalar loop versus the
+//! row-slice [`BlendTable`] path.
+//!
+//! Run with `cargo bench --bench blending`.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use gemini_watermark_removal::blending::{self, BlendTable};
+use gemini_watermark_removal::AlphaMap;
+use image::RgbImage;
+
+/// Alpha threshold and clamp used by the library.
+const ALPHA_THRESHOLD: f32 = 0.002;
+const MAX_ALPHA: f32 = 0.99;
+
+/// The original implementation: `get_pixel_mut` and scalar math per channel.
+fn naive_remove(image: &mut RgbImage, alpha_map: &[f32], size: u32, pos_x: u32, pos_y: u32) {
+    for dy in 0..size {
+        for dx in 0..size {
+            let alpha = alpha_map[(dy * size + dx) as usize];
+            if alpha < ALPHA_THRESHOLD {
+                continue;
+            }
+            let alpha = alpha.min(MAX_ALPHA);
+            let px = image.get_pixel_mut(pos_x + dx, pos_y + dy);
+            for ch in 0..3 {
+                let original = (f32::from(px[ch]) - alpha * 255.0) / (1.0 - alpha);
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                {
+                    px[ch] = original.clamp(0.0, 255.0) as u8;
+                }
+            }
+        }
+    }
+}
+
+/// A smooth synthetic alpha map peaking at 0.5 in the center.
+fn alpha_map(size: u32) -> AlphaMap {
+    #[allow(clippy::cast_precision_loss)]
+    let half = size as f32 / 2.0;
+    let values = (0..size * size)
+        .map(|i| {
+            #[allow(clippy::cast_precision_loss)]
+            let (dx, dy) = ((i % size) as f32 - half, (i / size) as f32 - half);
+            0.5 * (1.0 - (dx * dx + dy * dy).sqrt() / half).max(0.0)
+        })
+        .collect();
+    AlphaMap::new(values, size, size).unwrap()
+}
+
+fn bench_remove(c: &mut Criterion) {
+    let mut group = c.benchmark_group("reverse_blend");
+    for size in [48u32, 96] {
+        let alpha = alpha_map(size);
+        let table = BlendTable::new(&alpha, 255.0);
+        #[allow(clippy::cast_possible_truncation)]
+        let image = RgbImage::from_fn(size * 4, size * 4, |x, y| {
+            image::Rgb([(x * 3) as u8, (y * 5) as u8, (x ^ y) as u8])
+        });
+        let pos = size * 2;
+        group.throughput(Throughput::Elements(u64::from(size * size)));
+
+        group.bench_with_input(BenchmarkId::new("naive", size), &size, |b, &size| {
+            let mut img = image.clone();
+            b.iter(|| naive_remove(black_box(&mut img), alpha.values(), size, pos, pos));
+        });
+        group.bench_with_input(BenchmarkId::new("table", size), &size, |b, _| {
+            let mut img = image.clone();
+            b.iter(|| table.remove(black_box(&mut img), pos, pos));
+        });
+        group.bench_with_input(BenchmarkId::new("uncached", size), &size, |b, _| {
+            let mut img = image.clone();
+            b.iter(|| {
+                blending::remove_watermark_alpha_blend(
+                    black_box(&mut img),
+                    &alpha,
+                    pos,
+                    pos,
+                    255.0,
+                )
+                .unwrap();
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_remove);
+criterion_main!(benches);
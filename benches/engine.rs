@@ -0,0 +1,169 @@
+//! End-to-end engine benchmarks on a fixed synthetic dataset: engine
+//! initialization, in-memory detection and removal at both watermark sizes,
+//! single-file processing for JPEG and PNG (with and without verification),
+//! and directory throughput.
+//!
+//! Run with `cargo bench --bench engine`. The dataset is regenerated
+//! deterministically in the system temp directory on every run.
+
+use std::hint::black_box;
+use std::path::{Path, PathBuf};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use gemini_watermark_removal::{ProcessOptions, SynthesisOptions, WatermarkEngine, WatermarkSize};
+use image::RgbImage;
+
+/// Image dimensions for each watermark size (the large size needs both
+/// dimensions above 1024).
+const SMALL_DIMS: (u32, u32) = (1000, 750);
+const LARGE_DIMS: (u32, u32) = (2048, 1536);
+/// Number of files in the directory throughput dataset.
+const DIRECTORY_FILES: usize = 8;
+
+/// A textured image with the logo stamped on, identical across runs.
+fn watermarked(engine: &WatermarkEngine, (w, h): (u32, u32), seed: u64) -> RgbImage {
+    #[allow(clippy::cast_possible_truncation)]
+    let mut img = RgbImage::from_fn(w, h, |x, y| {
+        image::Rgb([
+            (x * 3 % 256) as u8,
+            (y * 5 % 256) as u8,
+            ((x ^ y) % 256) as u8,
+        ])
+    });
+    let opts = SynthesisOptions {
+        noise: 2.0,
+        seed,
+        ..SynthesisOptions::default()
+    };
+    engine.apply(&mut img, &opts).expect("failed to stamp logo");
+    img
+}
+
+/// Fresh, empty dataset directory.
+fn dataset_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join("gwr_bench").join(name);
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir_all(&dir).expect("failed to create dataset directory");
+    dir
+}
+
+fn size_label(size: WatermarkSize) -> &'static str {
+    match size {
+        WatermarkSize::Small => "48",
+        WatermarkSize::Large => "96",
+    }
+}
+
+fn bench_init(c: &mut Criterion) {
+    c.bench_function("engine_new", |b| {
+        b.iter(|| black_box(WatermarkEngine::new().unwrap()));
+    });
+}
+
+fn bench_detect_remove(c: &mut Criterion) {
+    let engine = WatermarkEngine::new().unwrap();
+    let opts = ProcessOptions::default();
+
+    let mut group = c.benchmark_group("engine");
+    for (size, dims) in [
+        (WatermarkSize::Small, SMALL_DIMS),
+        (WatermarkSize::Large, LARGE_DIMS),
+    ] {
+        let image = watermarked(&engine, dims, 1);
+        group.bench_with_input(
+            BenchmarkId::new("detect", size_label(size)),
+            &image,
+            |b, img| {
+                b.iter(|| engine.detect(black_box(img), &opts));
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("remove", size_label(size)),
+            &image,
+            |b, img| {
+                let mut img = img.clone();
+                b.iter(|| engine.remove(black_box(&mut img), None));
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_process_file(c: &mut Criterion) {
+    let engine = WatermarkEngine::new().unwrap();
+    let opts = ProcessOptions::default();
+    let verify = ProcessOptions {
+        verify: true,
+        ..ProcessOptions::default()
+    };
+    let dir = dataset_dir("process_file");
+    let image = watermarked(&engine, SMALL_DIMS, 2);
+
+    let mut group = c.benchmark_group("process_file");
+    group.sample_size(20);
+    for ext in ["jpg", "png"] {
+        let input = dir.join(format!("input.{ext}"));
+        let output = dir.join(format!("output.{ext}"));
+        gemini_watermark_removal::save_image(&image, &input).unwrap();
+
+        group.bench_with_input(BenchmarkId::new("process", ext), &input, |b, input| {
+            b.iter(|| engine.process_file(black_box(input), &output, &opts));
+        });
+        group.bench_with_input(
+            BenchmarkId::new("process_verify", ext),
+            &input,
+            |b, input| {
+                b.iter(|| engine.process_file(black_box(input), &output, &verify));
+            },
+        );
+        group.bench_with_input(BenchmarkId::new("detect_file", ext), &input, |b, input| {
+            b.iter(|| engine.detect_file(black_box(input), &opts).unwrap());
+        });
+    }
+    group.finish();
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+/// Write the directory dataset, alternating JPEG and PNG files.
+fn write_directory_dataset(engine: &WatermarkEngine, dir: &Path) {
+    for i in 0..DIRECTORY_FILES {
+        let ext = if i % 2 == 0 { "jpg" } else { "png" };
+        let image = watermarked(engine, SMALL_DIMS, i as u64);
+        gemini_watermark_removal::save_image(&image, &dir.join(format!("{i:02}.{ext}"))).unwrap();
+    }
+}
+
+fn bench_process_directory(c: &mut Criterion) {
+    let engine = WatermarkEngine::new().unwrap();
+    let input_dir = dataset_dir("directory_in");
+    let output_dir = dataset_dir("directory_out");
+    write_directory_dataset(&engine, &input_dir);
+
+    let mut group = c.benchmark_group("process_directory");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(DIRECTORY_FILES as u64));
+    for jobs in [Some(1), None] {
+        let label = jobs.map_or_else(|| "all_cores".to_string(), |n| format!("{n}_job"));
+        let opts = ProcessOptions {
+            jobs,
+            quiet: true,
+            ..ProcessOptions::default()
+        };
+        group.bench_function(label, |b| {
+            b.iter(|| engine.process_directory(black_box(&input_dir), &output_dir, &opts));
+        });
+    }
+    group.finish();
+
+    std::fs::remove_dir_all(&input_dir).ok();
+    std::fs::remove_dir_all(&output_dir).ok();
+}
+
+criterion_group!(
+    benches,
+    bench_init,
+    bench_detect_remove,
+    bench_process_file,
+    bench_process_directory
+);
+criterion_main!(benches);
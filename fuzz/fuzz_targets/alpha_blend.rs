@@ -0,0 +1,61 @@
+//! Forward and reverse blending with arbitrary alpha maps, sizes, positions
+//! and logo values, through both the free functions and `BlendTable`.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use gemini_watermark_removal::blending::{
+    apply_watermark_alpha_blend, remove_watermark_alpha_blend, BlendTable,
+};
+use gemini_watermark_removal::AlphaMap;
+use image::RgbImage;
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    width: u8,
+    height: u8,
+    fill: [u8; 3],
+    raw_alpha: Vec<f32>,
+    alpha: Vec<u8>,
+    wm_width: u8,
+    wm_height: u8,
+    pos_x: u32,
+    pos_y: u32,
+    logo_value: f32,
+}
+
+fuzz_target!(|input: Input| {
+    let Input {
+        width,
+        height,
+        fill,
+        raw_alpha,
+        alpha,
+        wm_width,
+        wm_height,
+        pos_x,
+        pos_y,
+        logo_value,
+    } = input;
+    let (wm_width, wm_height) = (u32::from(wm_width), u32::from(wm_height));
+
+    // Validation must reject, not panic on, arbitrary values
+    let len = raw_alpha.len();
+    let _ = AlphaMap::new(raw_alpha, u32::try_from(len).unwrap_or(u32::MAX), 1);
+
+    // A valid map of the requested size, tiled with `alpha`
+    let len = (wm_width * wm_height) as usize;
+    let values = alpha.iter().cycle().take(len).map(|&a| f32::from(a) / 255.0);
+    let values: Vec<f32> = values.chain(std::iter::repeat(0.0)).take(len).collect();
+    let Ok(alpha_map) = AlphaMap::new(values, wm_width, wm_height) else {
+        return;
+    };
+
+    let mut img = RgbImage::from_pixel(width.into(), height.into(), image::Rgb(fill));
+    let _ = apply_watermark_alpha_blend(&mut img, &alpha_map, pos_x, pos_y, logo_value);
+    let _ = remove_watermark_alpha_blend(&mut img, &alpha_map, pos_x, pos_y, logo_value);
+
+    let table = BlendTable::new(&alpha_map, logo_value);
+    table.remove(&mut img, pos_x, pos_y);
+});
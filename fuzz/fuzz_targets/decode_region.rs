@@ -0,0 +1,48 @@
+//! Region-only decoding of arbitrary bytes with arbitrary rectangles,
+//! checked against a full decode whenever both succeed.
+//!
+//! Pixels are only compared for PNG: in a corrupt JPEG a damaged byte can
+//! look like a restart marker, and the decoder's error recovery then
+//! legitimately differs from the cut stream.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use gemini_watermark_removal::decode::decode_region;
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+fuzz_target!(|input: Input| {
+    let dir = std::env::temp_dir().join(format!("gwr_fuzz_decode_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("input.img");
+    std::fs::write(&path, &input.data).unwrap();
+
+    let Ok(region) = decode_region(&path, input.x, input.y, input.width, input.height) else {
+        return;
+    };
+    let Ok(format) = image::guess_format(&input.data) else {
+        return;
+    };
+    let Ok(full) = image::load_from_memory(&input.data) else {
+        return;
+    };
+    let full = full.to_rgb8();
+    let x = input.x.min(full.width());
+    let y = input.y.min(full.height());
+    let width = input.width.min(full.width() - x);
+    let height = input.height.min(full.height() - y);
+    assert_eq!(region.dimensions(), (width, height));
+    if format == image::ImageFormat::Png {
+        let expected = image::imageops::crop_imm(&full, x, y, width, height).to_image();
+        assert_eq!(region, expected);
+    }
+});
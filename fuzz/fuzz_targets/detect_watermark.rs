@@ -0,0 +1,69 @@
+//! `detect_watermark` and `ncc_heatmap` with arbitrary images, alpha maps,
+//! watermark sizes, positions and logo values.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use gemini_watermark_removal::detection::detect_watermark;
+use gemini_watermark_removal::heatmap::{ncc_heatmap, SearchRegion};
+use gemini_watermark_removal::{AlphaMap, DetectionConfig};
+use image::RgbImage;
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    width: u8,
+    height: u8,
+    pixels: Vec<u8>,
+    alpha: Vec<u8>,
+    wm_width: u8,
+    wm_height: u8,
+    pos_x: u32,
+    pos_y: u32,
+    logo_value: u8,
+}
+
+/// Image of the given size tiled with `pixels`.
+fn image(width: u8, height: u8, pixels: &[u8]) -> RgbImage {
+    let mut img = RgbImage::new(width.into(), height.into());
+    if !pixels.is_empty() {
+        for (value, &p) in img.iter_mut().zip(pixels.iter().cycle()) {
+            *value = p;
+        }
+    }
+    img
+}
+
+/// Alpha map of the given size tiled with `alpha`, or `None` for an empty size.
+fn alpha_map(width: u8, height: u8, alpha: &[u8]) -> Option<AlphaMap> {
+    let len = usize::from(width) * usize::from(height);
+    let values = alpha.iter().cycle().take(len).map(|&a| f32::from(a) / 255.0);
+    let values = values.chain(std::iter::repeat(0.0)).take(len).collect();
+    AlphaMap::new(values, width.into(), height.into()).ok()
+}
+
+fuzz_target!(|input: Input| {
+    let img = image(input.width, input.height, &input.pixels);
+    let Some(alpha_map) = alpha_map(input.wm_width, input.wm_height, &input.alpha) else {
+        return;
+    };
+    if let Ok(result) = detect_watermark(
+        &img,
+        &alpha_map,
+        input.pos_x,
+        input.pos_y,
+        f32::from(input.logo_value),
+        &DetectionConfig::default(),
+    ) {
+        assert!(
+            result.confidence.is_finite() && (0.0..=1.0).contains(&result.confidence),
+            "{result:?}"
+        );
+    }
+
+    // Small templates only: the FFT tile grows with the watermark size
+    if input.wm_width <= 64 && input.wm_height <= 64 {
+        let heatmap = ncc_heatmap(&img, &alpha_map, SearchRegion::full(&img));
+        let _ = heatmap.peaks(5);
+    }
+});
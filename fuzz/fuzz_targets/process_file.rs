@@ -0,0 +1,33 @@
+//! Arbitrary uploads through the full file pipeline: `detect_file` followed by
+//! `process_file` (decode, detect, remove, verify, save).
+
+#![no_main]
+
+use gemini_watermark_removal::{ProcessOptions, WatermarkEngine};
+use libfuzzer_sys::fuzz_target;
+
+/// Extension chosen by the first input byte, which selects the decoder
+/// `process_file` uses.
+const EXTENSIONS: [&str; 4] = ["png", "jpg", "webp", "bmp"];
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&selector, bytes)) = data.split_first() else {
+        return;
+    };
+    let dir = std::env::temp_dir().join(format!("gwr_fuzz_process_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let ext = EXTENSIONS[usize::from(selector) % EXTENSIONS.len()];
+    let input = dir.join(format!("input.{ext}"));
+    std::fs::write(&input, bytes).unwrap();
+
+    let engine = WatermarkEngine::new().unwrap();
+    let opts = ProcessOptions {
+        force: selector & 0x40 != 0,
+        verify: true,
+        retry: selector & 0x80 != 0,
+        quiet: true,
+        ..ProcessOptions::default()
+    };
+    let _ = engine.detect_file(&input, &opts);
+    let _ = engine.process_file(&input, &dir.join(format!("output.{ext}")), &opts);
+});
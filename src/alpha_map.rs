@@ -0,0 +1,751 @@
+//! Watermark alpha maps.
+//!
+//! An [`AlphaMap`] holds the per-pixel opacity of a watermark logo. Maps are
+//! usually derived from a capture of the logo rendered on a black background
+//! ([`AlphaMap::from_capture`]), and can be resampled, rotated, flipped,
+//! cropped and shifted by sub-pixel offsets to match a logo that was scaled
+//! or moved. [`AlphaMap::to_png`] and [`AlphaMap::to_raw`] export a map for
+//! inspection or for loading back later.
+
+use std::f64::consts::PI;
+use std::io::Cursor;
+use std::path::Path;
+
+use image::{GrayImage, ImageFormat, Luma, RgbImage};
+
+use crate::blending::ALPHA_THRESHOLD;
+use crate::error::{Error, Result};
+
+/// Per-pixel opacity of a watermark logo, with its dimensions.
+///
+/// Values are stored row-major and lie in `[0, 1]`, where 0 leaves the
+/// image untouched and 1 replaces it with the logo color. Because the map
+/// carries its own width and height, the blending and detection functions
+/// that take one cannot be handed a map of the wrong length.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlphaMap {
+    width: u32,
+    height: u32,
+    values: Vec<f32>,
+}
+
+/// Resampling filter for [`AlphaMap::resize`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResizeFilter {
+    /// Bilinear interpolation between the four nearest values.
+    #[default]
+    Bilinear,
+    /// Three-lobe Lanczos kernel. Sharper edges than bilinear, and widened
+    /// when downscaling so fine detail is filtered instead of aliased.
+    Lanczos3,
+}
+
+/// Summary statistics of an [`AlphaMap`], from [`AlphaMap::stats`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AlphaStats {
+    /// Smallest alpha value.
+    pub min: f32,
+    /// Largest alpha value.
+    pub max: f32,
+    /// Mean alpha over the whole map.
+    pub mean: f32,
+    /// Population standard deviation of the alpha values.
+    pub std_dev: f32,
+    /// Fraction of pixels with alpha above the blending threshold (0.002),
+    /// i.e. the pixels that blending actually changes.
+    pub coverage: f32,
+    /// Alpha-weighted center `(x, y)` in pixel coordinates, or the geometric
+    /// center for an all-zero map.
+    pub centroid: (f32, f32),
+}
+
+impl AlphaMap {
+    /// Wrap the row-major `values` of a `width` x `height` alpha map.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidAlphaMap`] if either dimension is zero,
+    /// `values` does not hold exactly `width * height` entries, or a value is
+    /// not a number in `[0, 1]`.
+    pub fn new(values: Vec<f32>, width: u32, height: u32) -> Result<Self> {
+        if width == 0 || height == 0 {
+            return Err(Error::InvalidAlphaMap(format!(
+                "dimensions must be non-zero, got {width}x{height}"
+            )));
+        }
+        let expected = (width as usize).checked_mul(height as usize);
+        if expected != Some(values.len()) {
+            return Err(Error::InvalidAlphaMap(format!(
+                "{width}x{height} map needs {} values, got {}",
+                u64::from(width) * u64::from(height),
+                values.len()
+            )));
+        }
+        if let Some(index) = values.iter().position(|v| !(0.0..=1.0).contains(v)) {
+            return Err(Error::InvalidAlphaMap(format!(
+                "value {} at ({}, {}) is outside [0, 1]",
+                values[index],
+                index % width as usize,
+                index / width as usize
+            )));
+        }
+        Ok(Self {
+            width,
+            height,
+            values,
+        })
+    }
+
+    /// Derive an alpha map from an encoded capture of the logo.
+    ///
+    /// The capture is a screenshot of the watermark rendered on a black
+    /// background; the alpha at each pixel is `max(R, G, B) / 255`. Any
+    /// format the `image` crate can guess from the bytes is accepted, so a
+    /// map exported with [`to_png`](Self::to_png) loads back unchanged up to
+    /// 8-bit quantization.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::AlphaMapDecode`] if the data cannot be decoded.
+    pub fn from_capture(bytes: &[u8]) -> Result<Self> {
+        let image = image::load_from_memory(bytes)
+            .map_err(Error::AlphaMapDecode)?
+            .to_rgb8();
+        Self::from_capture_image(&image)
+    }
+
+    /// Derive an alpha map from a decoded capture (see
+    /// [`from_capture`](Self::from_capture)).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidAlphaMap`] if the image is empty.
+    pub fn from_capture_image(image: &RgbImage) -> Result<Self> {
+        let values = image
+            .pixels()
+            .map(|px| f32::from(px[0].max(px[1]).max(px[2])) / 255.0)
+            .collect();
+        Self::new(values, image.width(), image.height())
+    }
+
+    /// Load a map written by [`to_raw`](Self::to_raw).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidAlphaMap`] if `bytes` is not `4 * width * height`
+    /// long or holds values that [`new`](Self::new) rejects.
+    pub fn from_raw(bytes: &[u8], width: u32, height: u32) -> Result<Self> {
+        if bytes.len() % 4 != 0 {
+            return Err(Error::InvalidAlphaMap(format!(
+                "raw data is {} bytes, not a whole number of f32 values",
+                bytes.len()
+            )));
+        }
+        let values = bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        Self::new(values, width, height)
+    }
+
+    /// Width in pixels.
+    #[must_use]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height in pixels.
+    #[must_use]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Row-major alpha values, `width * height` entries.
+    #[must_use]
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    /// Alpha at `(x, y)`, or `None` outside the map.
+    #[must_use]
+    pub fn get(&self, x: u32, y: u32) -> Option<f32> {
+        (x < self.width && y < self.height).then(|| self.values[self.index(x, y)])
+    }
+
+    /// Check that the map placed with its top-left corner at `(x, y)` stays
+    /// within `u32` coordinates.
+    pub(crate) fn check_position(&self, x: u32, y: u32) -> Result<()> {
+        if x.checked_add(self.width).is_none() || y.checked_add(self.height).is_none() {
+            return Err(Error::InvalidRegion {
+                x,
+                y,
+                width: self.width,
+                height: self.height,
+            });
+        }
+        Ok(())
+    }
+
+    /// Resample to `new_width` x `new_height` with `filter`.
+    ///
+    /// Sample centers are aligned (`(x + 0.5) * scale - 0.5`), so scaling by an
+    /// integer factor keeps the logo centered. Results are clamped to `[0, 1]`
+    /// (Lanczos can ring slightly past the input range).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidAlphaMap`] if either new dimension is zero.
+    pub fn resize(&self, new_width: u32, new_height: u32, filter: ResizeFilter) -> Result<Self> {
+        if new_width == 0 || new_height == 0 {
+            return Err(Error::InvalidAlphaMap(format!(
+                "cannot resize to {new_width}x{new_height}"
+            )));
+        }
+        Ok(match filter {
+            ResizeFilter::Bilinear => self.resize_bilinear(new_width, new_height),
+            ResizeFilter::Lanczos3 => {
+                let rows = lanczos_rows(&self.values, self.width, self.height, new_width);
+                let columns = transpose(&rows, new_width, self.height);
+                let resized = lanczos_rows(&columns, self.height, new_width, new_height);
+                Self {
+                    width: new_width,
+                    height: new_height,
+                    values: transpose(&resized, new_height, new_width),
+                }
+            }
+        })
+    }
+
+    fn resize_bilinear(&self, new_width: u32, new_height: u32) -> Self {
+        let (width, height) = (self.width, self.height);
+        let mut resized = Vec::with_capacity(new_width as usize * new_height as usize);
+
+        #[allow(clippy::cast_precision_loss)]
+        let (sx, sy) = (
+            width as f32 / new_width as f32,
+            height as f32 / new_height as f32,
+        );
+
+        for y in 0..new_height {
+            #[allow(clippy::cast_precision_loss)]
+            let fy = ((y as f32 + 0.5) * sy - 0.5).clamp(0.0, (height - 1) as f32);
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let y0 = fy as u32;
+            let y1 = (y0 + 1).min(height - 1);
+            #[allow(clippy::cast_precision_loss)]
+            let ty = fy - y0 as f32;
+
+            for x in 0..new_width {
+                #[allow(clippy::cast_precision_loss)]
+                let fx = ((x as f32 + 0.5) * sx - 0.5).clamp(0.0, (width - 1) as f32);
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let x0 = fx as u32;
+                let x1 = (x0 + 1).min(width - 1);
+                #[allow(clippy::cast_precision_loss)]
+                let tx = fx - x0 as f32;
+
+                let at = |x, y| self.values[self.index(x, y)];
+                let top = at(x0, y0) * (1.0 - tx) + at(x1, y0) * tx;
+                let bottom = at(x0, y1) * (1.0 - tx) + at(x1, y1) * tx;
+                resized.push((top * (1.0 - ty) + bottom * ty).clamp(0.0, 1.0));
+            }
+        }
+
+        Self {
+            width: new_width,
+            height: new_height,
+            values: resized,
+        }
+    }
+
+    /// Rotate a quarter turn clockwise.
+    #[must_use]
+    pub fn rotate90(&self) -> Self {
+        // New pixel (x, y) comes from old (y, height - 1 - x)
+        self.remap(self.height, self.width, |x, y| (y, self.height - 1 - x))
+    }
+
+    /// Rotate a half turn.
+    #[must_use]
+    pub fn rotate180(&self) -> Self {
+        self.remap(self.width, self.height, |x, y| {
+            (self.width - 1 - x, self.height - 1 - y)
+        })
+    }
+
+    /// Rotate a quarter turn counter-clockwise.
+    #[must_use]
+    pub fn rotate270(&self) -> Self {
+        self.remap(self.height, self.width, |x, y| (self.width - 1 - y, x))
+    }
+
+    /// Mirror left to right.
+    #[must_use]
+    pub fn flip_horizontal(&self) -> Self {
+        self.remap(self.width, self.height, |x, y| (self.width - 1 - x, y))
+    }
+
+    /// Mirror top to bottom.
+    #[must_use]
+    pub fn flip_vertical(&self) -> Self {
+        self.remap(self.width, self.height, |x, y| (x, self.height - 1 - y))
+    }
+
+    /// The `width` x `height` sub-map with its top-left corner at `(x, y)`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidAlphaMap`] if the crop is empty or extends past
+    /// the map.
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Result<Self> {
+        let inside = x.checked_add(width).is_some_and(|x2| x2 <= self.width)
+            && y.checked_add(height).is_some_and(|y2| y2 <= self.height);
+        if !inside || width == 0 || height == 0 {
+            return Err(Error::InvalidAlphaMap(format!(
+                "crop {width}x{height} at ({x}, {y}) is outside the {}x{} map",
+                self.width, self.height
+            )));
+        }
+        Ok(self.remap(width, height, |dx, dy| (x + dx, y + dy)))
+    }
+
+    /// Translate the logo by `(dx, dy)` pixels, which need not be whole.
+    ///
+    /// Values are resampled bilinearly; pixels shifted in from outside the
+    /// map are transparent. Use this to match a logo rendered at a sub-pixel
+    /// offset from the capture.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidAlphaMap`] if an offset is not finite.
+    pub fn shift(&self, dx: f32, dy: f32) -> Result<Self> {
+        if !dx.is_finite() || !dy.is_finite() {
+            return Err(Error::InvalidAlphaMap(format!(
+                "shift offsets must be finite, got ({dx}, {dy})"
+            )));
+        }
+        let sample = |x: i64, y: i64| {
+            let (Ok(x), Ok(y)) = (u32::try_from(x), u32::try_from(y)) else {
+                return 0.0;
+            };
+            self.get(x, y).map_or(0.0, f64::from)
+        };
+        // Sample coordinates past a one-pixel border only read zeros, so
+        // clamping them keeps the integer corners from overflowing
+        let (max_x, max_y) = (f64::from(self.width) + 1.0, f64::from(self.height) + 1.0);
+        let mut values = Vec::with_capacity(self.values.len());
+        for y in 0..self.height {
+            let fy = (f64::from(y) - f64::from(dy)).clamp(-1.0, max_y);
+            let y0 = fy.floor();
+            let ty = fy - y0;
+            for x in 0..self.width {
+                let fx = (f64::from(x) - f64::from(dx)).clamp(-1.0, max_x);
+                let x0 = fx.floor();
+                let tx = fx - x0;
+                #[allow(clippy::cast_possible_truncation)]
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let top = sample(x0, y0) * (1.0 - tx) + sample(x0 + 1, y0) * tx;
+                let bottom = sample(x0, y0 + 1) * (1.0 - tx) + sample(x0 + 1, y0 + 1) * tx;
+                #[allow(clippy::cast_possible_truncation)]
+                values.push((top * (1.0 - ty) + bottom * ty).clamp(0.0, 1.0) as f32);
+            }
+        }
+        Ok(Self {
+            width: self.width,
+            height: self.height,
+            values,
+        })
+    }
+
+    /// Summary statistics of the alpha values.
+    #[must_use]
+    pub fn stats(&self) -> AlphaStats {
+        #[allow(clippy::cast_precision_loss)]
+        let count = self.values.len() as f64;
+        let (mut min, mut max) = (f32::INFINITY, f32::NEG_INFINITY);
+        let (mut sum, mut sum_sq, mut covered) = (0.0f64, 0.0f64, 0usize);
+        let (mut sum_x, mut sum_y) = (0.0f64, 0.0f64);
+        for (i, &alpha) in self.values.iter().enumerate() {
+            min = min.min(alpha);
+            max = max.max(alpha);
+            let a = f64::from(alpha);
+            sum += a;
+            sum_sq += a * a;
+            covered += usize::from(alpha >= ALPHA_THRESHOLD);
+            #[allow(clippy::cast_precision_loss)]
+            let (x, y) = (
+                (i % self.width as usize) as f64,
+                (i / self.width as usize) as f64,
+            );
+            sum_x += a * x;
+            sum_y += a * y;
+        }
+        let mean = sum / count;
+        let centroid = if sum > 0.0 {
+            (sum_x / sum, sum_y / sum)
+        } else {
+            (
+                (f64::from(self.width) - 1.0) / 2.0,
+                (f64::from(self.height) - 1.0) / 2.0,
+            )
+        };
+        #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+        AlphaStats {
+            min,
+            max,
+            mean: mean as f32,
+            std_dev: (sum_sq / count - mean * mean).max(0.0).sqrt() as f32,
+            coverage: (covered as f64 / count) as f32,
+            centroid: (centroid.0 as f32, centroid.1 as f32),
+        }
+    }
+
+    /// Render as an 8-bit grayscale image, alpha 1 as white.
+    #[must_use]
+    pub fn to_image(&self) -> GrayImage {
+        GrayImage::from_fn(self.width, self.height, |x, y| {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let value = (self.values[self.index(x, y)] * 255.0).round() as u8;
+            Luma([value])
+        })
+    }
+
+    /// Encode [`to_image`](Self::to_image) as PNG. The result is itself a
+    /// valid capture for [`from_capture`](Self::from_capture).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Image`] if encoding fails.
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        let mut png = Vec::new();
+        self.to_image()
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+        Ok(png)
+    }
+
+    /// Write [`to_png`](Self::to_png) to `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Image`] if encoding fails or [`Error::Io`] if the file
+    /// cannot be written.
+    pub fn save_png(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_png()?)?;
+        Ok(())
+    }
+
+    /// The values as little-endian `f32`s, row-major and without a header.
+    /// Lossless, unlike PNG; load with [`from_raw`](Self::from_raw).
+    #[must_use]
+    pub fn to_raw(&self) -> Vec<u8> {
+        self.values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+
+    /// A `width` x `height` map whose pixel `(x, y)` is this map's pixel
+    /// `source(x, y)`.
+    fn remap(&self, width: u32, height: u32, source: impl Fn(u32, u32) -> (u32, u32)) -> Self {
+        let mut values = Vec::with_capacity(width as usize * height as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = source(x, y);
+                values.push(self.values[self.index(sx, sy)]);
+            }
+        }
+        Self {
+            width,
+            height,
+            values,
+        }
+    }
+}
+
+/// The Lanczos kernel with three lobes.
+fn lanczos3(x: f64) -> f64 {
+    if x.abs() < 1e-12 {
+        1.0
+    } else if x.abs() >= 3.0 {
+        0.0
+    } else {
+        let px = PI * x;
+        3.0 * px.sin() * (px / 3.0).sin() / (px * px)
+    }
+}
+
+/// Resample each of `height` rows of `width` values to `new_width` values
+/// with a Lanczos-3 kernel, widened by the scale factor when downscaling.
+/// Weights are renormalized at the edges.
+fn lanczos_rows(values: &[f32], width: u32, height: u32, new_width: u32) -> Vec<f32> {
+    let scale = f64::from(width) / f64::from(new_width);
+    let filter_scale = scale.max(1.0);
+    let support = 3.0 * filter_scale;
+    let last = f64::from(width - 1);
+
+    let taps: Vec<(usize, Vec<f64>)> = (0..new_width)
+        .map(|x| {
+            let center = (f64::from(x) + 0.5) * scale - 0.5;
+            let start = (center - support).ceil().clamp(0.0, last);
+            let end = (center + support).floor().clamp(start, last);
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let (start, end) = (start as usize, end as usize);
+            #[allow(clippy::cast_precision_loss)]
+            let mut weights: Vec<f64> = (start..=end)
+                .map(|i| lanczos3((i as f64 - center) / filter_scale))
+                .collect();
+            let sum: f64 = weights.iter().sum();
+            if sum.abs() > 1e-12 {
+                for w in &mut weights {
+                    *w /= sum;
+                }
+            }
+            (start, weights)
+        })
+        .collect();
+
+    let mut resized = Vec::with_capacity(new_width as usize * height as usize);
+    for row in values.chunks_exact(width as usize) {
+        for (start, weights) in &taps {
+            let value: f64 = row[*start..]
+                .iter()
+                .zip(weights)
+                .map(|(&v, w)| f64::from(v) * w)
+                .sum();
+            #[allow(clippy::cast_possible_truncation)]
+            resized.push(value.clamp(0.0, 1.0) as f32);
+        }
+    }
+    resized
+}
+
+/// Transpose a row-major `width` x `height` buffer.
+fn transpose(values: &[f32], width: u32, height: u32) -> Vec<f32> {
+    let (w, h) = (width as usize, height as usize);
+    let mut transposed = Vec::with_capacity(values.len());
+    for x in 0..w {
+        transposed.extend((0..h).map(|y| values[y * w + x]));
+    }
+    transposed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alpha_maps;
+
+    /// 3x2 map with distinct values, row-major 1..=6 tenths.
+    fn asymmetric() -> AlphaMap {
+        AlphaMap::new(vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6], 3, 2).unwrap()
+    }
+
+    #[test]
+    fn new_validates_length_dimensions_and_range() {
+        let map = AlphaMap::new(vec![0.0, 0.5, 1.0, 0.25], 2, 2).unwrap();
+        assert_eq!((map.width(), map.height()), (2, 2));
+        assert_eq!(map.get(1, 1), Some(0.25));
+        assert_eq!(map.get(2, 0), None);
+
+        for (values, w, h) in [
+            (vec![0.5; 3], 2, 2),
+            (vec![], 0, 4),
+            (vec![0.5, f32::NAN], 2, 1),
+            (vec![0.5, 1.5], 2, 1),
+        ] {
+            let err = AlphaMap::new(values, w, h).unwrap_err();
+            assert!(matches!(err, Error::InvalidAlphaMap(_)), "{err:?}");
+        }
+    }
+
+    #[test]
+    fn embedded_captures_load_with_correct_dimensions() {
+        for (png, size) in [(alpha_maps::BG_48_PNG, 48), (alpha_maps::BG_96_PNG, 96)] {
+            let map = AlphaMap::from_capture(png).unwrap();
+            assert_eq!((map.width(), map.height()), (size, size));
+            assert_eq!(map.values().len(), (size * size) as usize);
+        }
+    }
+
+    #[test]
+    fn from_capture_rejects_invalid_data() {
+        let err = AlphaMap::from_capture(&[0xFF, 0x00, 0x42]).unwrap_err();
+        assert!(
+            matches!(err, Error::AlphaMapDecode(_)),
+            "Expected AlphaMapDecode, got {err:?}"
+        );
+        let err = AlphaMap::from_capture_image(&RgbImage::new(0, 0)).unwrap_err();
+        assert!(matches!(err, Error::InvalidAlphaMap(_)), "{err:?}");
+    }
+
+    #[test]
+    fn check_position_rejects_overflow() {
+        let map = AlphaMap::new(vec![0.5; 4], 2, 2).unwrap();
+        assert!(map.check_position(u32::MAX - 2, 0).is_ok());
+        let err = map.check_position(0, u32::MAX - 1).unwrap_err();
+        assert!(
+            matches!(err, Error::InvalidRegion { width: 2, .. }),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn resize_preserves_constant_and_dimensions() {
+        let map = AlphaMap::new(vec![0.4; 48 * 48], 48, 48).unwrap();
+        for filter in [ResizeFilter::Bilinear, ResizeFilter::Lanczos3] {
+            let resized = map.resize(96, 72, filter).unwrap();
+            assert_eq!((resized.width(), resized.height()), (96, 72));
+            assert!(resized.values().iter().all(|&a| (a - 0.4).abs() < 1e-5));
+        }
+        assert!(map.resize(0, 10, ResizeFilter::Bilinear).is_err());
+
+        let large = AlphaMap::from_capture(alpha_maps::BG_96_PNG).unwrap();
+        let small = AlphaMap::from_capture(alpha_maps::BG_48_PNG).unwrap();
+        for filter in [ResizeFilter::Bilinear, ResizeFilter::Lanczos3] {
+            let down = large.resize(48, 48, filter).unwrap();
+            let max = down.stats().max;
+            assert!(
+                max > 0.3,
+                "{filter:?}: downscaled logo lost its peak, {max}"
+            );
+            // Downscaling the large capture approximates the small one
+            let mean_err = down
+                .values()
+                .iter()
+                .zip(small.values())
+                .map(|(a, b)| (a - b).abs())
+                .sum::<f32>()
+                / 2304.0;
+            assert!(mean_err < 0.05, "{filter:?}: {mean_err}");
+        }
+    }
+
+    #[test]
+    fn lanczos_upscale_is_sharper_than_bilinear() {
+        // A hard step from 0 to 1: Lanczos keeps a steeper edge
+        let step = AlphaMap::new(
+            (0..8).map(|i| if i < 4 { 0.0 } else { 1.0 }).collect(),
+            8,
+            1,
+        )
+        .unwrap();
+        let bilinear = step.resize(32, 1, ResizeFilter::Bilinear).unwrap();
+        let lanczos = step.resize(32, 1, ResizeFilter::Lanczos3).unwrap();
+        let steepest = |map: &AlphaMap| {
+            map.values()
+                .windows(2)
+                .map(|w| w[1] - w[0])
+                .fold(0.0f32, f32::max)
+        };
+        assert!(steepest(&lanczos) > steepest(&bilinear));
+    }
+
+    #[test]
+    fn rotations_and_flips() {
+        let map = asymmetric();
+        let r90 = map.rotate90();
+        assert_eq!((r90.width(), r90.height()), (2, 3));
+        // Clockwise: the bottom-left corner moves to the top-left
+        assert_eq!(r90.values(), &[0.4, 0.1, 0.5, 0.2, 0.6, 0.3]);
+        assert_eq!(r90.rotate270(), map);
+        assert_eq!(r90.rotate90(), map.rotate180());
+        assert_eq!(map.rotate180().rotate180(), map);
+
+        assert_eq!(
+            map.flip_horizontal().values(),
+            &[0.3, 0.2, 0.1, 0.6, 0.5, 0.4]
+        );
+        assert_eq!(
+            map.flip_vertical().values(),
+            &[0.4, 0.5, 0.6, 0.1, 0.2, 0.3]
+        );
+        assert_eq!(map.flip_horizontal().flip_vertical(), map.rotate180());
+    }
+
+    #[test]
+    fn crop_extracts_sub_map_and_rejects_out_of_bounds() {
+        let map = asymmetric();
+        let crop = map.crop(1, 0, 2, 2).unwrap();
+        assert_eq!((crop.width(), crop.height()), (2, 2));
+        assert_eq!(crop.values(), &[0.2, 0.3, 0.5, 0.6]);
+
+        for (x, y, w, h) in [
+            (2, 0, 2, 1),
+            (0, 0, 0, 1),
+            (0, 1, 1, 2),
+            (u32::MAX, 0, 2, 1),
+        ] {
+            assert!(map.crop(x, y, w, h).is_err(), "({x},{y}) {w}x{h}");
+        }
+    }
+
+    #[test]
+    fn shift_translates_and_interpolates() {
+        let map = asymmetric();
+        let shifted = map.shift(1.0, 0.0).unwrap();
+        let expected = [0.0, 0.1, 0.2, 0.0, 0.4, 0.5];
+        for (a, b) in shifted.values().iter().zip(expected) {
+            assert!((a - b).abs() < 1e-6, "{:?}", shifted.values());
+        }
+
+        let half = map.shift(0.0, 0.5).unwrap();
+        // Halfway between the rows above, and half the top row at y = 0
+        assert!((half.get(0, 1).unwrap() - 0.25).abs() < 1e-6);
+        assert!((half.get(2, 0).unwrap() - 0.15).abs() < 1e-6);
+
+        assert_eq!(map.shift(0.0, 0.0).unwrap(), map);
+        for (dx, dy) in [
+            (1e30, 0.0),
+            (-1e30, 0.0),
+            (0.0, 1e30),
+            (0.0, -1e30),
+            (-4.0, -3.0),
+        ] {
+            let far = map.shift(dx, dy).unwrap();
+            assert!(far.values().iter().all(|&a| a == 0.0), "({dx}, {dy})");
+        }
+        assert!(map.shift(f32::NAN, 0.0).is_err());
+    }
+
+    #[test]
+    fn stats_summarize_values() {
+        let stats = asymmetric().stats();
+        assert!((stats.min - 0.1).abs() < 1e-6);
+        assert!((stats.max - 0.6).abs() < 1e-6);
+        assert!((stats.mean - 0.35).abs() < 1e-6);
+        assert!((stats.std_dev - 0.170_782_5).abs() < 1e-5);
+        assert!((stats.coverage - 1.0).abs() < f32::EPSILON);
+
+        let empty = AlphaMap::new(vec![0.0; 9], 3, 3).unwrap().stats();
+        assert!(empty.coverage.abs() < f32::EPSILON);
+        assert_eq!(empty.centroid, (1.0, 1.0));
+
+        let corner = AlphaMap::new(vec![0.0, 0.0, 0.0, 1.0], 2, 2)
+            .unwrap()
+            .stats();
+        assert_eq!(corner.centroid, (1.0, 1.0));
+        assert!((corner.coverage - 0.25).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn png_and_raw_export_round_trip() {
+        let map = AlphaMap::from_capture(alpha_maps::BG_48_PNG).unwrap();
+        let from_png = AlphaMap::from_capture(&map.to_png().unwrap()).unwrap();
+        assert_eq!(from_png, map, "8-bit captures survive the PNG round trip");
+
+        let shifted = map.shift(0.3, -0.7).unwrap();
+        let raw = shifted.to_raw();
+        assert_eq!(raw.len(), 48 * 48 * 4);
+        assert_eq!(AlphaMap::from_raw(&raw, 48, 48).unwrap(), shifted);
+        assert!(AlphaMap::from_raw(&raw[1..], 48, 48).is_err());
+        assert!(AlphaMap::from_raw(&raw, 48, 47).is_err());
+
+        let dir = std::env::temp_dir().join(format!("gwr_alpha_map_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("map.png");
+        map.save_png(&path).unwrap();
+        let loaded = AlphaMap::from_capture(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(loaded, map);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
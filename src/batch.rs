@@ -0,0 +1,291 @@
+//! Progress reporting, cancellation and scheduling for batch processing.
+//!
+//! [`WatermarkEngine::process_directory_with`](crate::WatermarkEngine::process_directory_with)
+//! and [`WatermarkEngine::process_iter`](crate::WatermarkEngine::process_iter)
+//! report each file to a [`BatchObserver`] as it is processed and checks a
+//! [`CancellationToken`] between files, so GUIs and job runners can show
+//! progress and abort long batches.
+//!
+//! Workers also share a memory budget (see
+//! [`ProcessOptions::memory_budget`](crate::ProcessOptions::memory_budget)) that
+//! bounds how many decoded pixels are held in memory at once.
+
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+
+use image::ImageDecoder;
+
+use crate::engine::{ProcessResult, ProcessStatus};
+
+/// Receives progress events while a batch is being processed.
+///
+/// All methods have empty default implementations, so implementors only
+/// override the events they care about. Events may arrive from several worker
+/// threads at once when parallel processing is enabled.
+pub trait BatchObserver: Sync {
+    /// Called once before any file is processed, with the number of files
+    /// found (`None` when the batch is fed from an iterator of unknown length).
+    fn batch_started(&self, _total: Option<usize>) {}
+
+    /// Called when a worker starts processing `path`.
+    fn file_started(&self, _path: &Path) {}
+
+    /// Called when a file has been processed (successfully, skipped, or failed).
+    fn file_finished(&self, _result: &ProcessResult) {}
+
+    /// Called once after the batch completes or is cancelled.
+    fn batch_finished(&self, _summary: &BatchSummary) {}
+}
+
+/// Observer that ignores all events.
+impl BatchObserver for () {}
+
+/// Cooperative cancellation flag shared between a batch and its controller.
+///
+/// Cloning yields a handle to the same flag. Cancellation is checked between
+/// files: the file currently being processed by each worker still completes.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a new token that is not cancelled.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request cancellation. Files not yet started will not be processed.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether cancellation has been requested.
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Totals for a finished (or cancelled) batch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchSummary {
+    /// Number of files in the batch (for iterator-fed batches, the number of
+    /// pairs pulled before the iterator ended or the batch was cancelled).
+    pub total: usize,
+    /// Files where the watermark was removed and the output saved.
+    pub succeeded: usize,
+    /// Files saved with a residual watermark still detected (also counted in
+    /// `succeeded`).
+    pub residual: usize,
+    /// Files skipped (no watermark detected or image too small).
+    pub skipped: usize,
+    /// Files that failed to load, process, or save.
+    pub failed: usize,
+    /// Files never started because the batch was cancelled.
+    pub cancelled: usize,
+}
+
+impl BatchSummary {
+    /// Build a summary from the results of the files that were processed.
+    #[must_use]
+    pub fn from_results(total: usize, results: &[ProcessResult]) -> Self {
+        let mut summary = Self {
+            total,
+            cancelled: total.saturating_sub(results.len()),
+            ..Self::default()
+        };
+        for r in results {
+            summary.record(r);
+        }
+        summary
+    }
+
+    /// Count one processed file's outcome (`total` is left to the caller).
+    pub(crate) fn record(&mut self, result: &ProcessResult) {
+        match result.status {
+            ProcessStatus::Cleaned => self.succeeded += 1,
+            ProcessStatus::CleanedWithResidual => {
+                self.succeeded += 1;
+                self.residual += 1;
+            }
+            ProcessStatus::Skipped => self.skipped += 1,
+            ProcessStatus::Failed => self.failed += 1,
+        }
+    }
+
+    /// Number of files that were processed (not cancelled).
+    #[must_use]
+    pub fn processed(&self) -> usize {
+        self.succeeded + self.skipped + self.failed
+    }
+}
+
+/// Counting semaphore over bytes of decoded image data.
+///
+/// Each worker reserves the estimated size of the image it is about to decode
+/// and blocks until enough of the budget is free. Reservations larger than the
+/// whole budget are clamped to it, so an oversized image still runs (alone).
+#[derive(Debug)]
+pub(crate) struct MemoryBudget {
+    limit: u64,
+    used: Mutex<u64>,
+    freed: Condvar,
+}
+
+/// A reservation against a [`MemoryBudget`], released on drop.
+#[derive(Debug)]
+pub(crate) struct MemoryPermit<'a> {
+    budget: &'a MemoryBudget,
+    bytes: u64,
+}
+
+impl MemoryBudget {
+    pub(crate) fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            used: Mutex::new(0),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Block until `bytes` (clamped to the limit) fit in the budget, then reserve them.
+    pub(crate) fn acquire(&self, bytes: u64) -> MemoryPermit<'_> {
+        let bytes = bytes.min(self.limit);
+        let mut used = self.used.lock().unwrap_or_else(PoisonError::into_inner);
+        while *used + bytes > self.limit {
+            used = self
+                .freed
+                .wait(used)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        *used += bytes;
+        MemoryPermit {
+            budget: self,
+            bytes,
+        }
+    }
+}
+
+impl Drop for MemoryPermit<'_> {
+    fn drop(&mut self) {
+        let mut used = self
+            .budget
+            .used
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        *used -= self.bytes;
+        self.budget.freed.notify_all();
+    }
+}
+
+/// Estimate the memory needed to process the image at `path`.
+///
+/// Reads only the image header: the decoded buffer plus the RGB8 working copy.
+/// Returns 0 if the header cannot be read (the file will fail to load anyway).
+pub(crate) fn estimate_decoded_bytes(path: &Path) -> u64 {
+    let Ok(decoder) = image::ImageReader::open(path)
+        .and_then(image::ImageReader::with_guessed_format)
+        .map_err(image::ImageError::IoError)
+        .and_then(image::ImageReader::into_decoder)
+    else {
+        return 0;
+    };
+    let (w, h) = decoder.dimensions();
+    decoder
+        .total_bytes()
+        .saturating_add(u64::from(w) * u64::from(h) * 3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn result(status: ProcessStatus) -> ProcessResult {
+        ProcessResult {
+            path: PathBuf::from("x.png"),
+            success: status != ProcessStatus::Failed,
+            skipped: status == ProcessStatus::Skipped,
+            status,
+            confidence: 0.0,
+            residual: None,
+            profile: String::new(),
+            version: String::new(),
+            message: String::new(),
+        }
+    }
+
+    #[test]
+    fn cancellation_token_is_shared_between_clones() {
+        let token = CancellationToken::new();
+        let handle = token.clone();
+        assert!(!token.is_cancelled());
+        handle.cancel();
+        assert!(token.is_cancelled());
+    }
+
+    #[test]
+    fn summary_counts_each_outcome() {
+        let results = vec![
+            result(ProcessStatus::Cleaned),
+            result(ProcessStatus::CleanedWithResidual),
+            result(ProcessStatus::Skipped),
+            result(ProcessStatus::Failed),
+        ];
+        let summary = BatchSummary::from_results(6, &results);
+        assert_eq!(
+            summary,
+            BatchSummary {
+                total: 6,
+                succeeded: 2,
+                residual: 1,
+                skipped: 1,
+                failed: 1,
+                cancelled: 2,
+            }
+        );
+        assert_eq!(summary.processed(), 4);
+    }
+
+    #[test]
+    fn memory_budget_clamps_oversized_requests() {
+        let budget = MemoryBudget::new(100);
+        let permit = budget.acquire(1_000);
+        assert_eq!(permit.bytes, 100);
+        drop(permit);
+        assert_eq!(*budget.used.lock().unwrap(), 0);
+    }
+
+    #[test]
+    fn memory_budget_blocks_until_released() {
+        let budget = MemoryBudget::new(100);
+        let first = budget.acquire(60);
+
+        std::thread::scope(|scope| {
+            let waiter = scope.spawn(|| {
+                let _second = budget.acquire(60);
+                let used = *budget.used.lock().unwrap();
+                used
+            });
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            assert!(!waiter.is_finished(), "Second permit should wait");
+            drop(first);
+            assert_eq!(waiter.join().unwrap(), 60);
+        });
+    }
+
+    #[test]
+    fn estimate_decoded_bytes_reads_header() {
+        let dir = std::env::temp_dir().join("gwr_test_estimate_bytes");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("img.png");
+        image::RgbImage::new(40, 10).save(&path).unwrap();
+
+        // 40x10 RGB8 decoded buffer plus the RGB8 working copy
+        assert_eq!(estimate_decoded_bytes(&path), 2 * 40 * 10 * 3);
+        assert_eq!(estimate_decoded_bytes(&dir.join("missing.png")), 0);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
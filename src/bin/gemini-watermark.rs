@@ -1,10 +1,16 @@
+use std::io::{IsTerminal, Write};
 use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
 
+use gemini_watermark_removal::evaluation::{self, OperatingPoint};
 use gemini_watermark_removal::{
-    default_output_path, ProcessOptions, ProcessResult, WatermarkEngine, WatermarkSize,
+    default_output_path, is_supported_image, restore_backup, BatchObserver, BatchSummary,
+    CancellationToken, DetectionConfig, ProcessOptions, ProcessResult, ProcessStatus,
+    QualityReport, RemovalRegion, WatermarkEngine, WatermarkProfile, WatermarkSize,
 };
 
 #[derive(Parser)]
@@ -12,35 +18,131 @@ use gemini_watermark_removal::{
     name = "gemini-watermark",
     about = "Remove visible Gemini AI watermarks via reverse alpha blending",
     version,
-    after_help = "Simple usage: gemini-watermark <image>  (auto-detect and remove in-place)\n\n\
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    after_help = "Simple usage: gemini-watermark <image>  (auto-detect and remove, writes <name>_cleaned.<ext>)\n\
+                  In-place:     gemini-watermark <image> --in-place --backup-suffix .bak\n\n\
                   NOTE: This tool only removes the VISIBLE Gemini watermark (star/sparkle logo).\n\
                   It cannot remove SynthID (invisible watermark)."
 )]
-#[allow(clippy::struct_excessive_bools)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    remove: RemoveArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Restore original images from backups created with --backup-suffix
+    Restore(RestoreArgs),
+    /// Report PSNR, SSIM and residual watermark scores for cleaned images
+    Compare(CompareArgs),
+    /// Measure detection accuracy on labeled folders and suggest a threshold
+    Evaluate(EvaluateArgs),
+    /// Watch a directory and clean new images as they arrive
+    #[cfg(feature = "watch")]
+    Watch(WatchArgs),
+}
+
+#[derive(Args)]
+struct RemoveArgs {
     /// Input image file or directory
-    input: String,
+    #[arg(required = true)]
+    input: Option<String>,
 
     /// Output file or directory (default: {name}_cleaned.{ext})
-    #[arg(short, long)]
+    #[arg(short, long, conflicts_with = "in_place")]
     output: Option<String>,
 
+    /// Overwrite the input file(s) atomically instead of writing copies
+    #[arg(short, long)]
+    in_place: bool,
+
+    /// Back up files before overwriting them, appending this suffix (e.g. .bak)
+    #[arg(long, value_name = "SUFFIX")]
+    backup_suffix: Option<String>,
+
+    /// Number of parallel workers for batch processing (default: all cores)
+    #[arg(short, long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: Option<u16>,
+
+    /// Maximum memory for decoded images in flight during batch processing, in MiB
+    #[arg(long, value_name = "MIB", value_parser = clap::value_parser!(u64).range(1..))]
+    memory_budget: Option<u64>,
+
+    /// Save a heatmap of logo-like patterns over the whole image as a PNG (single image only)
+    #[arg(long, value_name = "PNG")]
+    heatmap: Option<PathBuf>,
+
+    #[command(flatten)]
+    process: ProcessArgs,
+}
+
+/// Detection and logging flags shared by the default mode and `watch`.
+#[derive(Args)]
+#[allow(clippy::struct_excessive_bools)]
+struct ProcessArgs {
     /// Skip watermark detection, process unconditionally
     #[arg(short, long)]
     force: bool,
 
-    /// Detection confidence threshold (0.0-1.0)
-    #[arg(short, long, default_value = "0.25")]
-    threshold: f32,
+    /// Detection confidence threshold (0.0-1.0) [default: 0.25, or from --config]
+    #[arg(short, long)]
+    threshold: Option<f32>,
 
-    /// Force 48x48 watermark size (for images <= 1024px)
+    /// Load detection weights and thresholds from a `key = value` config file
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Watermark profile to remove, or `auto` to use the best-scoring one
+    #[arg(long, value_name = "NAME", default_value = "auto")]
+    profile: String,
+
+    /// Register an extra watermark profile from a file, or add its logo
+    /// versions to the registered profile of the same name (repeatable)
+    #[arg(long, value_name = "FILE")]
+    profile_file: Vec<PathBuf>,
+
+    /// Logo version of the chosen --profile to remove; needs a --profile
+    /// other than auto [default: best-scoring]
+    #[arg(long, value_name = "ID")]
+    logo_version: Option<String>,
+
+    /// Watermark location: top-left corner X,Y, or offsets from a corner
+    /// (tl:X,Y, tr:X,Y, bl:X,Y, br:X,Y)
+    #[arg(long, value_name = "POS")]
+    position: Option<RemovalRegion>,
+
+    /// Watermark side length in pixels at --position (rescales the logo)
+    #[arg(long, value_name = "N", requires = "position")]
+    size: Option<u32>,
+
+    /// Force the small watermark size (48x48 for Gemini, images <= 1024px)
     #[arg(long)]
     force_small: bool,
 
-    /// Force 96x96 watermark size (for images > 1024px)
+    /// Force the large watermark size (96x96 for Gemini, images > 1024px)
     #[arg(long)]
     force_large: bool,
 
+    /// Retry with other sizes, offsets and logo values if a residual watermark remains
+    #[arg(long, conflicts_with = "no_verify")]
+    retry: bool,
+
+    /// Skip checking the cleaned image for a residual watermark
+    #[arg(long)]
+    no_verify: bool,
+
+    /// Residual confidence above which a cleaned image is flagged (0.0-1.0)
+    #[arg(long, value_name = "T", default_value = "0.25")]
+    residual_threshold: f32,
+
+    /// Write per-image debug images (ROI, alpha overlay, Sobel maps, diff) here
+    #[arg(long, value_name = "DIR")]
+    debug_dir: Option<PathBuf>,
+
     /// Enable verbose output
     #[arg(short, long)]
     verbose: bool,
@@ -50,110 +152,849 @@ struct Cli {
     quiet: bool,
 }
 
+#[derive(Args)]
+struct RestoreArgs {
+    /// Image file or directory whose backups should be restored
+    path: String,
+
+    /// Suffix the backups were created with
+    #[arg(long, value_name = "SUFFIX", default_value = ".bak")]
+    backup_suffix: String,
+
+    /// Suppress all non-error output
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+#[derive(Args)]
+struct CompareArgs {
+    /// Cleaned image file or directory
+    cleaned: String,
+
+    /// Reference image file or directory (matched by file name); without it
+    /// only the residual watermark score is reported
+    reference: Option<String>,
+
+    /// Residual detection threshold (0.0-1.0)
+    #[arg(short, long, default_value = "0.25")]
+    threshold: f32,
+
+    /// Force 48x48 watermark size (for images <= 1024px)
+    #[arg(long, conflicts_with = "force_large")]
+    force_small: bool,
+
+    /// Force 96x96 watermark size (for images > 1024px)
+    #[arg(long)]
+    force_large: bool,
+}
+
+#[derive(Args)]
+struct EvaluateArgs {
+    /// Directory of images known to carry the watermark
+    watermarked: PathBuf,
+
+    /// Directory of images known to be clean
+    clean: PathBuf,
+
+    /// Highest acceptable false-positive rate for the suggested threshold (0.0-1.0)
+    #[arg(long, value_name = "RATE", default_value = "0.01")]
+    target_fpr: f64,
+
+    /// Write the ROC and precision-recall curves to this CSV file
+    #[arg(long, value_name = "FILE")]
+    csv: Option<PathBuf>,
+
+    /// Load detection weights from a `key = value` config file
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Force 48x48 watermark size (for images <= 1024px)
+    #[arg(long, conflicts_with = "force_large")]
+    force_small: bool,
+
+    /// Force 96x96 watermark size (for images > 1024px)
+    #[arg(long)]
+    force_large: bool,
+}
+
+#[cfg(feature = "watch")]
+#[derive(Args)]
+struct WatchArgs {
+    /// Directory to watch for new images
+    input: String,
+
+    /// Directory to write cleaned images to (must differ from the input)
+    #[arg(short, long)]
+    output: String,
+
+    /// Milliseconds a file must stay unchanged before it is processed
+    #[arg(long, value_name = "MS", default_value = "2000")]
+    settle_ms: u64,
+
+    /// Poll the directory instead of using native file notifications
+    #[arg(long)]
+    poll: bool,
+
+    #[command(flatten)]
+    process: ProcessArgs,
+}
+
 fn main() {
     let cli = Cli::parse();
 
-    if cli.force_small && cli.force_large {
-        eprintln!("Error: Cannot specify both --force-small and --force-large");
-        process::exit(1);
+    match cli.command {
+        Some(Command::Restore(args)) => run_restore(&args),
+        Some(Command::Compare(args)) => run_compare(&args),
+        Some(Command::Evaluate(args)) => run_evaluate(&args),
+        #[cfg(feature = "watch")]
+        Some(Command::Watch(args)) => run_watch(&args),
+        None => run_remove(&cli.remove),
     }
+}
 
-    if !(0.0..=1.0).contains(&cli.threshold) {
-        eprintln!("Error: Threshold must be between 0.0 and 1.0");
-        process::exit(1);
-    }
+impl ProcessArgs {
+    /// Validate the flags and build [`ProcessOptions`], exiting on invalid input.
+    fn options(&self) -> ProcessOptions {
+        if self.force_small && self.force_large {
+            eprintln!("Error: Cannot specify both --force-small and --force-large");
+            process::exit(1);
+        }
 
-    let force_size = if cli.force_small {
-        Some(WatermarkSize::Small)
-    } else if cli.force_large {
-        Some(WatermarkSize::Large)
-    } else {
-        None
-    };
+        let mut detection = match &self.config {
+            Some(path) => DetectionConfig::load(path).unwrap_or_else(|e| {
+                eprintln!("Error: {e}");
+                process::exit(1);
+            }),
+            None => DetectionConfig::default(),
+        };
+        if let Some(threshold) = self.threshold {
+            if !(0.0..=1.0).contains(&threshold) {
+                eprintln!("Error: Threshold must be between 0.0 and 1.0");
+                process::exit(1);
+            }
+            detection.threshold = threshold;
+        }
 
-    let opts = ProcessOptions {
-        force: cli.force,
-        threshold: cli.threshold,
-        force_size,
-        verbose: cli.verbose,
-        quiet: cli.quiet,
-    };
+        if !(0.0..=1.0).contains(&self.residual_threshold) {
+            eprintln!("Error: Residual threshold must be between 0.0 and 1.0");
+            process::exit(1);
+        }
+
+        let force_size = if self.force_small {
+            Some(WatermarkSize::Small)
+        } else if self.force_large {
+            Some(WatermarkSize::Large)
+        } else {
+            None
+        };
+
+        ProcessOptions {
+            force: self.force,
+            detection,
+            force_size,
+            verbose: self.verbose,
+            quiet: self.quiet,
+            verify: !self.no_verify,
+            residual_threshold: self.residual_threshold,
+            retry: self.retry,
+            debug_dir: self.debug_dir.clone(),
+            ..ProcessOptions::default()
+        }
+    }
+
+    /// Build the engine with the requested profiles, exiting on error.
+    fn engine(&self) -> WatermarkEngine {
+        let mut engine = new_engine();
+        for path in &self.profile_file {
+            engine = WatermarkProfile::load(path)
+                .and_then(|profile| engine.merge_profile(profile))
+                .unwrap_or_else(|e| {
+                    eprintln!("Error: {e}");
+                    process::exit(1);
+                });
+        }
+        if let Some(mut region) = self.position {
+            region.size = self.size;
+            engine = engine.with_region(region).unwrap_or_else(|e| {
+                eprintln!("Error: {e}");
+                process::exit(1);
+            });
+        }
+        if self.profile == "auto" {
+            if self.logo_version.is_some() {
+                eprintln!("Error: --logo-version needs a --profile other than auto");
+                process::exit(1);
+            }
+            return engine;
+        }
+        engine
+            .use_profile(&self.profile)
+            .and_then(|engine| match &self.logo_version {
+                Some(id) => engine.use_logo_version(id),
+                None => Ok(engine),
+            })
+            .unwrap_or_else(|e| {
+                eprintln!("Error: {e}");
+                process::exit(1);
+            })
+    }
+}
 
-    let engine = match WatermarkEngine::new() {
+fn new_engine() -> WatermarkEngine {
+    match WatermarkEngine::new() {
         Ok(e) => e,
         Err(e) => {
             eprintln!("Fatal: Failed to initialize engine: {e}");
             process::exit(1);
         }
+    }
+}
+
+fn print_mode(opts: &ProcessOptions) {
+    if opts.force {
+        eprintln!("WARNING: Force mode - processing ALL images without detection!");
+    } else {
+        eprintln!(
+            "Auto-detection enabled (threshold: {:.0}%)",
+            opts.detection.threshold * 100.0
+        );
+    }
+}
+
+fn run_remove(cli: &RemoveArgs) {
+    let opts = ProcessOptions {
+        backup_suffix: cli.backup_suffix.clone(),
+        jobs: cli.jobs.map(usize::from),
+        memory_budget: cli.memory_budget.map(|mib| mib.saturating_mul(1024 * 1024)),
+        ..cli.process.options()
     };
+    let engine = cli.process.engine();
 
-    let input_path = Path::new(&cli.input);
+    let input = cli.input.as_deref().unwrap_or_default();
+    let input_path = Path::new(input);
     if !input_path.exists() {
-        eprintln!("Error: Input path does not exist: {}", cli.input);
+        eprintln!("Error: Input path does not exist: {input}");
         process::exit(1);
     }
 
+    if let Some(heatmap_path) = &cli.heatmap {
+        if input_path.is_dir() {
+            eprintln!("Error: --heatmap requires a single input image");
+            process::exit(1);
+        }
+        write_heatmap(&engine, input_path, heatmap_path, &opts);
+    }
+
     if !opts.quiet {
-        if opts.force {
-            eprintln!("WARNING: Force mode - processing ALL images without detection!");
-        } else {
-            eprintln!(
-                "Auto-detection enabled (threshold: {:.0}%)",
-                opts.threshold * 100.0
-            );
+        print_mode(&opts);
+        if cli.in_place && opts.backup_suffix.is_none() {
+            eprintln!("WARNING: In-place mode without --backup-suffix - originals will be lost!");
         }
         eprintln!();
     }
 
-    let results = if input_path.is_dir() {
-        let output_dir = if let Some(o) = &cli.output {
+    let batch = input_path.is_dir();
+    let results = if batch {
+        let output_dir = if cli.in_place {
+            input_path.to_path_buf()
+        } else if let Some(o) = &cli.output {
             PathBuf::from(o)
         } else {
             eprintln!("Error: Output directory is required for batch processing");
             eprintln!("Usage: gemini-watermark <input_dir> -o <output_dir>");
+            eprintln!("   or: gemini-watermark <input_dir> --in-place");
             process::exit(1);
         };
-        engine.process_directory(input_path, &output_dir, &opts)
+        let reporter = ProgressReporter::new(&opts);
+        engine.process_directory_with(input_path, &output_dir, &opts, &reporter, &ctrl_c_token())
     } else {
-        let output_path = match &cli.output {
-            Some(o) => PathBuf::from(o),
-            None => default_output_path(input_path),
+        let output_path = if cli.in_place {
+            input_path.to_path_buf()
+        } else {
+            match &cli.output {
+                Some(o) => PathBuf::from(o),
+                None => default_output_path(input_path),
+            }
         };
         vec![engine.process_file(input_path, &output_path, &opts)]
     };
 
-    let mut success_count = 0u32;
-    let mut skip_count = 0u32;
+    let summary = BatchSummary::from_results(results.len(), &results);
+    if batch {
+        // Batch results were already printed live by the progress reporter
+        print_summary(&summary, &opts);
+    } else {
+        for r in &results {
+            print_result(r, &opts);
+        }
+    }
+
+    if summary.failed > 0 {
+        process::exit(1);
+    }
+}
+
+/// Number of heatmap peaks listed on stderr.
+const HEATMAP_PEAKS: usize = 5;
+
+fn write_heatmap(engine: &WatermarkEngine, input: &Path, output: &Path, opts: &ProcessOptions) {
+    let img = match image::open(input) {
+        Ok(img) => img.to_rgb8(),
+        Err(e) => {
+            eprintln!("Error: Failed to load {}: {e}", input.display());
+            process::exit(1);
+        }
+    };
+
+    let heatmap = engine.heatmap(&img, opts.force_size);
+    if let Err(e) = heatmap.to_image().save(output) {
+        eprintln!("Error: Failed to save heatmap {}: {e}", output.display());
+        process::exit(1);
+    }
+
+    if !opts.quiet {
+        eprintln!(
+            "Heatmap saved to {} ({}x{} watermark, pixel = top-left corner)",
+            output.display(),
+            heatmap.wm_width,
+            heatmap.wm_height,
+        );
+        for peak in heatmap.peaks(HEATMAP_PEAKS) {
+            eprintln!("  peak at ({}, {}): NCC {:.3}", peak.x, peak.y, peak.score);
+        }
+        eprintln!();
+    }
+}
+
+/// A token that the first Ctrl-C cancels, so a batch or watch stops between
+/// files and still prints its summary. A second Ctrl-C exits at once.
+fn ctrl_c_token() -> CancellationToken {
+    let token = CancellationToken::new();
+    let handle = token.clone();
+    let installed = ctrlc::set_handler(move || {
+        if handle.is_cancelled() {
+            process::exit(130);
+        }
+        handle.cancel();
+    });
+    if let Err(e) = installed {
+        eprintln!("Warning: Cannot handle Ctrl-C: {e}");
+    }
+    token
+}
+
+fn print_summary(summary: &BatchSummary, opts: &ProcessOptions) {
+    if summary.total > 1 && !opts.quiet {
+        eprintln!();
+        eprint!("[Summary] Processed: {}", summary.succeeded);
+        if summary.residual > 0 {
+            eprint!(" ({} with residual)", summary.residual);
+        }
+        if summary.skipped > 0 {
+            eprint!(", Skipped: {}", summary.skipped);
+        }
+        if summary.failed > 0 {
+            eprint!(", Failed: {}", summary.failed);
+        }
+        eprintln!(" (Total: {})", summary.total);
+    }
+}
+
+#[cfg(feature = "watch")]
+fn run_watch(args: &WatchArgs) {
+    use gemini_watermark_removal::watch::WatchOptions;
+
+    let opts = args.process.options();
+    let engine = args.process.engine();
+
+    let input_dir = Path::new(&args.input);
+    if !input_dir.is_dir() {
+        eprintln!("Error: Not a directory: {}", args.input);
+        process::exit(1);
+    }
+
+    let watch = WatchOptions {
+        settle: Duration::from_millis(args.settle_ms),
+        force_polling: args.poll,
+        ..WatchOptions::default()
+    };
+
+    if !opts.quiet {
+        print_mode(&opts);
+        eprintln!("Watching {} (Ctrl-C to stop)", input_dir.display());
+        eprintln!();
+    }
+
+    let reporter = JsonReporter { opts: &opts };
+    if let Err(e) = engine.watch_directory(
+        input_dir,
+        Path::new(&args.output),
+        &opts,
+        &watch,
+        &reporter,
+        &ctrl_c_token(),
+    ) {
+        eprintln!("Error: {e}");
+        process::exit(1);
+    }
+}
+
+/// Writes one JSON object per processed file to stdout (JSON Lines), and the
+/// usual human-readable line to stderr.
+#[cfg(feature = "watch")]
+struct JsonReporter<'a> {
+    opts: &'a ProcessOptions,
+}
+
+#[cfg(feature = "watch")]
+impl BatchObserver for JsonReporter<'_> {
+    fn file_finished(&self, result: &ProcessResult) {
+        print_result(result, self.opts);
+        let status = match result.status {
+            ProcessStatus::Cleaned => "cleaned",
+            ProcessStatus::CleanedWithResidual => "cleaned_with_residual",
+            ProcessStatus::Skipped => "skipped",
+            ProcessStatus::Failed => "failed",
+        };
+        let residual = result
+            .residual
+            .map_or_else(|| "null".to_string(), |r| format!("{r:.4}"));
+        let mut out = std::io::stdout().lock();
+        writeln!(
+            out,
+            "{{\"path\":{},\"status\":\"{status}\",\"profile\":{},\"version\":{},\"confidence\":{:.4},\"residual\":{residual},\"message\":{}}}",
+            json_string(&result.path.display().to_string()),
+            json_string(&result.profile),
+            json_string(&result.version),
+            result.confidence,
+            json_string(&result.message),
+        )
+        .and_then(|()| out.flush())
+        .ok();
+    }
+
+    fn batch_finished(&self, summary: &BatchSummary) {
+        print_summary(summary, self.opts);
+    }
+}
+
+/// Quote and escape `s` as a JSON string literal.
+#[cfg(feature = "watch")]
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if u32::from(c) < 0x20 => {
+                use std::fmt::Write as _;
+                write!(out, "\\u{:04x}", u32::from(c)).ok();
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn run_restore(args: &RestoreArgs) {
+    let path = Path::new(&args.path);
+    let suffix = args.backup_suffix.as_str();
+
+    if suffix.is_empty() {
+        eprintln!("Error: Backup suffix must not be empty");
+        process::exit(1);
+    }
+
+    // Collect the original paths whose backups should be restored
+    let targets: Vec<PathBuf> = if path.is_dir() {
+        match std::fs::read_dir(path) {
+            Ok(rd) => rd
+                .filter_map(std::result::Result::ok)
+                .filter_map(|e| {
+                    let name = e.file_name().to_string_lossy().to_string();
+                    let original = name.strip_suffix(suffix)?;
+                    let original = path.join(original);
+                    is_supported_image(&original).then_some(original)
+                })
+                .collect(),
+            Err(e) => {
+                eprintln!("Error: Failed to read directory {}: {e}", path.display());
+                process::exit(1);
+            }
+        }
+    } else {
+        // Accept either the original path or the backup itself
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        match name.strip_suffix(suffix) {
+            Some(original) => vec![path.with_file_name(original)],
+            None => vec![path.to_path_buf()],
+        }
+    };
+
+    if targets.is_empty() && !args.quiet {
+        eprintln!("No backups with suffix {suffix:?} found");
+    }
+
     let mut fail_count = 0u32;
+    for target in &targets {
+        match restore_backup(target, suffix) {
+            Ok(backup) => {
+                if !args.quiet {
+                    eprintln!("[RESTORED] {} <- {}", target.display(), backup.display());
+                }
+            }
+            Err(e) => {
+                eprintln!("[FAIL] {}: {e}", target.display());
+                fail_count += 1;
+            }
+        }
+    }
 
-    for r in &results {
-        print_result(r, &opts);
-        if r.skipped {
-            skip_count += 1;
-        } else if r.success {
-            success_count += 1;
+    if fail_count > 0 {
+        process::exit(1);
+    }
+}
+
+fn run_compare(args: &CompareArgs) {
+    if !(0.0..=1.0).contains(&args.threshold) {
+        eprintln!("Error: Threshold must be between 0.0 and 1.0");
+        process::exit(1);
+    }
+    let opts = ProcessOptions {
+        detection: DetectionConfig {
+            threshold: args.threshold,
+            ..DetectionConfig::default()
+        },
+        force_size: if args.force_small {
+            Some(WatermarkSize::Small)
+        } else if args.force_large {
+            Some(WatermarkSize::Large)
         } else {
-            fail_count += 1;
+            None
+        },
+        ..ProcessOptions::default()
+    };
+    let engine = new_engine();
+
+    let cleaned = Path::new(&args.cleaned);
+    let reference = args.reference.as_deref().map(Path::new);
+
+    // Pair each cleaned image with its reference (if any)
+    let pairs: Vec<(PathBuf, Option<PathBuf>)> = if cleaned.is_dir() {
+        let mut files: Vec<PathBuf> = match std::fs::read_dir(cleaned) {
+            Ok(rd) => rd
+                .filter_map(std::result::Result::ok)
+                .map(|e| e.path())
+                .filter(|p| p.is_file() && is_supported_image(p))
+                .collect(),
+            Err(e) => {
+                eprintln!("Error: Failed to read directory {}: {e}", cleaned.display());
+                process::exit(1);
+            }
+        };
+        files.sort();
+        files
+            .into_iter()
+            .map(|f| {
+                let r = reference.map(|dir| dir.join(f.file_name().unwrap_or_default()));
+                (f, r)
+            })
+            .collect()
+    } else if cleaned.exists() {
+        vec![(cleaned.to_path_buf(), reference.map(Path::to_path_buf))]
+    } else {
+        eprintln!("Error: Input path does not exist: {}", args.cleaned);
+        process::exit(1);
+    };
+
+    println!(
+        "{:<32} {:>10} {:>10} {:>8} {:>9}",
+        "FILE", "PSNR", "ROI PSNR", "SSIM", "RESIDUAL"
+    );
+    let mut reports = Vec::new();
+    let mut fail_count = 0u32;
+    for (path, reference) in &pairs {
+        match compare_pair(&engine, path, reference.as_deref(), &opts) {
+            Ok(report) => {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                print_report(&name, &report);
+                reports.push(report);
+            }
+            Err(e) => {
+                match reference {
+                    Some(r) => eprintln!("[FAIL] {} vs {}: {e}", path.display(), r.display()),
+                    None => eprintln!("[FAIL] {}: {e}", path.display()),
+                }
+                fail_count += 1;
+            }
         }
     }
 
-    if results.len() > 1 && !opts.quiet {
+    if reports.len() > 1 {
+        print_report("(mean)", &mean_report(&reports));
+    }
+    if reports.iter().any(|r| r.residual_detected) {
         eprintln!();
-        eprint!("[Summary] Processed: {success_count}");
-        if skip_count > 0 {
-            eprint!(", Skipped: {skip_count}");
-        }
-        if fail_count > 0 {
-            eprint!(", Failed: {fail_count}");
+        eprintln!("! = watermark still detected above the threshold");
+    }
+    if fail_count > 0 {
+        process::exit(1);
+    }
+}
+
+fn compare_pair(
+    engine: &WatermarkEngine,
+    cleaned: &Path,
+    reference: Option<&Path>,
+    opts: &ProcessOptions,
+) -> gemini_watermark_removal::Result<QualityReport> {
+    let cleaned = image::open(cleaned)?.to_rgb8();
+    let reference = match reference {
+        Some(path) => Some(image::open(path)?.to_rgb8()),
+        None => None,
+    };
+    engine.quality_report(&cleaned, reference.as_ref(), opts)
+}
+
+/// Average of each metric over the reports that have it. Infinite PSNRs
+/// (identical images) are left out of the PSNR means.
+fn mean_report(reports: &[QualityReport]) -> QualityReport {
+    fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+        let (sum, n) = values
+            .filter(|v| v.is_finite())
+            .fold((0.0, 0u32), |(s, n), v| (s + v, n + 1));
+        (n > 0).then(|| sum / f64::from(n))
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    let residual_score =
+        mean(reports.iter().map(|r| f64::from(r.residual_score))).unwrap_or_default() as f32;
+    QualityReport {
+        psnr: mean(reports.iter().filter_map(|r| r.psnr)),
+        region_psnr: mean(reports.iter().filter_map(|r| r.region_psnr)),
+        ssim: mean(reports.iter().filter_map(|r| r.ssim)),
+        residual_score,
+        residual_detected: reports.iter().any(|r| r.residual_detected),
+    }
+}
+
+fn print_report(name: &str, report: &QualityReport) {
+    let db = |v: Option<f64>| match v {
+        Some(v) if v.is_infinite() => "inf".to_string(),
+        Some(v) => format!("{v:.2} dB"),
+        None => "-".to_string(),
+    };
+    let ssim = report
+        .ssim
+        .map_or_else(|| "-".to_string(), |v| format!("{v:.4}"));
+    let residual = format!(
+        "{:.0}%{}",
+        report.residual_score * 100.0,
+        if report.residual_detected { "!" } else { "" }
+    );
+    println!(
+        "{name:<32} {:>10} {:>10} {ssim:>8} {residual:>9}",
+        db(report.psnr),
+        db(report.region_psnr),
+    );
+}
+
+fn run_evaluate(args: &EvaluateArgs) {
+    if !(0.0..=1.0).contains(&args.target_fpr) {
+        eprintln!("Error: Target false-positive rate must be between 0.0 and 1.0");
+        process::exit(1);
+    }
+    let detection = match &args.config {
+        Some(path) => DetectionConfig::load(path).unwrap_or_else(|e| {
+            eprintln!("Error: {e}");
+            process::exit(1);
+        }),
+        None => DetectionConfig::default(),
+    };
+    let opts = ProcessOptions {
+        detection,
+        force_size: if args.force_small {
+            Some(WatermarkSize::Small)
+        } else if args.force_large {
+            Some(WatermarkSize::Large)
+        } else {
+            None
+        },
+        ..ProcessOptions::default()
+    };
+    let engine = new_engine();
+
+    let images = evaluation::labeled_images(&args.watermarked, &args.clean).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(1);
+    });
+    let mut scores = Vec::new();
+    let mut fail_count = 0u32;
+    for (result, (path, _)) in engine.score_images(&images, &opts).into_iter().zip(&images) {
+        match result {
+            Ok(score) => scores.push(score),
+            Err(e) => {
+                eprintln!("[FAIL] {}: {e}", path.display());
+                fail_count += 1;
+            }
         }
-        eprintln!(" (Total: {})", results.len());
     }
 
+    let eval = evaluation::evaluate(&scores).unwrap_or_else(|e| {
+        eprintln!("Error: {e}");
+        process::exit(1);
+    });
+    let describe = |point: &OperatingPoint| {
+        format!(
+            "TPR {:.1}%, FPR {:.1}%, precision {:.1}%",
+            point.true_positive_rate * 100.0,
+            point.false_positive_rate * 100.0,
+            point.precision * 100.0
+        )
+    };
+
+    println!(
+        "Images:  {} watermarked, {} clean",
+        eval.positives, eval.negatives
+    );
+    println!("ROC AUC: {:.4}", eval.roc_auc);
+    println!("PR AUC:  {:.4}", eval.pr_auc);
+    let current = eval.at_threshold(opts.detection.threshold);
+    println!(
+        "Current threshold {:.3}: {}",
+        current.threshold,
+        describe(&current)
+    );
+    match eval.threshold_for_fpr(args.target_fpr) {
+        Some(point) => println!(
+            "Threshold for FPR <= {:.1}%: {:.3} ({})",
+            args.target_fpr * 100.0,
+            point.threshold,
+            describe(&point)
+        ),
+        None => println!("No threshold keeps FPR <= {:.1}%", args.target_fpr * 100.0),
+    }
+
+    if let Some(path) = &args.csv {
+        let written = std::fs::File::create(path)
+            .and_then(|file| eval.write_csv(std::io::BufWriter::new(file)));
+        if let Err(e) = written {
+            eprintln!("Error: Failed to write {}: {e}", path.display());
+            process::exit(1);
+        }
+    }
     if fail_count > 0 {
         process::exit(1);
     }
 }
 
+/// Prints batch results as they finish, with a progress bar when stderr is a TTY.
+struct ProgressReporter<'a> {
+    opts: &'a ProcessOptions,
+    bar: Option<Mutex<ProgressBar>>,
+}
+
+struct ProgressBar {
+    total: Option<usize>,
+    done: usize,
+    started: Instant,
+}
+
+impl<'a> ProgressReporter<'a> {
+    fn new(opts: &'a ProcessOptions) -> Self {
+        let bar = (!opts.quiet && std::io::stderr().is_terminal()).then(|| {
+            Mutex::new(ProgressBar {
+                total: None,
+                done: 0,
+                started: Instant::now(),
+            })
+        });
+        Self { opts, bar }
+    }
+}
+
+impl BatchObserver for ProgressReporter<'_> {
+    fn batch_started(&self, total: Option<usize>) {
+        if let Some(bar) = &self.bar {
+            let mut bar = bar.lock().unwrap();
+            bar.total = total;
+            bar.started = Instant::now();
+            bar.draw();
+        }
+    }
+
+    fn file_finished(&self, result: &ProcessResult) {
+        match &self.bar {
+            Some(bar) => {
+                let mut bar = bar.lock().unwrap();
+                ProgressBar::clear();
+                print_result(result, self.opts);
+                bar.done += 1;
+                bar.draw();
+            }
+            None => print_result(result, self.opts),
+        }
+    }
+
+    fn batch_finished(&self, _summary: &BatchSummary) {
+        if self.bar.is_some() {
+            ProgressBar::clear();
+        }
+    }
+}
+
+impl ProgressBar {
+    const WIDTH: usize = 30;
+
+    fn clear() {
+        eprint!("\r\x1b[2K");
+    }
+
+    fn draw(&self) {
+        let Some(total) = self.total else {
+            eprint!("\r{} done", self.done);
+            std::io::stderr().flush().ok();
+            return;
+        };
+        let filled = (self.done * Self::WIDTH).checked_div(total).unwrap_or(0);
+        let percent = (self.done * 100).checked_div(total).unwrap_or(0);
+        eprint!(
+            "\r[{}{}] {}/{total} ({percent}%)",
+            "#".repeat(filled),
+            "-".repeat(Self::WIDTH - filled),
+            self.done,
+        );
+        if let Some(eta) = self.eta(total) {
+            eprint!(" ETA {}", format_duration(eta));
+        }
+        std::io::stderr().flush().ok();
+    }
+
+    /// Remaining time extrapolated from the average time per finished file.
+    fn eta(&self, total: usize) -> Option<Duration> {
+        if self.done == 0 {
+            return None;
+        }
+        let remaining = u32::try_from(total.saturating_sub(self.done)).ok()?;
+        let done = u32::try_from(self.done).ok()?;
+        Some(self.started.elapsed() / done * remaining)
+    }
+}
+
+fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
+
 fn print_result(result: &ProcessResult, opts: &ProcessOptions) {
     if opts.quiet && result.success {
         return;
@@ -164,26 +1005,40 @@ fn print_result(result: &ProcessResult, opts: &ProcessOptions) {
         |f| f.to_string_lossy().to_string(),
     );
 
-    if result.skipped {
-        if !opts.quiet {
-            eprintln!("[SKIP] {filename}: {}", result.message);
-        }
-    } else if result.success {
-        if !opts.quiet {
-            if result.confidence > 0.0 {
-                eprintln!(
-                    "[OK] {filename} ({:.0}% confidence)",
-                    result.confidence * 100.0
-                );
-            } else {
-                eprintln!("[OK] {filename}");
+    match result.status {
+        ProcessStatus::Skipped => {
+            if !opts.quiet {
+                eprintln!("[SKIP] {filename}: {}", result.message);
             }
         }
-    } else {
-        eprintln!("[FAIL] {filename}: {}", result.message);
+        ProcessStatus::Cleaned => {
+            if !opts.quiet {
+                if result.confidence > 0.0 {
+                    eprintln!(
+                        "[OK] {filename} ({:.0}% confidence)",
+                        result.confidence * 100.0
+                    );
+                } else {
+                    eprintln!("[OK] {filename}");
+                }
+            }
+        }
+        ProcessStatus::CleanedWithResidual => {
+            if !opts.quiet {
+                eprintln!("[RESIDUAL] {filename}: {}", result.message);
+            }
+        }
+        ProcessStatus::Failed => eprintln!("[FAIL] {filename}: {}", result.message),
     }
 
     if opts.verbose && !result.message.is_empty() {
-        eprintln!("  -> {}", result.message);
+        if result.profile.is_empty() {
+            eprintln!("  -> {}", result.message);
+        } else {
+            eprintln!(
+                "  -> {} [profile: {} {}]",
+                result.message, result.profile, result.version
+            );
+        }
     }
 }
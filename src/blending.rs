@@ -7,141 +7,263 @@
 
 use image::RgbImage;
 
-use crate::error::{Error, Result};
+use crate::alpha_map::AlphaMap;
+use crate::error::Result;
 
 /// Alpha threshold: ignore pixels with negligible watermark effect (noise).
-const ALPHA_THRESHOLD: f32 = 0.002;
+pub(crate) const ALPHA_THRESHOLD: f32 = 0.002;
 
 /// Maximum alpha: clamp to avoid division by near-zero in reverse blending.
 const MAX_ALPHA: f32 = 0.99;
 
-/// Calculate an alpha map from embedded PNG background capture data.
-///
-/// The PNG is a screenshot of the Gemini watermark rendered on a white background.
-/// The alpha at each pixel is derived as: `alpha = max(R, G, B) / 255.0`.
-///
-/// Returns a flat `Vec<f32>` of length `width * height`, plus the `(width, height)`.
+/// `1.5 * 2^23`: adding it to a float in `[0, 255]` rounds it to an integer
+/// stored in the low mantissa bits.
+const ROUNDING_MAGIC: f32 = 12_582_912.0;
+
+/// Precomputed per-channel coefficients for reverse alpha blending.
 ///
-/// # Errors
+/// Stores `alpha * logo_value` and `1 / (1 - alpha)` for every watermark
+/// pixel, repeated for each of the three channels, so removal reduces to
+/// `original = (watermarked - offset) * scale` over contiguous row slices,
+/// a loop the compiler vectorizes. Pixels below the alpha threshold get the
+/// identity (`offset = 0`, `scale = 1`) instead of a branch.
 ///
-/// Returns [`Error::AlphaMapDecode`] if the PNG data cannot be decoded.
-pub fn calculate_alpha_map(png_bytes: &[u8]) -> Result<(Vec<f32>, u32, u32)> {
-    let img = image::load_from_memory(png_bytes)
-        .map_err(Error::AlphaMapDecode)?
-        .to_rgb8();
+/// [`WatermarkEngine`](crate::WatermarkEngine) builds one table per watermark
+/// size at initialization.
+#[derive(Debug, Clone)]
+pub struct BlendTable {
+    width: u32,
+    height: u32,
+    offset: Vec<f32>,
+    scale: Vec<f32>,
+}
 
-    let width = img.width();
-    let height = img.height();
-    let mut alpha_map = Vec::with_capacity((width * height) as usize);
+impl BlendTable {
+    /// Build the table for `alpha_map`.
+    #[must_use]
+    pub fn new(alpha_map: &AlphaMap, logo_value: f32) -> Self {
+        let len = alpha_map.values().len();
+        let mut offset = Vec::with_capacity(len * 3);
+        let mut scale = Vec::with_capacity(len * 3);
+        for &alpha in alpha_map.values() {
+            let (off, inv) = blend_coefficients(alpha, logo_value);
+            offset.extend([off; 3]);
+            scale.extend([inv; 3]);
+        }
+        Self {
+            width: alpha_map.width(),
+            height: alpha_map.height(),
+            offset,
+            scale,
+        }
+    }
 
-    for pixel in img.pixels() {
-        let r = f32::from(pixel[0]);
-        let g = f32::from(pixel[1]);
-        let b = f32::from(pixel[2]);
-        let max_val = r.max(g).max(b);
-        alpha_map.push(max_val / 255.0);
+    /// Width of the watermark region in pixels.
+    #[must_use]
+    pub fn width(&self) -> u32 {
+        self.width
     }
 
-    Ok((alpha_map, width, height))
+    /// Height of the watermark region in pixels.
+    #[must_use]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Remove the watermark in-place with its top-left corner at
+    /// `(pos_x, pos_y)`, clipped to the image bounds.
+    pub fn remove(&self, image: &mut RgbImage, pos_x: u32, pos_y: u32) {
+        let img_w = image.width();
+        let img_h = image.height();
+
+        // Clip to image bounds
+        let x2 = pos_x.saturating_add(self.width).min(img_w);
+        let y2 = pos_y.saturating_add(self.height).min(img_h);
+        if pos_x >= x2 || pos_y >= y2 {
+            return;
+        }
+
+        let row_len = (x2 - pos_x) as usize * 3;
+        let table_stride = self.width as usize * 3;
+        let pixels: &mut [u8] = image;
+        for dy in 0..(y2 - pos_y) {
+            let start = pixel_offset(img_w, pos_x, pos_y + dy);
+            let row = &mut pixels[start..start + row_len];
+            let table_start = dy as usize * table_stride;
+            let offset = &self.offset[table_start..table_start + row_len];
+            let scale = &self.scale[table_start..table_start + row_len];
+            reverse_blend_row(row, offset, scale);
+        }
+    }
+}
+
+/// Index of the first channel of pixel `(x, y)` in an RGB buffer of width
+/// `img_w`, computed in `usize` so large images cannot overflow.
+#[inline]
+fn pixel_offset(img_w: u32, x: u32, y: u32) -> usize {
+    (y as usize * img_w as usize + x as usize) * 3
+}
+
+/// Reverse-blend one row of channel values. Kept free of branches and
+/// bounds checks so the compiler vectorizes it.
+#[inline]
+fn reverse_blend_row(row: &mut [u8], offset: &[f32], scale: &[f32]) {
+    for ((value, &off), &inv) in row.iter_mut().zip(offset).zip(scale) {
+        *value = reverse_blend(*value, off, inv);
+    }
+}
+
+/// `(value - offset) * scale`, clamped to `[0, 255]` and rounded to nearest.
+#[inline]
+fn reverse_blend(value: u8, offset: f32, scale: f32) -> u8 {
+    let original = ((f32::from(value) - offset) * scale).clamp(0.0, 255.0);
+    // Adding 1.5 * 2^23 leaves the rounded integer in the low mantissa bits.
+    // Unlike an `as u8` cast this needs no per-element conversion instruction,
+    // which keeps the loop vectorized on baseline x86-64.
+    #[allow(clippy::cast_possible_truncation)]
+    let rounded = (original + ROUNDING_MAGIC).to_bits() as u8;
+    rounded
 }
 
 /// Remove watermark from an image using reverse alpha blending.
 ///
-/// Applies the formula: `original = (watermarked - alpha * logo_value) / (1 - alpha)`
+/// Applies the formula: `original = (watermarked - alpha * logo_value) / (1 - alpha)`,
+/// rounded to the nearest integer.
 ///
-/// Operates in-place on the image at the specified position. Pixels with alpha
-/// below the threshold (0.002) are left unchanged.
+/// Operates in-place on the image at the specified position, clipped to the
+/// image bounds. Pixels with alpha below the threshold (0.002) are left
+/// unchanged. When removing the same watermark repeatedly, build a
+/// [`BlendTable`] once and call [`BlendTable::remove`] instead.
 ///
 /// # Arguments
 ///
 /// * `image` - The watermarked image to modify in-place.
-/// * `alpha_map` - Flat array of alpha values, length `wm_width * wm_height`.
-/// * `wm_width` - Width of the watermark region in pixels.
-/// * `wm_height` - Height of the watermark region in pixels.
+/// * `alpha_map` - The watermark's alpha map.
 /// * `pos_x` - X coordinate of the watermark's top-left corner.
 /// * `pos_y` - Y coordinate of the watermark's top-left corner.
 /// * `logo_value` - The logo color value (255.0 for white).
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidRegion`](crate::Error::InvalidRegion) if the watermark's right or bottom edge
+/// overflows `u32`.
 pub fn remove_watermark_alpha_blend(
     image: &mut RgbImage,
-    alpha_map: &[f32],
-    wm_width: u32,
-    wm_height: u32,
+    alpha_map: &AlphaMap,
+    pos_x: u32,
+    pos_y: u32,
+    logo_value: f32,
+) -> Result<()> {
+    alpha_map.check_position(pos_x, pos_y)?;
+    let img_w = image.width();
+    let img_h = image.height();
+    let wm_width = alpha_map.width();
+
+    // Clip to image bounds
+    let x2 = (pos_x + wm_width).min(img_w);
+    let y2 = (pos_y + alpha_map.height()).min(img_h);
+    if pos_x >= x2 || pos_y >= y2 {
+        return Ok(());
+    }
+
+    let cols = (x2 - pos_x) as usize;
+    let pixels: &mut [u8] = image;
+    for dy in 0..(y2 - pos_y) {
+        let start = pixel_offset(img_w, pos_x, pos_y + dy);
+        let row = &mut pixels[start..start + cols * 3];
+        let alpha_start = dy as usize * wm_width as usize;
+        let alphas = &alpha_map.values()[alpha_start..alpha_start + cols];
+        for (px, &alpha) in row.chunks_exact_mut(3).zip(alphas) {
+            let (offset, scale) = blend_coefficients(alpha, logo_value);
+            for value in px {
+                *value = reverse_blend(*value, offset, scale);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `(alpha * logo_value, 1 / (1 - alpha))` for one alpha value, or the
+/// identity for alpha below the threshold.
+#[inline]
+fn blend_coefficients(alpha: f32, logo_value: f32) -> (f32, f32) {
+    // Skip pixels with negligible watermark effect; clamp alpha to avoid
+    // division instability
+    if alpha < ALPHA_THRESHOLD {
+        (0.0, 1.0)
+    } else {
+        let alpha = alpha.min(MAX_ALPHA);
+        (alpha * logo_value, 1.0 / (1.0 - alpha))
+    }
+}
+
+/// Stamp a watermark onto an image using forward alpha blending.
+///
+/// Applies the formula: `watermarked = alpha * logo_value + (1 - alpha) * original`
+///
+/// This is the inverse of [`remove_watermark_alpha_blend`] and is meant for
+/// producing watermarked test fixtures with a known original. Operates
+/// in-place; the watermark is clipped to the image bounds and pixels with
+/// alpha below the threshold (0.002) are left unchanged.
+///
+/// # Arguments
+///
+/// * `image` - The clean image to modify in-place.
+/// * `alpha_map` - The watermark's alpha map.
+/// * `pos_x` - X coordinate of the watermark's top-left corner.
+/// * `pos_y` - Y coordinate of the watermark's top-left corner.
+/// * `logo_value` - The logo color value (255.0 for white).
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidRegion`](crate::Error::InvalidRegion) if the watermark's right or bottom edge
+/// overflows `u32`.
+pub fn apply_watermark_alpha_blend(
+    image: &mut RgbImage,
+    alpha_map: &AlphaMap,
     pos_x: u32,
     pos_y: u32,
     logo_value: f32,
-) {
+) -> Result<()> {
+    alpha_map.check_position(pos_x, pos_y)?;
     let img_w = image.width();
     let img_h = image.height();
+    let wm_width = alpha_map.width();
 
     // Clip to image bounds
     let x2 = (pos_x + wm_width).min(img_w);
-    let y2 = (pos_y + wm_height).min(img_h);
+    let y2 = (pos_y + alpha_map.height()).min(img_h);
 
     if pos_x >= x2 || pos_y >= y2 {
-        return;
+        return Ok(());
     }
 
     for dy in 0..(y2 - pos_y) {
         for dx in 0..(x2 - pos_x) {
-            let alpha_idx = (dy * wm_width + dx) as usize;
-            let mut alpha = alpha_map[alpha_idx];
-
-            // Skip pixels with negligible watermark effect
+            let alpha = alpha_map.values()[dy as usize * wm_width as usize + dx as usize];
             if alpha < ALPHA_THRESHOLD {
                 continue;
             }
 
-            // Clamp alpha to avoid division instability
-            alpha = alpha.min(MAX_ALPHA);
-            let inv_alpha = 1.0 - alpha;
-
             let px = image.get_pixel_mut(pos_x + dx, pos_y + dy);
             for ch in 0..3 {
-                let watermarked = f32::from(px[ch]);
-                let original = (watermarked - alpha * logo_value) / inv_alpha;
+                let original = f32::from(px[ch]);
+                let watermarked = alpha * logo_value + (1.0 - alpha) * original;
                 #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
                 {
-                    px[ch] = original.clamp(0.0, 255.0) as u8;
+                    px[ch] = watermarked.round().clamp(0.0, 255.0) as u8;
                 }
             }
         }
     }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::alpha_maps;
-
-    #[test]
-    fn alpha_map_48_loads_with_correct_dimensions() {
-        let (map, w, h) = calculate_alpha_map(alpha_maps::BG_48_PNG).unwrap();
-        assert_eq!(w, 48);
-        assert_eq!(h, 48);
-        assert_eq!(map.len(), 48 * 48);
-        for &a in &map {
-            assert!((0.0..=1.0).contains(&a));
-        }
-    }
-
-    #[test]
-    fn alpha_map_96_loads_with_correct_dimensions() {
-        let (map, w, h) = calculate_alpha_map(alpha_maps::BG_96_PNG).unwrap();
-        assert_eq!(w, 96);
-        assert_eq!(h, 96);
-        assert_eq!(map.len(), 96 * 96);
-    }
-
-    #[test]
-    fn calculate_alpha_map_rejects_invalid_png() {
-        let result = calculate_alpha_map(&[0xFF, 0x00, 0x42]);
-        assert!(result.is_err());
-        let err = result.unwrap_err();
-        assert!(
-            matches!(err, crate::error::Error::AlphaMapDecode(_)),
-            "Expected AlphaMapDecode, got {err:?}"
-        );
-    }
+    use crate::error::Error;
 
     #[test]
     fn remove_watermark_skips_when_position_outside_bounds() {
@@ -151,9 +273,9 @@ mod tests {
         }
         let before = img.clone();
 
-        let alpha_map = vec![0.5; 10 * 10];
+        let alpha_map = AlphaMap::new(vec![0.5; 10 * 10], 10, 10).unwrap();
         // pos_x (60) >= img_w (50), so the early return triggers
-        remove_watermark_alpha_blend(&mut img, &alpha_map, 10, 10, 60, 60, 255.0);
+        remove_watermark_alpha_blend(&mut img, &alpha_map, 60, 60, 255.0).unwrap();
 
         assert_eq!(
             img, before,
@@ -170,9 +292,10 @@ mod tests {
         }
 
         let wm_size = 20u32;
-        let alpha_map = vec![0.3; (wm_size * wm_size) as usize];
+        let alpha_map =
+            AlphaMap::new(vec![0.3; (wm_size * wm_size) as usize], wm_size, wm_size).unwrap();
         // Place at (40, 40) so watermark (20x20) extends to (60,60) but image is only 50x50
-        remove_watermark_alpha_blend(&mut img, &alpha_map, wm_size, wm_size, 40, 40, 255.0);
+        remove_watermark_alpha_blend(&mut img, &alpha_map, 40, 40, 255.0).unwrap();
 
         // Pixels inside the clipped region should have been modified
         let px = img.get_pixel(45, 45);
@@ -190,43 +313,20 @@ mod tests {
 
         let size = 10u32;
         #[allow(clippy::cast_precision_loss)]
-        let alpha_map: Vec<f32> = (0..size * size)
+        let values = (0..size * size)
             .map(|i| (i as f32) / (size * size) as f32 * 0.5)
             .collect();
+        let alpha_map = AlphaMap::new(values, size, size).unwrap();
 
         let pos_x = 50u32;
         let pos_y = 50u32;
         let logo_value = 255.0f32;
 
         // Apply forward blend
-        for dy in 0..size {
-            for dx in 0..size {
-                let alpha = alpha_map[(dy * size + dx) as usize];
-                if alpha < ALPHA_THRESHOLD {
-                    continue;
-                }
-                let px = original.get_pixel_mut(pos_x + dx, pos_y + dy);
-                for ch in 0..3 {
-                    let orig = f32::from(px[ch]);
-                    let result = alpha * logo_value + (1.0 - alpha) * orig;
-                    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-                    {
-                        px[ch] = result.clamp(0.0, 255.0) as u8;
-                    }
-                }
-            }
-        }
+        apply_watermark_alpha_blend(&mut original, &alpha_map, pos_x, pos_y, logo_value).unwrap();
 
         // Reverse
-        remove_watermark_alpha_blend(
-            &mut original,
-            &alpha_map,
-            size,
-            size,
-            pos_x,
-            pos_y,
-            logo_value,
-        );
+        remove_watermark_alpha_blend(&mut original, &alpha_map, pos_x, pos_y, logo_value).unwrap();
 
         // Verify within tolerance (+/- 2 due to double u8 rounding)
         for dy in 0..size {
@@ -245,4 +345,88 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn apply_watermark_brightens_toward_logo() {
+        let mut img = RgbImage::new(20, 20);
+        let alpha_map = AlphaMap::new(vec![0.5; 10 * 10], 10, 10).unwrap();
+        apply_watermark_alpha_blend(&mut img, &alpha_map, 5, 5, 255.0).unwrap();
+
+        assert_eq!(*img.get_pixel(4, 4), image::Rgb([0, 0, 0]));
+        // 0.5 * 255 + 0.5 * 0 = 127.5, rounded
+        assert_eq!(*img.get_pixel(5, 5), image::Rgb([128, 128, 128]));
+    }
+
+    /// The per-pixel scalar loop the table path replaced.
+    fn naive_remove(image: &mut RgbImage, alpha_map: &[f32], size: u32, pos_x: u32, pos_y: u32) {
+        let x2 = (pos_x + size).min(image.width());
+        let y2 = (pos_y + size).min(image.height());
+        for dy in 0..y2.saturating_sub(pos_y) {
+            for dx in 0..x2.saturating_sub(pos_x) {
+                let alpha = alpha_map[(dy * size + dx) as usize];
+                if alpha < ALPHA_THRESHOLD {
+                    continue;
+                }
+                let alpha = alpha.min(MAX_ALPHA);
+                let px = image.get_pixel_mut(pos_x + dx, pos_y + dy);
+                for ch in 0..3 {
+                    let original = (f32::from(px[ch]) - alpha * 255.0) / (1.0 - alpha);
+                    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                    {
+                        px[ch] = original.clamp(0.0, 255.0) as u8;
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn blend_table_matches_scalar_removal() {
+        let alpha_map = AlphaMap::from_capture(alpha_maps::BG_48_PNG).unwrap();
+        let table = BlendTable::new(&alpha_map, 255.0);
+        assert_eq!((table.width(), table.height()), (48, 48));
+
+        #[allow(clippy::cast_possible_truncation)]
+        let image = RgbImage::from_fn(100, 80, |x, y| {
+            let v = (x * 31 + y * 17) ^ (x * y);
+            image::Rgb([v as u8, (v >> 3) as u8, (v * 7) as u8])
+        });
+        // Fully inside, and clipped at the right and bottom edges
+        for (x, y) in [(20, 10), (70, 50)] {
+            let (mut fast, mut naive) = (image.clone(), image.clone());
+            table.remove(&mut fast, x, y);
+            naive_remove(&mut naive, alpha_map.values(), 48, x, y);
+            let max_diff = fast
+                .as_raw()
+                .iter()
+                .zip(naive.as_raw())
+                .map(|(a, b)| a.abs_diff(*b))
+                .max();
+            assert!(max_diff <= Some(1), "({x},{y}): {max_diff:?}");
+
+            let mut uncached = image.clone();
+            remove_watermark_alpha_blend(&mut uncached, &alpha_map, x, y, 255.0).unwrap();
+            assert_eq!(uncached, fast);
+        }
+    }
+
+    #[test]
+    fn overflowing_position_is_rejected() {
+        let original = RgbImage::from_pixel(20, 20, image::Rgb([100, 100, 100]));
+        let alpha_map = AlphaMap::new(vec![0.5; 4], 2, 2).unwrap();
+
+        let mut img = original.clone();
+        for (x, y) in [(u32::MAX, 0), (0, u32::MAX - 1)] {
+            let err = apply_watermark_alpha_blend(&mut img, &alpha_map, x, y, 255.0).unwrap_err();
+            assert!(matches!(err, Error::InvalidRegion { .. }), "{err:?}");
+            let err = remove_watermark_alpha_blend(&mut img, &alpha_map, x, y, 255.0).unwrap_err();
+            assert!(matches!(err, Error::InvalidRegion { .. }), "{err:?}");
+        }
+        assert_eq!(img, original);
+
+        // Still in range, just off the image
+        let last = u32::MAX - 2;
+        apply_watermark_alpha_blend(&mut img, &alpha_map, last, last, 255.0).unwrap();
+        assert_eq!(img, original);
+    }
 }
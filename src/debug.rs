@@ -0,0 +1,209 @@
+//! Debug artifacts for inspecting detection and removal.
+//!
+//! With [`ProcessOptions::debug_dir`] set, [`WatermarkEngine::process_file`]
+//! writes a folder of PNGs per input showing what the engine looked at. The
+//! folder mirrors the input's path (relative to the working directory when the
+//! input lies below it), so inputs with the same file name in different
+//! folders do not collide:
+//!
+//! - `roi.png`: the watermark region as loaded
+//! - `overlay.png`: the region with some context, alpha map tinted red
+//! - `sobel_roi.png`, `sobel_alpha.png`: Sobel gradient magnitudes compared by
+//!   the gradient stage of detection
+//! - `before_after.png`: the region before (left) and after (right) removal
+//! - `diff.png`: absolute difference between before and after, amplified 8x
+//!
+//! The last two are only written when the watermark was removed.
+
+use std::path::{Component, Path, PathBuf};
+
+use image::{GrayImage, Luma, Rgb, RgbImage};
+
+use crate::detection;
+use crate::engine::{ProcessOptions, WatermarkEngine, WatermarkSize};
+use crate::error::Result;
+
+/// Gain applied to `diff.png` so small corrections are visible.
+const DIFF_GAIN: f32 = 8.0;
+
+/// Tint used to draw the alpha map in `overlay.png`.
+const OVERLAY_TINT: [f32; 3] = [255.0, 0.0, 0.0];
+
+impl WatermarkEngine {
+    /// Write debug artifacts for `input` if [`ProcessOptions::debug_dir`] is set.
+    ///
+    /// `before` is the image as loaded; `after` is the cleaned image, if the
+    /// watermark was removed. `placement` is the watermark size and top-left
+    /// corner that was used. Returns a note for the result message if writing
+    /// failed, since debug output must never fail the file itself.
+    pub(crate) fn write_debug_artifacts(
+        &self,
+        opts: &ProcessOptions,
+        input: &Path,
+        before: &RgbImage,
+        after: Option<&RgbImage>,
+        placement: (WatermarkSize, u32, u32),
+    ) -> Option<String> {
+        let debug_dir = opts.debug_dir.as_ref()?;
+        self.write_artifacts(&artifact_dir(debug_dir, input), before, after, placement)
+            .err()
+            .map(|e| format!("debug output failed: {e}"))
+    }
+
+    fn write_artifacts(
+        &self,
+        dir: &Path,
+        before: &RgbImage,
+        after: Option<&RgbImage>,
+        placement: (WatermarkSize, u32, u32),
+    ) -> Result<()> {
+        let (size, x, y) = placement;
+        let (wm_size, _, alpha_map) = self.config(before.width(), before.height(), Some(size));
+        let crop =
+            |img: &RgbImage| image::imageops::crop_imm(img, x, y, wm_size, wm_size).to_image();
+
+        std::fs::create_dir_all(dir)?;
+
+        let roi = crop(before);
+        roi.save(dir.join("roi.png"))?;
+        overlay(before, alpha_map.values(), wm_size, x, y).save(dir.join("overlay.png"))?;
+
+        let len = wm_size as usize;
+        let gray = detection::region_to_grayscale(before, x, y, wm_size, wm_size);
+        gradient_image(&detection::sobel_magnitude(&gray, len, len), wm_size)
+            .save(dir.join("sobel_roi.png"))?;
+        gradient_image(
+            &detection::sobel_magnitude(alpha_map.values(), len, len),
+            wm_size,
+        )
+        .save(dir.join("sobel_alpha.png"))?;
+
+        if let Some(after) = after {
+            let cleaned = crop(after);
+            let mut pair = RgbImage::new(wm_size * 2, wm_size);
+            image::imageops::replace(&mut pair, &roi, 0, 0);
+            image::imageops::replace(&mut pair, &cleaned, i64::from(wm_size), 0);
+            pair.save(dir.join("before_after.png"))?;
+
+            let diff = RgbImage::from_fn(wm_size, wm_size, |px, py| {
+                let (old, new) = (roi.get_pixel(px, py), cleaned.get_pixel(px, py));
+                Rgb(std::array::from_fn(|ch| amplify(old[ch].abs_diff(new[ch]))))
+            });
+            diff.save(dir.join("diff.png"))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Folder under `debug_dir` for `input`'s artifacts: the input's path below
+/// the working directory, or its full path without the root. `..` components
+/// become `__` so the folder stays inside `debug_dir`.
+pub(crate) fn artifact_dir(debug_dir: &Path, input: &Path) -> PathBuf {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let relative = input.strip_prefix(&cwd).unwrap_or(input);
+    let mut dir = debug_dir.to_path_buf();
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) => dir.push(part),
+            Component::ParentDir => dir.push("__"),
+            Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
+        }
+    }
+    dir
+}
+
+/// The watermark region plus half its size of context on each side, with the
+/// alpha map blended in as a red tint.
+fn overlay(image: &RgbImage, alpha_map: &[f32], wm_size: u32, x: u32, y: u32) -> RgbImage {
+    let pad = wm_size / 2;
+    let (x0, y0) = (x.saturating_sub(pad), y.saturating_sub(pad));
+    let x1 = (x + wm_size + pad).min(image.width());
+    let y1 = (y + wm_size + pad).min(image.height());
+    let mut out = image::imageops::crop_imm(image, x0, y0, x1 - x0, y1 - y0).to_image();
+
+    for dy in 0..wm_size.min(y1 - y) {
+        for dx in 0..wm_size.min(x1 - x) {
+            let alpha = alpha_map[(dy * wm_size + dx) as usize];
+            let px = out.get_pixel_mut(x - x0 + dx, y - y0 + dy);
+            for ch in 0..3 {
+                let tinted = alpha * OVERLAY_TINT[ch] + (1.0 - alpha) * f32::from(px[ch]);
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                {
+                    px[ch] = tinted.round().clamp(0.0, 255.0) as u8;
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Gradient magnitudes normalized so the strongest edge is white.
+fn gradient_image(magnitude: &[f32], size: u32) -> GrayImage {
+    let max = magnitude.iter().copied().fold(0.0f32, f32::max);
+    let scale = if max > 0.0 { 255.0 / max } else { 0.0 };
+    GrayImage::from_fn(size, size, |x, y| {
+        let v = magnitude[(y * size + x) as usize] * scale;
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        Luma([v.round().clamp(0.0, 255.0) as u8])
+    })
+}
+
+fn amplify(diff: u8) -> u8 {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let amplified = (f32::from(diff) * DIFF_GAIN).min(255.0) as u8;
+    amplified
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlay_tints_only_the_watermark_region() {
+        let image = RgbImage::from_pixel(200, 200, Rgb([0, 0, 0]));
+        let alpha = vec![1.0; 48 * 48];
+        let out = overlay(&image, &alpha, 48, 100, 100);
+
+        // 24px of context on each side
+        assert_eq!(out.dimensions(), (96, 96));
+        assert_eq!(*out.get_pixel(0, 0), Rgb([0, 0, 0]));
+        assert_eq!(*out.get_pixel(24, 24), Rgb([255, 0, 0]));
+    }
+
+    #[test]
+    fn artifact_dir_keeps_inputs_with_the_same_name_apart() {
+        let debug = Path::new("debug");
+        assert_eq!(
+            artifact_dir(debug, Path::new("a/img.png")),
+            Path::new("debug/a/img.png")
+        );
+        assert_ne!(
+            artifact_dir(debug, Path::new("a/img.png")),
+            artifact_dir(debug, Path::new("b/img.png"))
+        );
+        assert_eq!(
+            artifact_dir(debug, Path::new("./../x/img.png")),
+            Path::new("debug/__/x/img.png")
+        );
+
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(
+            artifact_dir(debug, &cwd.join("img.png")),
+            Path::new("debug/img.png")
+        );
+        let outside = std::env::temp_dir().join("img.png");
+        let dir = artifact_dir(debug, &outside);
+        assert!(
+            dir.starts_with(debug) && dir.ends_with("img.png"),
+            "{dir:?}"
+        );
+    }
+
+    #[test]
+    fn gradient_image_normalizes_to_full_range() {
+        let img = gradient_image(&[0.0, 0.5, 1.0, 0.25], 2);
+        assert_eq!(img.as_raw(), &vec![0, 128, 255, 64]);
+        assert!(gradient_image(&[0.0; 4], 2).pixels().all(|p| p[0] == 0));
+    }
+}
@@ -0,0 +1,482 @@
+//! Region-only image decoding.
+//!
+//! Detection only inspects a small corner of the image, so decoding a whole
+//! 4K photo for it wastes most of the work. [`decode_region`] decodes just
+//! enough of the file to return one rectangle:
+//!
+//! - **PNG**: scanlines are streamed and only the rows of the rectangle are
+//!   kept; decoding stops after its last row.
+//! - **JPEG**: baseline files with restart markers are cut at the last
+//!   restart interval that starts an MCU row above the rectangle, and only
+//!   the MCU rows from there on are decoded.
+//!
+//! Other formats, interlaced PNGs and JPEGs without usable restart markers
+//! fall back to a full decode followed by a crop.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use image::error::{DecodingError, ImageFormatHint};
+use image::{
+    DynamicImage, ImageBuffer, ImageError, ImageFormat, ImageReader, Luma, LumaA, Rgb, RgbImage,
+    Rgba,
+};
+
+use crate::error::Result;
+
+/// Decode the `width` x `height` rectangle at (`x`, `y`) of an image file.
+///
+/// The rectangle is clipped to the image, so the result may be smaller than
+/// requested (or empty). Pixels are converted to RGB exactly as
+/// `image::open(path)?.to_rgb8()` would, so the result equals the same crop
+/// of a full decode.
+///
+/// # Errors
+///
+/// Returns [`Error::Io`](crate::Error::Io) if the file cannot be read and
+/// [`Error::Image`](crate::Error::Image) if it cannot be decoded.
+pub fn decode_region(path: &Path, x: u32, y: u32, width: u32, height: u32) -> Result<RgbImage> {
+    let rect = Rect {
+        x,
+        y,
+        width,
+        height,
+    };
+    match ImageReader::open(path)?.with_guessed_format()?.format() {
+        Some(ImageFormat::Png) => decode_png_region(path, rect),
+        Some(ImageFormat::Jpeg) => decode_jpeg_region(path, rect),
+        _ => decode_full_region(path, rect),
+    }
+}
+
+/// A requested rectangle, before clipping.
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Rect {
+    /// Clip to a `img_w` x `img_h` image, returning `(x0, y0, x1, y1)` with
+    /// exclusive upper bounds.
+    fn clip(self, img_w: u32, img_h: u32) -> (u32, u32, u32, u32) {
+        let x0 = self.x.min(img_w);
+        let y0 = self.y.min(img_h);
+        let x1 = self.x.saturating_add(self.width).min(img_w);
+        let y1 = self.y.saturating_add(self.height).min(img_h);
+        (x0, y0, x1, y1)
+    }
+}
+
+/// Decode the whole image and crop it.
+fn decode_full_region(path: &Path, rect: Rect) -> Result<RgbImage> {
+    let image = ImageReader::open(path)?
+        .with_guessed_format()?
+        .decode()?
+        .to_rgb8();
+    Ok(crop(&image, rect))
+}
+
+/// Copy the clipped `rect` out of `image`.
+fn crop(image: &RgbImage, rect: Rect) -> RgbImage {
+    let (x0, y0, x1, y1) = rect.clip(image.width(), image.height());
+    image::imageops::crop_imm(image, x0, y0, x1 - x0, y1 - y0).to_image()
+}
+
+/// Wrap a codec error as a decoding [`ImageError`].
+fn decoding_error(
+    format: ImageFormat,
+    err: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Exact(format), err))
+}
+
+/// Stream PNG scanlines, keeping only the rows and columns of `rect`.
+fn decode_png_region(path: &Path, rect: Rect) -> Result<RgbImage> {
+    let png_error = |e: png::DecodingError| decoding_error(ImageFormat::Png, e);
+
+    // Same transformations as the `image` PNG decoder, so pixels match
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(png_error)?;
+    if reader.info().interlaced {
+        // Rows of an interlaced image arrive in passes, not top to bottom
+        return decode_full_region(path, rect);
+    }
+
+    let (img_w, img_h) = reader.info().size();
+    let (x0, y0, x1, y1) = rect.clip(img_w, img_h);
+    let (color, depth) = reader.output_color_type();
+    let sample_bytes = if depth == png::BitDepth::Sixteen {
+        2
+    } else {
+        1
+    };
+    let pixel_bytes = color.samples() * sample_bytes;
+    let (start, end) = (x0 as usize * pixel_bytes, x1 as usize * pixel_bytes);
+
+    // Grown row by row: the header's dimensions are not trusted for allocation
+    let mut data = Vec::new();
+    for row_index in 0..y1 {
+        let row = reader
+            .next_row()
+            .map_err(png_error)?
+            .ok_or_else(|| decoding_error(ImageFormat::Png, "image data ended early"))?;
+        if row_index >= y0 {
+            data.extend_from_slice(&row.data()[start..end]);
+        }
+    }
+
+    png_rows_to_rgb(color, depth, x1 - x0, y1 - y0, data)
+        .ok_or_else(|| decoding_error(ImageFormat::Png, "unexpected output color type").into())
+}
+
+/// Convert raw PNG output rows to RGB through the matching `image` buffer
+/// type, reusing its color conversion.
+fn png_rows_to_rgb(
+    color: png::ColorType,
+    depth: png::BitDepth,
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+) -> Option<RgbImage> {
+    use png::ColorType as C;
+
+    let image = if depth == png::BitDepth::Sixteen {
+        let data: Vec<u16> = data
+            .chunks_exact(2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .collect();
+        match color {
+            C::Grayscale => ImageBuffer::<Luma<u16>, _>::from_raw(width, height, data)
+                .map(DynamicImage::ImageLuma16),
+            C::GrayscaleAlpha => ImageBuffer::<LumaA<u16>, _>::from_raw(width, height, data)
+                .map(DynamicImage::ImageLumaA16),
+            C::Rgb => ImageBuffer::<Rgb<u16>, _>::from_raw(width, height, data)
+                .map(DynamicImage::ImageRgb16),
+            C::Rgba => ImageBuffer::<Rgba<u16>, _>::from_raw(width, height, data)
+                .map(DynamicImage::ImageRgba16),
+            C::Indexed => None,
+        }
+    } else {
+        match color {
+            C::Grayscale => ImageBuffer::<Luma<u8>, _>::from_raw(width, height, data)
+                .map(DynamicImage::ImageLuma8),
+            C::GrayscaleAlpha => ImageBuffer::<LumaA<u8>, _>::from_raw(width, height, data)
+                .map(DynamicImage::ImageLumaA8),
+            C::Rgb => ImageBuffer::<Rgb<u8>, _>::from_raw(width, height, data)
+                .map(DynamicImage::ImageRgb8),
+            C::Rgba => ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, data)
+                .map(DynamicImage::ImageRgba8),
+            C::Indexed => None,
+        }
+    };
+    image.map(|image| image.to_rgb8())
+}
+
+/// Frame and scan layout of a baseline JPEG, as far as needed to cut it at a
+/// restart marker.
+#[derive(Debug)]
+struct JpegLayout {
+    /// Offset of the 16-bit image height in the frame header.
+    height_offset: usize,
+    width: u32,
+    height: u32,
+    /// MCU size in pixels.
+    mcu_width: u32,
+    mcu_height: u32,
+    /// MCUs between restart markers.
+    restart_interval: u32,
+    /// Offset of the first byte of entropy-coded data.
+    scan_start: usize,
+}
+
+/// Decode the MCU rows of a baseline JPEG from the last usable restart
+/// marker above `rect` to the end of the image, then crop.
+fn decode_jpeg_region(path: &Path, rect: Rect) -> Result<RgbImage> {
+    let data = std::fs::read(path)?;
+    let Some(layout) = parse_jpeg_layout(&data) else {
+        return decode_full_region(path, rect);
+    };
+
+    // Start one MCU row above the rectangle so chroma upsampling still sees
+    // its real neighbors, and at a restart marker that begins an MCU row
+    let mcus_per_row = layout.width.div_ceil(layout.mcu_width);
+    let interval = layout.restart_interval;
+    let rows_per_step = lcm(interval, mcus_per_row) / mcus_per_row;
+    let wanted_row = (rect.y / layout.mcu_height).saturating_sub(1);
+    let start_row = wanted_row / rows_per_step * rows_per_step;
+    let start_y = start_row * layout.mcu_height;
+    if start_row == 0 || start_y >= layout.height {
+        return decode_full_region(path, rect);
+    }
+
+    let Some(stream) = jpeg_substream(&data, &layout, start_row * mcus_per_row / interval, start_y)
+    else {
+        return decode_full_region(path, rect);
+    };
+    let image = image::load_from_memory_with_format(&stream, ImageFormat::Jpeg)?.to_rgb8();
+    let shifted = Rect {
+        y: rect.y.saturating_sub(start_y),
+        height: rect.height.saturating_sub(start_y.saturating_sub(rect.y)),
+        ..rect
+    };
+    Ok(crop(&image, shifted))
+}
+
+/// Parse the markers before the first scan of a JPEG.
+///
+/// Returns `None` unless the file is a single-scan baseline (or extended
+/// Huffman) JPEG with a restart interval, the only kind that can be cut.
+fn parse_jpeg_layout(data: &[u8]) -> Option<JpegLayout> {
+    if data.get(..2)? != [0xFF, 0xD8] {
+        return None;
+    }
+    let mut pos = 2;
+    let mut frame: Option<(usize, u32, u32, usize, u32, u32)> = None;
+    let mut restart_interval = 0;
+
+    loop {
+        // Skip fill bytes before the marker code
+        if *data.get(pos)? != 0xFF {
+            return None;
+        }
+        while *data.get(pos)? == 0xFF {
+            pos += 1;
+        }
+        let marker = data[pos];
+        pos += 1;
+        let length = usize::from(u16::from_be_bytes([*data.get(pos)?, *data.get(pos + 1)?]));
+        let segment = data.get(pos + 2..pos + length)?;
+
+        match marker {
+            // Baseline and extended sequential Huffman frames
+            0xC0 | 0xC1 => {
+                let components = usize::from(*segment.get(5)?);
+                let mut h_max = 1;
+                let mut v_max = 1;
+                for c in 0..components {
+                    let sampling = *segment.get(6 + 3 * c + 1)?;
+                    h_max = h_max.max(u32::from(sampling >> 4));
+                    v_max = v_max.max(u32::from(sampling & 0x0F));
+                }
+                let height = u32::from(u16::from_be_bytes([segment[1], segment[2]]));
+                let width = u32::from(u16::from_be_bytes([segment[3], segment[4]]));
+                frame = Some((pos + 3, width, height, components, h_max, v_max));
+            }
+            // Progressive, lossless, hierarchical or arithmetic frames
+            0xC2 | 0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => return None,
+            0xDD => {
+                restart_interval =
+                    u32::from(u16::from_be_bytes([*segment.first()?, *segment.get(1)?]));
+            }
+            0xDA => {
+                let (height_offset, width, height, components, h_max, v_max) = frame?;
+                // A scan covering fewer components means more scans follow
+                if usize::from(*segment.first()?) != components
+                    || restart_interval == 0
+                    || width == 0
+                    || height == 0
+                {
+                    return None;
+                }
+                // A single-component scan is never interleaved: one block per MCU
+                let (mcu_width, mcu_height) = if components == 1 {
+                    (8, 8)
+                } else {
+                    (8 * h_max, 8 * v_max)
+                };
+                return Some(JpegLayout {
+                    height_offset,
+                    width,
+                    height,
+                    mcu_width,
+                    mcu_height,
+                    restart_interval,
+                    scan_start: pos + length,
+                });
+            }
+            _ => {}
+        }
+        pos += length;
+    }
+}
+
+/// Build a JPEG holding the image from restart interval `skip` onward.
+///
+/// The headers are copied with the frame height reduced by `skipped_rows`
+/// pixels, and the entropy-coded data after the `skip`-th restart marker is
+/// appended with the remaining markers renumbered to start at `RST0`.
+/// Returns `None` if the scan has fewer restart markers than `skip`.
+fn jpeg_substream(
+    data: &[u8],
+    layout: &JpegLayout,
+    skip: u32,
+    skipped_rows: u32,
+) -> Option<Vec<u8>> {
+    let is_restart = |b: u8| (0xD0..=0xD7).contains(&b);
+
+    let mut pos = layout.scan_start;
+    let mut seen = 0;
+    while seen < skip {
+        if *data.get(pos)? == 0xFF && is_restart(*data.get(pos + 1)?) {
+            seen += 1;
+            pos += 1;
+        }
+        pos += 1;
+    }
+
+    let new_height = u16::try_from(layout.height - skipped_rows).ok()?;
+    let mut stream = Vec::with_capacity(layout.scan_start + data.len() - pos);
+    stream.extend_from_slice(&data[..layout.scan_start]);
+    stream[layout.height_offset..layout.height_offset + 2]
+        .copy_from_slice(&new_height.to_be_bytes());
+
+    #[allow(clippy::cast_possible_truncation)] // value is below 8
+    let shift = (skip % 8) as u8;
+    let mut prev = 0;
+    for &byte in &data[pos..] {
+        let byte = if prev == 0xFF && is_restart(byte) {
+            0xD0 + (byte - 0xD0 + 8 - shift) % 8
+        } else {
+            byte
+        };
+        stream.push(byte);
+        prev = byte;
+    }
+    Some(stream)
+}
+
+/// Least common multiple of two positive integers.
+fn lcm(a: u32, b: u32) -> u32 {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+    a / x * b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gradient test image with enough texture to make any misplaced row visible.
+    #[allow(clippy::cast_possible_truncation)]
+    fn gradient(w: u32, h: u32) -> RgbImage {
+        RgbImage::from_fn(w, h, |x, y| {
+            image::Rgb([
+                (x * 7 % 256) as u8,
+                (y * 5 % 256) as u8,
+                ((x + y) % 256) as u8,
+            ])
+        })
+    }
+
+    /// Encode `img` as a JPEG with a restart marker every `interval` MCUs.
+    fn write_restart_jpeg(img: &RgbImage, path: &Path, interval: u16) {
+        let mut encoder = jpeg_encoder::Encoder::new_file(path, 85).unwrap();
+        encoder.set_restart_interval(interval);
+        let w = u16::try_from(img.width()).unwrap();
+        let h = u16::try_from(img.height()).unwrap();
+        encoder
+            .encode(img.as_raw(), w, h, jpeg_encoder::ColorType::Rgb)
+            .unwrap();
+    }
+
+    fn full_crop(path: &Path, x: u32, y: u32, w: u32, h: u32) -> RgbImage {
+        let full = image::open(path).unwrap().to_rgb8();
+        crop(
+            &full,
+            Rect {
+                x,
+                y,
+                width: w,
+                height: h,
+            },
+        )
+    }
+
+    #[test]
+    fn png_region_matches_full_decode() {
+        let dir = std::env::temp_dir().join("gwr_test_decode_png");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let rgb = dir.join("rgb.png");
+        gradient(300, 200).save(&rgb).unwrap();
+        let gray16 = dir.join("gray16.png");
+        DynamicImage::ImageRgb8(gradient(300, 200))
+            .to_luma16()
+            .save(&gray16)
+            .unwrap();
+
+        for path in [&rgb, &gray16] {
+            let region = decode_region(path, 150, 90, 100, 80).unwrap();
+            assert_eq!(region, full_crop(path, 150, 90, 100, 80));
+        }
+
+        // Clipped at the bottom-right corner
+        let region = decode_region(&rgb, 250, 180, 100, 100).unwrap();
+        assert_eq!(region.dimensions(), (50, 20));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn jpeg_region_matches_full_decode() {
+        let dir = std::env::temp_dir().join("gwr_test_decode_jpeg");
+        std::fs::create_dir_all(&dir).unwrap();
+        let img = gradient(330, 300);
+
+        // Restart every MCU row (21 MCUs) and every 4 MCUs, which only lines
+        // up with a row start every fourth row
+        for interval in [21, 4] {
+            let path = dir.join(format!("restart{interval}.jpg"));
+            write_restart_jpeg(&img, &path, interval);
+
+            let data = std::fs::read(&path).unwrap();
+            let layout = parse_jpeg_layout(&data).unwrap();
+            assert_eq!((layout.mcu_width, layout.mcu_height), (16, 16));
+            assert_eq!(layout.restart_interval, u32::from(interval));
+
+            let region = decode_region(&path, 200, 170, 96, 96).unwrap();
+            assert_eq!(region, full_crop(&path, 200, 170, 96, 96));
+        }
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn jpeg_without_restart_markers_falls_back() {
+        let dir = std::env::temp_dir().join("gwr_test_decode_jpeg_plain");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("plain.jpg");
+        gradient(200, 200).save(&path).unwrap();
+
+        assert!(parse_jpeg_layout(&std::fs::read(&path).unwrap()).is_none());
+        let region = decode_region(&path, 120, 120, 48, 48).unwrap();
+        assert_eq!(region, full_crop(&path, 120, 120, 48, 48));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn decode_region_rejects_missing_and_corrupt_files() {
+        let dir = std::env::temp_dir().join("gwr_test_decode_corrupt");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("truncated.png");
+        let mut bytes = Vec::new();
+        gradient(100, 100)
+            .write_to(&mut std::io::Cursor::new(&mut bytes), ImageFormat::Png)
+            .unwrap();
+        bytes.truncate(bytes.len() / 2);
+        std::fs::write(&path, bytes).unwrap();
+
+        assert!(decode_region(&path, 0, 90, 10, 10).is_err());
+        assert!(decode_region(&dir.join("missing.png"), 0, 0, 1, 1).is_err());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -1,31 +1,256 @@
-//! Three-stage watermark detection algorithm.
+//! Multi-stage watermark detection algorithm.
 //!
-//! Detects the presence of a Gemini watermark using a weighted ensemble:
-//! 1. **Spatial NCC** (50%): normalized cross-correlation with the alpha map
-//! 2. **Gradient NCC** (30%): edge signature matching via Sobel operators
-//! 3. **Variance Analysis** (20%): texture dampening detection
+//! Detects the presence of a Gemini watermark using a weighted ensemble
+//! (default weights in parentheses, normalized by their sum):
+//! 1. **Spatial NCC** (0.35): normalized cross-correlation with the alpha map
+//! 2. **Gradient NCC** (0.2): edge signature matching via Sobel operators
+//! 3. **Variance Analysis** (0.1): texture dampening compared to the most
+//!    representative nearby [`ReferenceRegion`]
+//! 4. **Color Fit** (0.15): per-channel fit to the forward blending model
+//! 5. **Model Fit** (2.0): forward-model prediction over a background
+//!    estimated from the ring around the region
+//!
+//! The weights and thresholds are set by [`DetectionConfig`], which can be
+//! loaded from a config file.
+
+use std::path::Path;
 
 use image::RgbImage;
 
-/// Detection weight: spatial NCC.
-const SPATIAL_WEIGHT: f32 = 0.50;
-/// Detection weight: gradient NCC.
-const GRADIENT_WEIGHT: f32 = 0.30;
-/// Detection weight: variance analysis.
-const VARIANCE_WEIGHT: f32 = 0.20;
-/// Circuit breaker: if spatial NCC < this, reject early.
-const SPATIAL_CIRCUIT_BREAKER: f32 = 0.25;
-/// Internal detection threshold for declaring "detected".
-const DETECTION_THRESHOLD: f32 = 0.35;
-/// Minimum reference region height for variance analysis.
+use crate::alpha_map::AlphaMap;
+use crate::error::{Error, Result};
+use crate::keyvalue::{self, Line};
+
+/// Minimum reference region height (or width, for the region to the left)
+/// for variance analysis.
 const MIN_REF_HEIGHT: u32 = 8;
+/// Minimum number of low-alpha pixels inside the region needed to compare
+/// reference candidates against the background under the logo.
+const MIN_BACKGROUND_PIXELS: usize = 16;
 /// Minimum reference stddev to compute variance score (in normalized [0,1] space).
 const MIN_REF_STDDEV: f32 = 5.0 / 255.0;
+/// Minimum headroom below the logo color for a channel to count in the color
+/// fit (in normalized [0,1] space); saturated channels cannot show the overlay.
+const MIN_CHANNEL_HEADROOM: f64 = 8.0 / 255.0;
+/// Width in pixels of the ring around the region used to estimate the
+/// background for the model fit stage.
+pub(crate) const RING_WIDTH: u32 = 6;
+/// Minimum alpha for a pixel to count in the model fit residuals.
+const MODEL_MIN_ALPHA: f32 = 0.05;
+
+/// Weights and thresholds for the multi-stage detector.
+///
+/// # Decision rule
+///
+/// ```text
+/// confidence = (spatial_weight * spatial + gradient_weight * gradient
+///               + variance_weight * variance + color_weight * color
+///               + model_weight * model)
+///              / (sum of the weights)
+/// detected   = max(spatial, color) >= spatial_breaker && confidence >= threshold
+/// ```
+///
+/// When both the spatial and color scores are below `spatial_breaker`, the
+/// gradient, variance and model fit stages are skipped and score 0. The color
+/// score takes part in the breaker because grayscale correlation alone misses
+/// the logo on saturated backgrounds.
+///
+/// When no usable [`ReferenceRegion`] exists (image border, flat
+/// surroundings), the variance stage is left out of the weighted mean instead
+/// of scoring 0.
+///
+/// # Config file
+///
+/// [`DetectionConfig::load`] reads a plain-text file of `key = value` lines.
+/// Every key is optional and defaults to the values below. A `#` at the
+/// start of a line or after whitespace starts a comment, and an optional
+/// `[detection]` header is allowed:
+///
+/// ```text
+/// [detection]
+/// spatial_weight = 0.35
+/// gradient_weight = 0.2
+/// variance_weight = 0.1
+/// color_weight = 0.15
+/// model_weight = 2.0
+/// spatial_breaker = 0.25
+/// threshold = 0.25
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DetectionConfig {
+    /// Weight of the spatial NCC score.
+    pub spatial_weight: f32,
+    /// Weight of the gradient NCC score.
+    pub gradient_weight: f32,
+    /// Weight of the variance analysis score.
+    pub variance_weight: f32,
+    /// Weight of the per-channel color fit score.
+    pub color_weight: f32,
+    /// Weight of the forward-model fit score.
+    pub model_weight: f32,
+    /// Minimum spatial NCC or color fit score; below both, detection stops
+    /// early (circuit breaker).
+    pub spatial_breaker: f32,
+    /// Minimum confidence to declare a watermark detected (0.0-1.0).
+    pub threshold: f32,
+}
+
+impl Default for DetectionConfig {
+    fn default() -> Self {
+        Self {
+            spatial_weight: 0.35,
+            gradient_weight: 0.20,
+            variance_weight: 0.10,
+            color_weight: 0.15,
+            model_weight: 2.00,
+            spatial_breaker: 0.25,
+            threshold: 0.25,
+        }
+    }
+}
+
+impl DetectionConfig {
+    /// Load a config file (see the [type-level docs](Self) for the format).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if the file cannot be read and [`Error::Config`]
+    /// if it is malformed or a value is out of range.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|e| match e {
+            Error::Config(msg) => Error::Config(format!("{}: {msg}", path.display())),
+            other => other,
+        })
+    }
+
+    /// Parse config text, starting from the defaults.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Config`] on unknown keys or sections, unparsable
+    /// numbers, or values that fail [`validate`](Self::validate).
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut config = Self::default();
+        for line in keyvalue::lines(text) {
+            let (line_no, line) = line?;
+            let (key, value) = match line {
+                Line::Section("detection") => continue,
+                Line::Section(name) => {
+                    return Err(keyvalue::line_error(
+                        line_no,
+                        format!("unexpected section `[{name}]`"),
+                    ));
+                }
+                Line::Pair(key, value) => (key, value),
+            };
+            let value: f32 = value
+                .parse()
+                .map_err(|_| keyvalue::line_error(line_no, format!("`{value}` is not a number")))?;
+            let field = match key {
+                "spatial_weight" => &mut config.spatial_weight,
+                "gradient_weight" => &mut config.gradient_weight,
+                "variance_weight" => &mut config.variance_weight,
+                "color_weight" => &mut config.color_weight,
+                "model_weight" => &mut config.model_weight,
+                "spatial_breaker" => &mut config.spatial_breaker,
+                "threshold" => &mut config.threshold,
+                _ => {
+                    return Err(keyvalue::line_error(
+                        line_no,
+                        format!("unknown key `{key}`"),
+                    ))
+                }
+            };
+            *field = value;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check that weights are non-negative with a positive sum and thresholds
+    /// lie in `[0, 1]`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Config`] describing the first invalid value.
+    pub fn validate(&self) -> Result<()> {
+        let weights = [
+            ("spatial_weight", self.spatial_weight),
+            ("gradient_weight", self.gradient_weight),
+            ("variance_weight", self.variance_weight),
+            ("color_weight", self.color_weight),
+            ("model_weight", self.model_weight),
+        ];
+        for (name, value) in weights {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(Error::Config(format!(
+                    "{name} must be a non-negative number, got {value}"
+                )));
+            }
+        }
+        if self.weight_sum() <= 0.0 {
+            return Err(Error::Config("at least one weight must be positive".into()));
+        }
+        for (name, value) in [
+            ("spatial_breaker", self.spatial_breaker),
+            ("threshold", self.threshold),
+        ] {
+            if !(0.0..=1.0).contains(&value) {
+                return Err(Error::Config(format!(
+                    "{name} must be between 0.0 and 1.0, got {value}"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl DetectionConfig {
+    fn weight_sum(&self) -> f32 {
+        self.spatial_weight
+            + self.gradient_weight
+            + self.variance_weight
+            + self.color_weight
+            + self.model_weight
+    }
+
+    /// Weighted mean of the stage scores, leaving out the variance stage when
+    /// `include_variance` is false.
+    fn confidence(&self, result: &DetectionResult, include_variance: bool) -> f32 {
+        let variance_weight = if include_variance {
+            self.variance_weight
+        } else {
+            0.0
+        };
+        let weighted = self.spatial_weight * result.spatial_score
+            + self.gradient_weight * result.gradient_score
+            + variance_weight * result.variance_score
+            + self.color_weight * result.color_score
+            + self.model_weight * result.model_score;
+        let total = self.weight_sum() - self.variance_weight + variance_weight;
+        if total <= 0.0 {
+            return 0.0;
+        }
+        (weighted / total).clamp(0.0, 1.0)
+    }
+}
+
+/// Neighborhood used as the texture reference by the variance stage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReferenceRegion {
+    /// Strip of up to one watermark height directly above the region.
+    Above,
+    /// Strip of up to one watermark width directly left of the region.
+    Left,
+    /// Ring a few pixels wide surrounding the region on all sides.
+    Ring,
+}
 
 /// Result of watermark detection.
 #[derive(Debug, Clone)]
 pub struct DetectionResult {
-    /// Whether a watermark was detected above the confidence threshold.
+    /// Whether a watermark was detected, per the [`DetectionConfig`] decision rule.
     pub detected: bool,
     /// Overall confidence score in `[0, 1]`.
     pub confidence: f32,
@@ -35,6 +260,20 @@ pub struct DetectionResult {
     pub gradient_score: f32,
     /// Stage 3: variance analysis score.
     pub variance_score: f32,
+    /// Stage 4: per-channel color fit score.
+    pub color_score: f32,
+    /// Stage 5: forward-model fit score.
+    pub model_score: f32,
+    /// Reference region used by the variance stage (`None` if the stage did
+    /// not run or no usable reference was found).
+    pub reference: Option<ReferenceRegion>,
+    /// Name of the watermark profile that was matched. Set by
+    /// [`WatermarkEngine`](crate::WatermarkEngine); `None` from
+    /// [`detect_watermark`], which works on a bare alpha map.
+    pub profile: Option<String>,
+    /// Id of the profile's logo version that was matched, set alongside
+    /// [`profile`](Self::profile).
+    pub version: Option<String>,
 }
 
 impl Default for DetectionResult {
@@ -45,6 +284,11 @@ impl Default for DetectionResult {
             spatial_score: 0.0,
             gradient_score: 0.0,
             variance_score: 0.0,
+            color_score: 0.0,
+            model_score: 0.0,
+            reference: None,
+            profile: None,
+            version: None,
         }
     }
 }
@@ -52,23 +296,27 @@ impl Default for DetectionResult {
 /// Convert an RGB image region to grayscale float values in `[0, 1]`.
 ///
 /// Uses luminance formula: `0.299*R + 0.587*G + 0.114*B`.
-fn region_to_grayscale(img: &RgbImage, x: u32, y: u32, w: u32, h: u32) -> Vec<f32> {
+pub(crate) fn region_to_grayscale(img: &RgbImage, x: u32, y: u32, w: u32, h: u32) -> Vec<f32> {
     let mut gray = Vec::with_capacity((w * h) as usize);
     for dy in 0..h {
         for dx in 0..w {
-            let px = img.get_pixel(x + dx, y + dy);
-            let lum =
-                0.299 * f32::from(px[0]) + 0.587 * f32::from(px[1]) + 0.114 * f32::from(px[2]);
-            gray.push(lum / 255.0);
+            gray.push(luminance(img, x + dx, y + dy));
         }
     }
     gray
 }
 
+/// Luminance of one pixel in `[0, 1]`.
+fn luminance(img: &RgbImage, x: u32, y: u32) -> f32 {
+    let px = img.get_pixel(x, y);
+    let lum = 0.299 * f32::from(px[0]) + 0.587 * f32::from(px[1]) + 0.114 * f32::from(px[2]);
+    lum / 255.0
+}
+
 /// Normalized Cross-Correlation between two equal-length float slices.
 ///
 /// `NCC = sum((a-mean_a)*(b-mean_b)) / sqrt(sum((a-mean_a)^2) * sum((b-mean_b)^2))`
-fn ncc(a: &[f32], b: &[f32]) -> f32 {
+pub(crate) fn ncc(a: &[f32], b: &[f32]) -> f32 {
     debug_assert_eq!(a.len(), b.len());
     #[allow(clippy::cast_precision_loss)]
     let n = a.len() as f32;
@@ -102,7 +350,7 @@ fn ncc(a: &[f32], b: &[f32]) -> f32 {
 /// Compute Sobel gradient magnitude for a 2D float array.
 ///
 /// Uses 3x3 Sobel kernels. Border pixels are set to 0.
-fn sobel_magnitude(data: &[f32], width: usize, height: usize) -> Vec<f32> {
+pub(crate) fn sobel_magnitude(data: &[f32], width: usize, height: usize) -> Vec<f32> {
     let mut result = vec![0.0_f32; width * height];
 
     for y in 1..height - 1 {
@@ -140,42 +388,325 @@ fn stddev(data: &[f32]) -> f32 {
     variance.sqrt()
 }
 
+/// Score how well each color channel of a region fits the forward blending
+/// model `channel = bg + alpha * (logo - bg)` with a logo of color
+/// `logo_value` (0-255).
+///
+/// Each channel is regressed on alpha as `channel = a + b * alpha`; on a
+/// locally flat background the fit has slope `b = logo - a`. A channel scores
+/// its correlation with alpha (in the direction the logo moves it) times how
+/// close the slope is to that expectation, and channels are averaged weighted
+/// by their headroom `|logo - a|`, so channels already at the logo color do
+/// not drown out the others.
+fn color_fit_score(
+    image: &RgbImage,
+    x: u32,
+    y: u32,
+    alpha_region: &AlphaMap,
+    logo_value: f32,
+) -> f32 {
+    let logo = f64::from(logo_value) / 255.0;
+    let (w, h) = (alpha_region.width(), alpha_region.height());
+    let alpha: Vec<f64> = alpha_region
+        .values()
+        .iter()
+        .map(|&a| f64::from(a))
+        .collect();
+    #[allow(clippy::cast_precision_loss)]
+    let count = alpha.len() as f64;
+    if count < 2.0 {
+        return 0.0;
+    }
+    let alpha_mean = alpha.iter().sum::<f64>() / count;
+    let alpha_var: f64 = alpha.iter().map(|a| (a - alpha_mean).powi(2)).sum();
+    if alpha_var < 1e-10 {
+        return 0.0;
+    }
+
+    let (mut score_sum, mut weight_sum) = (0.0, 0.0);
+    for ch in 0..3 {
+        let mut values = Vec::with_capacity(alpha.len());
+        for dy in 0..h {
+            for dx in 0..w {
+                values.push(f64::from(image.get_pixel(x + dx, y + dy)[ch]) / 255.0);
+            }
+        }
+        let mean = values.iter().sum::<f64>() / count;
+        let (mut cov, mut var) = (0.0, 0.0);
+        for (v, a) in values.iter().zip(&alpha) {
+            cov += (v - mean) * (a - alpha_mean);
+            var += (v - mean).powi(2);
+        }
+
+        let slope = cov / alpha_var;
+        let intercept = mean - slope * alpha_mean;
+        let headroom = logo - intercept;
+        if headroom.abs() < MIN_CHANNEL_HEADROOM {
+            continue;
+        }
+        let correlation = if var < 1e-10 {
+            0.0
+        } else {
+            (headroom.signum() * cov / (alpha_var * var).sqrt()).max(0.0)
+        };
+        let consistency = (1.0 - (slope / headroom - 1.0).abs()).max(0.0);
+        score_sum += headroom.abs() * correlation * consistency;
+        weight_sum += headroom.abs();
+    }
+
+    if weight_sum <= 0.0 {
+        return 0.0;
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    let score = (score_sum / weight_sum) as f32;
+    score
+}
+
+/// Grayscale pixels of the ring of [`RING_WIDTH`] pixels around a region,
+/// clipped to the image.
+fn ring_to_grayscale(img: &RgbImage, x: u32, y: u32, w: u32, h: u32) -> Vec<f32> {
+    let (x0, y0) = (x.saturating_sub(RING_WIDTH), y.saturating_sub(RING_WIDTH));
+    let x1 = (x + w + RING_WIDTH).min(img.width());
+    let y1 = (y + h + RING_WIDTH).min(img.height());
+    let mut gray = Vec::new();
+    for py in y0..y1 {
+        for px in x0..x1 {
+            let inside = px >= x && px < x + w && py >= y && py < y + h;
+            if !inside {
+                gray.push(luminance(img, px, py));
+            }
+        }
+    }
+    gray
+}
+
+/// Mean and standard deviation of a float slice.
+fn mean_stddev(data: &[f32]) -> (f32, f32) {
+    if data.is_empty() {
+        return (0.0, 0.0);
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let mean = data.iter().sum::<f32>() / data.len() as f32;
+    (mean, stddev(data))
+}
+
+/// Pick the reference region for the variance stage.
+///
+/// Candidates are the strip above, the strip to the left and the surrounding
+/// ring; those too small or too flat (stddev below [`MIN_REF_STDDEV`]) are
+/// dropped. The pixels inside the region where the alpha map is negligible
+/// show the true background, so the candidate whose mean and stddev are
+/// closest to theirs wins. Without enough such pixels the ring is preferred,
+/// then above, then left. Returns the chosen region and its stddev.
+fn select_reference(
+    image: &RgbImage,
+    x: u32,
+    y: u32,
+    gray_region: &[f32],
+    alpha_region: &AlphaMap,
+) -> Option<(ReferenceRegion, f32)> {
+    let (w, h) = (alpha_region.width(), alpha_region.height());
+    let mut candidates = Vec::with_capacity(3);
+    let ring = ring_to_grayscale(image, x, y, w, h);
+    if ring.len() > (MIN_REF_HEIGHT * w.max(h)) as usize {
+        candidates.push((ReferenceRegion::Ring, ring));
+    }
+    let above_h = y.min(h);
+    if above_h > MIN_REF_HEIGHT {
+        let above = region_to_grayscale(image, x, y - above_h, w, above_h);
+        candidates.push((ReferenceRegion::Above, above));
+    }
+    let left_w = x.min(w);
+    if left_w > MIN_REF_HEIGHT {
+        let left = region_to_grayscale(image, x - left_w, y, left_w, h);
+        candidates.push((ReferenceRegion::Left, left));
+    }
+
+    let background: Vec<f32> = gray_region
+        .iter()
+        .zip(alpha_region.values())
+        .filter(|(_, &alpha)| alpha < MODEL_MIN_ALPHA)
+        .map(|(&v, _)| v)
+        .collect();
+    let (bg_mean, bg_stddev) = mean_stddev(&background);
+
+    let mut best: Option<(ReferenceRegion, f32, f32)> = None;
+    for (region, pixels) in candidates {
+        let (mean, sd) = mean_stddev(&pixels);
+        if sd <= MIN_REF_STDDEV {
+            continue;
+        }
+        let distance = if background.len() >= MIN_BACKGROUND_PIXELS {
+            (mean - bg_mean).abs() + (sd - bg_stddev).abs()
+        } else {
+            0.0
+        };
+        if best.is_none_or(|(_, _, d)| distance < d) {
+            best = Some((region, sd, distance));
+        }
+    }
+    best.map(|(region, sd, _)| (region, sd))
+}
+
+/// Least-squares plane `c0 + c1 * x + c2 * y` through `(x, y, value)` samples,
+/// falling back to the mean when the samples do not span a plane.
+fn fit_plane(samples: &[(f64, f64, f64)]) -> [f64; 3] {
+    let mut ata = [[0.0f64; 3]; 3];
+    let mut atb = [0.0f64; 3];
+    for &(x, y, v) in samples {
+        let row = [1.0, x, y];
+        for i in 0..3 {
+            for j in 0..3 {
+                ata[i][j] += row[i] * row[j];
+            }
+            atb[i] += row[i] * v;
+        }
+    }
+
+    // Cramer's rule on the 3x3 normal equations
+    let det = |m: &[[f64; 3]; 3]| {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+    let denom = det(&ata);
+    if denom.abs() < 1e-9 * ata[0][0].max(1.0).powi(3) {
+        let mean = if ata[0][0] > 0.0 {
+            atb[0] / ata[0][0]
+        } else {
+            0.0
+        };
+        return [mean, 0.0, 0.0];
+    }
+    std::array::from_fn(|col| {
+        let mut m = ata;
+        for (row, value) in m.iter_mut().zip(atb) {
+            row[col] = value;
+        }
+        det(&m) / denom
+    })
+}
+
+/// Score how well the region is explained by the forward blending equation
+/// `alpha * logo_value + (1 - alpha) * bg` compared to no watermark at all.
+///
+/// The background `bg` is a plane fitted per channel to a ring of
+/// [`RING_WIDTH`] pixels around the region. The score is the fraction of the
+/// no-watermark squared error that the watermark prediction removes, over
+/// pixels with visible alpha: near 1 when the intensity change matches the
+/// equation, near 0 for content that merely correlates with the logo shape.
+fn model_fit_score(
+    image: &RgbImage,
+    x: u32,
+    y: u32,
+    alpha_region: &AlphaMap,
+    logo_value: f32,
+) -> f32 {
+    let (w, h) = (alpha_region.width(), alpha_region.height());
+    let (img_w, img_h) = image.dimensions();
+    let (rx0, ry0) = (x.saturating_sub(RING_WIDTH), y.saturating_sub(RING_WIDTH));
+    let rx1 = (x + w + RING_WIDTH).min(img_w);
+    let ry1 = (y + h + RING_WIDTH).min(img_h);
+    let inside = |px: u32, py: u32| px >= x && px < x + w && py >= y && py < y + h;
+
+    // Coordinates relative to the region center keep the fit well conditioned
+    let (cx, cy) = (
+        f64::from(x) + f64::from(w) / 2.0,
+        f64::from(y) + f64::from(h) / 2.0,
+    );
+    let mut ring: [Vec<(f64, f64, f64)>; 3] = Default::default();
+    for py in ry0..ry1 {
+        for px in rx0..rx1 {
+            if inside(px, py) {
+                continue;
+            }
+            let pixel = image.get_pixel(px, py);
+            for (ch, samples) in ring.iter_mut().enumerate() {
+                samples.push((f64::from(px) - cx, f64::from(py) - cy, f64::from(pixel[ch])));
+            }
+        }
+    }
+    if ring[0].len() < 3 {
+        return 0.0;
+    }
+    let planes = ring.map(|samples| fit_plane(&samples));
+
+    let (mut err_none, mut err_model) = (0.0f64, 0.0f64);
+    for dy in 0..h {
+        for dx in 0..w {
+            let alpha = alpha_region.values()[(dy * w + dx) as usize];
+            if alpha < MODEL_MIN_ALPHA {
+                continue;
+            }
+            let alpha = f64::from(alpha);
+            let (px, py) = (f64::from(x + dx) - cx, f64::from(y + dy) - cy);
+            let pixel = image.get_pixel(x + dx, y + dy);
+            for (ch, plane) in planes.iter().enumerate() {
+                let bg = (plane[0] + plane[1] * px + plane[2] * py).clamp(0.0, 255.0);
+                let observed = f64::from(pixel[ch]);
+                let predicted = alpha * f64::from(logo_value) + (1.0 - alpha) * bg;
+                err_none += (observed - bg).powi(2);
+                err_model += (observed - predicted).powi(2);
+            }
+        }
+    }
+
+    if err_none <= 0.0 {
+        return 0.0;
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    let score = (1.0 - err_model / err_none).clamp(0.0, 1.0) as f32;
+    score
+}
+
 /// Detect whether a Gemini watermark is present at the given position.
 ///
-/// Uses a three-stage weighted ensemble:
-/// 1. **Spatial NCC** (50%): correlation between region brightness and alpha map
-/// 2. **Gradient NCC** (30%): edge signature matching via Sobel operators
-/// 3. **Variance Analysis** (20%): texture dampening detection
+/// Uses a weighted ensemble of five stages (default weights in parentheses):
+/// 1. **Spatial NCC** (0.35): correlation between region brightness and alpha map
+/// 2. **Gradient NCC** (0.2): edge signature matching via Sobel operators
+/// 3. **Variance Analysis** (0.1): texture dampening detection
+/// 4. **Color Fit** (0.15): per-channel fit to the forward blending model
+/// 5. **Model Fit** (2.0): forward-model prediction over a background fitted
+///    to the ring around the region
+///
+/// See [`DetectionConfig`] for the weights and the decision rule.
 ///
 /// # Arguments
 ///
 /// * `image` - The image to analyze.
-/// * `alpha_map` - Flat alpha map array of size `wm_width * wm_height`.
-/// * `wm_width` - Width of the watermark region.
-/// * `wm_height` - Height of the watermark region.
+/// * `alpha_map` - The watermark's alpha map.
 /// * `pos_x` - X coordinate of the watermark's top-left corner.
 /// * `pos_y` - Y coordinate of the watermark's top-left corner.
-/// * `user_threshold` - User-specified threshold for the spatial circuit breaker.
-#[must_use]
+/// * `logo_value` - Logo color the watermark blends toward (255 for the white
+///   Gemini logo), used by the color and model fit stages.
+/// * `config` - Stage weights, spatial circuit breaker and detection threshold.
+///
+/// A region entirely outside the image yields an empty, undetected result.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidRegion`] if the watermark's right or bottom edge
+/// overflows `u32`.
 pub fn detect_watermark(
     image: &RgbImage,
-    alpha_map: &[f32],
-    wm_width: u32,
-    wm_height: u32,
+    alpha_map: &AlphaMap,
     pos_x: u32,
     pos_y: u32,
-    user_threshold: f32,
-) -> DetectionResult {
+    logo_value: f32,
+    config: &DetectionConfig,
+) -> Result<DetectionResult> {
+    alpha_map.check_position(pos_x, pos_y)?;
     let mut result = DetectionResult::default();
 
     let img_w = image.width();
     let img_h = image.height();
+    let (wm_width, wm_height) = (alpha_map.width(), alpha_map.height());
 
     // Clip ROI to image bounds
     let x2 = (pos_x + wm_width).min(img_w);
     let y2 = (pos_y + wm_height).min(img_h);
     if pos_x >= x2 || pos_y >= y2 {
-        return result;
+        return Ok(result);
     }
 
     let roi_w = x2 - pos_x;
@@ -185,62 +716,51 @@ pub fn detect_watermark(
     let gray_region = region_to_grayscale(image, pos_x, pos_y, roi_w, roi_h);
 
     // Get corresponding alpha sub-region (in case of clipping)
-    let alpha_region: Vec<f32> = if roi_w == wm_width && roi_h == wm_height {
-        alpha_map.to_vec()
+    let clipped;
+    let alpha_region = if roi_w == wm_width && roi_h == wm_height {
+        alpha_map
     } else {
-        let mut sub = Vec::with_capacity((roi_w * roi_h) as usize);
-        for dy in 0..roi_h {
-            for dx in 0..roi_w {
-                sub.push(alpha_map[(dy * wm_width + dx) as usize]);
-            }
-        }
-        sub
+        clipped = alpha_map.crop(0, 0, roi_w, roi_h)?;
+        &clipped
     };
 
     // Stage 1: Spatial NCC
-    let spatial_score = ncc(&gray_region, &alpha_region).max(0.0);
+    let spatial_score = ncc(&gray_region, alpha_region.values()).max(0.0);
     result.spatial_score = spatial_score;
 
+    // Stage 4: Color fit (before the breaker, which it can keep open)
+    result.color_score = color_fit_score(image, pos_x, pos_y, alpha_region, logo_value);
+
     // Circuit breaker
-    let breaker = user_threshold.min(SPATIAL_CIRCUIT_BREAKER);
-    if spatial_score < breaker {
-        result.confidence = spatial_score * 0.5;
-        return result;
+    if spatial_score.max(result.color_score) < config.spatial_breaker {
+        result.confidence = config.confidence(&result, true);
+        return Ok(result);
     }
 
     // Stage 2: Gradient NCC
     let w = roi_w as usize;
     let h = roi_h as usize;
     let img_grad = sobel_magnitude(&gray_region, w, h);
-    let alpha_grad = sobel_magnitude(&alpha_region, w, h);
+    let alpha_grad = sobel_magnitude(alpha_region.values(), w, h);
     let gradient_score = ncc(&img_grad, &alpha_grad).max(0.0);
     result.gradient_score = gradient_score;
 
     // Stage 3: Variance Analysis
-    let mut variance_score = 0.0_f32;
-
-    // Use region above watermark as reference
-    let ref_h = pos_y.min(wm_height).min(img_h.saturating_sub(pos_y));
-    if ref_h > MIN_REF_HEIGHT && pos_y >= ref_h {
-        let ref_region = region_to_grayscale(image, pos_x, pos_y - ref_h, roi_w, ref_h);
+    let reference = select_reference(image, pos_x, pos_y, &gray_region, alpha_region);
+    if let Some((region, ref_stddev)) = reference {
         let wm_stddev = stddev(&gray_region);
-        let ref_stddev = stddev(&ref_region);
-
-        if ref_stddev > MIN_REF_STDDEV {
-            variance_score = (1.0 - wm_stddev / ref_stddev).clamp(0.0, 1.0);
-        }
+        result.variance_score = (1.0 - wm_stddev / ref_stddev).clamp(0.0, 1.0);
+        result.reference = Some(region);
     }
-    result.variance_score = variance_score;
 
-    // Weighted ensemble
-    let confidence = SPATIAL_WEIGHT * spatial_score
-        + GRADIENT_WEIGHT * gradient_score
-        + VARIANCE_WEIGHT * variance_score;
+    // Stage 5: Model fit
+    result.model_score = model_fit_score(image, pos_x, pos_y, alpha_region, logo_value);
 
-    result.confidence = confidence.clamp(0.0, 1.0);
-    result.detected = result.confidence >= DETECTION_THRESHOLD;
+    // Weighted ensemble
+    result.confidence = config.confidence(&result, result.reference.is_some());
+    result.detected = result.confidence >= config.threshold;
 
-    result
+    Ok(result)
 }
 
 #[cfg(test)]
@@ -318,21 +838,40 @@ mod tests {
     fn detect_returns_result_for_small_image_with_clipping() {
         // Image smaller than watermark region — should not panic, ROI gets clipped
         let img = RgbImage::new(20, 20);
-        let alpha_map = vec![0.3; 48 * 48];
+        let alpha_map = AlphaMap::new(vec![0.3; 48 * 48], 48, 48).unwrap();
         // Watermark 48x48 placed at (0,0) on a 20x20 image — heavy clipping
-        let result = detect_watermark(&img, &alpha_map, 48, 48, 0, 0, 0.25);
+        let result =
+            detect_watermark(&img, &alpha_map, 0, 0, 255.0, &DetectionConfig::default()).unwrap();
         // Should run without panic, confidence should be low for blank image
         assert!(!result.detected);
     }
 
+    #[test]
+    fn detect_rejects_overflowing_position() {
+        let img = RgbImage::new(100, 100);
+        let config = DetectionConfig::default();
+        let alpha_map = AlphaMap::new(vec![0.3; 4], 2, 2).unwrap();
+        let err = detect_watermark(&img, &alpha_map, u32::MAX, 0, 255.0, &config).unwrap_err();
+        assert!(
+            matches!(err, Error::InvalidRegion { x: u32::MAX, .. }),
+            "{err:?}"
+        );
+
+        // In range but off the image: nothing to detect
+        let result = detect_watermark(&img, &alpha_map, u32::MAX - 2, 0, 255.0, &config).unwrap();
+        assert!(!result.detected);
+    }
+
     #[test]
     fn detect_circuit_breaker_rejects_low_spatial() {
         // Uniform image should have near-zero spatial NCC with any alpha pattern
         let img = RgbImage::new(100, 100); // all black
         #[allow(clippy::cast_precision_loss)]
-        let alpha_map: Vec<f32> = (0..48 * 48).map(|i| (i % 5) as f32 / 10.0).collect();
+        let values = (0..48 * 48).map(|i| (i % 5) as f32 / 10.0).collect();
+        let alpha_map = AlphaMap::new(values, 48, 48).unwrap();
 
-        let result = detect_watermark(&img, &alpha_map, 48, 48, 20, 20, 0.25);
+        let result =
+            detect_watermark(&img, &alpha_map, 20, 20, 255.0, &DetectionConfig::default()).unwrap();
 
         // Circuit breaker should trigger: gradient and variance stay 0
         assert!(result.gradient_score.abs() < f32::EPSILON);
@@ -364,4 +903,266 @@ mod tests {
             "Edge should produce non-zero gradient, got {center_grad}"
         );
     }
+
+    #[test]
+    fn config_parse_overrides_defaults() {
+        let text =
+            "# tuned for JPEG\n[detection]\nthreshold = \"0.4\" # stricter\n\nspatial_weight=0.6\n";
+        let config = DetectionConfig::parse(text).unwrap();
+        assert!((config.threshold - 0.4).abs() < f32::EPSILON);
+        assert!((config.spatial_weight - 0.6).abs() < f32::EPSILON);
+        let expected = DetectionConfig {
+            threshold: config.threshold,
+            spatial_weight: config.spatial_weight,
+            ..DetectionConfig::default()
+        };
+        assert_eq!(config, expected);
+        assert_eq!(
+            DetectionConfig::parse("").unwrap(),
+            DetectionConfig::default()
+        );
+    }
+
+    #[test]
+    fn config_parse_rejects_bad_input() {
+        for text in [
+            "threshold = 1.5",
+            "spatial_weight = -1",
+            "gradient_weight = nan",
+            "thresold = 0.3",
+            "threshold",
+            "threshold = high",
+            "[other]",
+            "threshold = 0.4# no space before the comment",
+        ] {
+            let err = DetectionConfig::parse(text).unwrap_err();
+            assert!(matches!(err, Error::Config(_)), "{text}: {err}");
+        }
+        let err = DetectionConfig::parse("\nbogus = 1").unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{err}");
+    }
+
+    #[test]
+    fn detect_uses_config_threshold() {
+        // Stamp a logo onto a horizontal gradient so all stages fire
+        #[allow(clippy::cast_possible_truncation)]
+        let mut img = RgbImage::from_fn(100, 100, |x, _| image::Rgb([(x * 2) as u8; 3]));
+        #[allow(clippy::cast_precision_loss)]
+        let values = (0..48 * 48)
+            .map(|i| if (i / 48 + i % 48) % 12 < 6 { 0.5 } else { 0.0 })
+            .collect();
+        let alpha_map = AlphaMap::new(values, 48, 48).unwrap();
+        crate::blending::apply_watermark_alpha_blend(&mut img, &alpha_map, 40, 40, 255.0).unwrap();
+
+        let lenient =
+            detect_watermark(&img, &alpha_map, 40, 40, 255.0, &DetectionConfig::default()).unwrap();
+        assert!(lenient.detected, "{lenient:?}");
+
+        let strict = DetectionConfig {
+            threshold: 1.0,
+            ..DetectionConfig::default()
+        };
+        let result = detect_watermark(&img, &alpha_map, 40, 40, 255.0, &strict).unwrap();
+        assert!(!result.detected);
+        assert!((result.confidence - lenient.confidence).abs() < f32::EPSILON);
+    }
+
+    /// Radial alpha falling from 0.5 at the center to 0 at the edge.
+    fn radial_alpha(size: u32) -> AlphaMap {
+        #[allow(clippy::cast_precision_loss)]
+        let half = size as f32 / 2.0;
+        let values = (0..size * size)
+            .map(|i| {
+                #[allow(clippy::cast_precision_loss)]
+                let (dx, dy) = ((i % size) as f32 - half, (i / size) as f32 - half);
+                0.5 * (1.0 - (dx * dx + dy * dy).sqrt() / half).max(0.0)
+            })
+            .collect();
+        AlphaMap::new(values, size, size).unwrap()
+    }
+
+    #[test]
+    fn color_stage_detects_logo_on_saturated_texture() {
+        // Bright textured yellow: red and green are near saturation and carry
+        // the luminance texture, so only the blue channel shows the overlay
+        #[allow(clippy::cast_possible_truncation)]
+        let mut img = RgbImage::from_fn(100, 100, |x, y| {
+            let noise =
+                (x.wrapping_mul(7919) ^ y.wrapping_mul(104_729)).wrapping_mul(2_654_435_761);
+            let v = 160 + (noise >> 24) as u8 % 96;
+            image::Rgb([v, v, 20])
+        });
+        let alpha_map = radial_alpha(48);
+        crate::blending::apply_watermark_alpha_blend(&mut img, &alpha_map, 40, 40, 255.0).unwrap();
+
+        let result =
+            detect_watermark(&img, &alpha_map, 40, 40, 255.0, &DetectionConfig::default()).unwrap();
+        assert!(result.spatial_score < 0.5, "{result:?}");
+        assert!(result.color_score > 0.6, "{result:?}");
+        assert!(result.detected, "{result:?}");
+
+        let gray_only = DetectionConfig {
+            color_weight: 0.0,
+            ..DetectionConfig::default()
+        };
+        let without = detect_watermark(&img, &alpha_map, 40, 40, 255.0, &gray_only).unwrap();
+        assert!(without.confidence < result.confidence);
+    }
+
+    #[test]
+    fn color_stage_ignores_saturated_channels_and_plain_texture() {
+        // Flat white: no headroom in any channel
+        let white = RgbImage::from_pixel(60, 60, image::Rgb([255, 255, 255]));
+        let alpha_map = radial_alpha(48);
+        assert!(color_fit_score(&white, 0, 0, &alpha_map, 255.0).abs() < f32::EPSILON);
+
+        // A gradient unrelated to the logo shape scores low
+        #[allow(clippy::cast_possible_truncation)]
+        let ramp = RgbImage::from_fn(60, 60, |x, _| image::Rgb([(x * 4) as u8; 3]));
+        let score = color_fit_score(&ramp, 0, 0, &alpha_map, 255.0);
+        assert!(score < 0.1, "{score}");
+    }
+
+    #[test]
+    fn fit_plane_recovers_plane_and_falls_back_to_mean() {
+        let samples: Vec<(f64, f64, f64)> = (0..25)
+            .map(|i| {
+                let (x, y) = (f64::from(i % 5), f64::from(i / 5));
+                (x, y, 10.0 + 2.0 * x - 3.0 * y)
+            })
+            .collect();
+        let plane = fit_plane(&samples);
+        for (got, want) in plane.iter().zip([10.0, 2.0, -3.0]) {
+            assert!((got - want).abs() < 1e-9, "{plane:?}");
+        }
+
+        // Collinear samples cannot define a plane
+        let line = [(0.0, 0.0, 4.0), (1.0, 1.0, 6.0), (2.0, 2.0, 8.0)];
+        let fallback = fit_plane(&line);
+        assert!(fallback
+            .iter()
+            .zip([6.0, 0.0, 0.0])
+            .all(|(got, want)| (got - want).abs() < 1e-12));
+    }
+
+    #[test]
+    fn model_fit_separates_logo_from_dim_star_content() {
+        #[allow(clippy::cast_possible_truncation)]
+        let background = RgbImage::from_fn(120, 120, |x, y| {
+            image::Rgb([
+                (40 + x / 2) as u8,
+                (60 + y / 3) as u8,
+                (30 + (x + y) / 4) as u8,
+            ])
+        });
+        let alpha_map = radial_alpha(48);
+
+        let mut logo = background.clone();
+        crate::blending::apply_watermark_alpha_blend(&mut logo, &alpha_map, 40, 40, 255.0).unwrap();
+        let logo_score = model_fit_score(&logo, 40, 40, &alpha_map, 255.0);
+        assert!(logo_score > 0.9, "{logo_score}");
+
+        // Same shape, but blending toward mid-gray: correlates like the logo
+        // without matching its intensity
+        let mut star = background.clone();
+        crate::blending::apply_watermark_alpha_blend(&mut star, &alpha_map, 40, 40, 140.0).unwrap();
+        let star_score = model_fit_score(&star, 40, 40, &alpha_map, 255.0);
+        assert!(star_score < 0.5, "{star_score}");
+
+        let config = DetectionConfig::default();
+        let star_result = detect_watermark(&star, &alpha_map, 40, 40, 255.0, &config).unwrap();
+        let logo_result = detect_watermark(&logo, &alpha_map, 40, 40, 255.0, &config).unwrap();
+        assert!(star_result.spatial_score > 0.9, "{star_result:?}");
+        assert!(star_result.confidence < logo_result.confidence);
+        assert!(!star_result.detected, "{star_result:?}");
+
+        assert!(model_fit_score(&background, 40, 40, &alpha_map, 255.0) < 0.05);
+    }
+
+    #[test]
+    fn fit_stages_use_the_logo_value() {
+        #[allow(clippy::cast_possible_truncation)]
+        let mut img = RgbImage::from_fn(120, 120, |x, y| {
+            image::Rgb([
+                (20 + x / 3) as u8,
+                (30 + y / 4) as u8,
+                (25 + (x + y) / 6) as u8,
+            ])
+        });
+        let alpha_map = radial_alpha(48);
+        crate::blending::apply_watermark_alpha_blend(&mut img, &alpha_map, 40, 40, 140.0).unwrap();
+
+        assert!(model_fit_score(&img, 40, 40, &alpha_map, 140.0) > 0.9);
+        assert!(color_fit_score(&img, 40, 40, &alpha_map, 140.0) > 0.7);
+        assert!(model_fit_score(&img, 40, 40, &alpha_map, 255.0) < 0.5);
+
+        let config = DetectionConfig::default();
+        let dim = detect_watermark(&img, &alpha_map, 40, 40, 140.0, &config).unwrap();
+        let white = detect_watermark(&img, &alpha_map, 40, 40, 255.0, &config).unwrap();
+        assert!(dim.detected, "{dim:?}");
+        assert!(dim.confidence > white.confidence, "{dim:?} {white:?}");
+    }
+
+    /// Checkerboard texture with the given cell size and contrast.
+    fn texture(x: u32, y: u32, cell: u32, low: u8, high: u8) -> image::Rgb<u8> {
+        image::Rgb(
+            [if (x / cell + y / cell) % 2 == 0 {
+                low
+            } else {
+                high
+            }; 3],
+        )
+    }
+
+    #[test]
+    fn reference_skips_flat_sky_above() {
+        // Flat sky above row 60, fine texture below it
+        let img = RgbImage::from_fn(120, 120, |x, y| {
+            if y < 60 {
+                image::Rgb([200, 220, 255])
+            } else {
+                texture(x, y, 2, 60, 120)
+            }
+        });
+        let alpha_map = radial_alpha(48);
+        let gray = region_to_grayscale(&img, 60, 60, 48, 48);
+        let (region, _) = select_reference(&img, 60, 60, &gray, &alpha_map).unwrap();
+        assert_eq!(region, ReferenceRegion::Left);
+    }
+
+    #[test]
+    fn reference_matches_background_under_the_logo() {
+        // Coarse bright texture to the left, the region's fine dark texture
+        // everywhere else
+        let img = RgbImage::from_fn(120, 120, |x, y| {
+            if x < 50 {
+                texture(x, y, 8, 180, 250)
+            } else {
+                texture(x, y, 2, 40, 90)
+            }
+        });
+        let alpha_map = radial_alpha(48);
+        let gray = region_to_grayscale(&img, 60, 60, 48, 48);
+        let (region, _) = select_reference(&img, 60, 60, &gray, &alpha_map).unwrap();
+        assert_ne!(region, ReferenceRegion::Left);
+    }
+
+    #[test]
+    fn missing_reference_leaves_variance_out_of_confidence() {
+        // Logo on a flat background: no reference has texture
+        let mut img = RgbImage::from_pixel(100, 100, image::Rgb([90, 90, 90]));
+        let alpha_map = radial_alpha(48);
+        crate::blending::apply_watermark_alpha_blend(&mut img, &alpha_map, 40, 40, 255.0).unwrap();
+
+        let config = DetectionConfig::default();
+        let result = detect_watermark(&img, &alpha_map, 40, 40, 255.0, &config).unwrap();
+        assert_eq!(result.reference, None);
+        let without_variance = DetectionConfig {
+            variance_weight: 0.0,
+            ..config
+        };
+        let expected = without_variance.confidence(&result, true);
+        assert!((result.confidence - expected).abs() < 1e-6, "{result:?}");
+        assert!(result.detected);
+    }
 }
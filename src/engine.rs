@@ -1,52 +1,118 @@
 //! Core watermark removal engine.
 
+use std::borrow::Cow;
+use std::convert::Infallible;
+use std::fs::File;
+use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
 
-use image::{DynamicImage, ImageFormat, RgbImage};
+use image::{ImageFormat, RgbImage};
 
-use crate::alpha_maps;
-use crate::blending;
-use crate::detection::{self, DetectionResult};
+use crate::alpha_map::AlphaMap;
+use crate::alpha_map::ResizeFilter;
+use crate::batch::{self, BatchObserver, BatchSummary, CancellationToken, MemoryBudget};
+use crate::blending::{self, BlendTable};
+use crate::decode;
+use crate::detection::{self, DetectionConfig, DetectionResult};
 use crate::error::{Error, Result};
+use crate::profile::{LogoVersion, WatermarkProfile};
+use crate::region::RemovalRegion;
 
 /// Watermark size classification.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum WatermarkSize {
-    /// 48x48 watermark, 32px margin (images where either dimension <= 1024).
+    /// The profile's small logo (Gemini: 48x48, 32px margin, for images where
+    /// either dimension <= 1024).
     Small,
-    /// 96x96 watermark, 64px margin (images where both dimensions > 1024).
+    /// The profile's large logo (Gemini: 96x96, 64px margin, for images where
+    /// both dimensions > 1024).
     Large,
 }
 
 /// Options controlling watermark processing behavior.
 #[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct ProcessOptions {
     /// Skip watermark detection, process unconditionally.
     pub force: bool,
-    /// Detection confidence threshold (0.0-1.0).
-    pub threshold: f32,
+    /// Detection weights, circuit breaker and threshold.
+    pub detection: DetectionConfig,
     /// Force a specific watermark size instead of auto-detecting.
     pub force_size: Option<WatermarkSize>,
     /// Enable verbose logging.
     pub verbose: bool,
     /// Suppress non-error output.
     pub quiet: bool,
+    /// Back up an existing output file before overwriting it, appending this
+    /// suffix to its file name (e.g. `".bak"` turns `photo.jpg` into `photo.jpg.bak`).
+    pub backup_suffix: Option<String>,
+    /// Number of worker threads for batch processing (`None` uses all cores).
+    ///
+    /// Only takes effect when the `parallel` feature is enabled.
+    pub jobs: Option<usize>,
+    /// Upper bound in bytes on decoded image data held in memory at once by
+    /// batch workers (`None` is unbounded).
+    ///
+    /// Each file's cost is estimated from its header before decoding; workers
+    /// wait until enough of the budget is free.
+    pub memory_budget: Option<u64>,
+    /// Re-run detection on the cleaned image and report a residual watermark
+    /// as [`ProcessStatus::CleanedWithResidual`].
+    ///
+    /// Off by default: it runs detection twice more per file, on the cleaned
+    /// region and its inverse, and [`retry`](Self::retry) repeats removal and
+    /// detection for every candidate. The CLI turns it on unless
+    /// `--no-verify` is given.
+    pub verify: bool,
+    /// Residual confidence above which a cleaned image still counts as
+    /// watermarked (0.0-1.0).
+    pub residual_threshold: f32,
+    /// When verification finds a residual, retry with the other watermark
+    /// size, small position offsets and dimmer logo values, keeping the
+    /// attempt with the lowest residual. Only takes effect with `verify`.
+    pub retry: bool,
+    /// Write per-image debug artifacts (ROI crop, alpha overlay, Sobel maps,
+    /// before/after and amplified difference) into a subdirectory of this
+    /// directory that mirrors each input's path.
+    pub debug_dir: Option<PathBuf>,
 }
 
 impl Default for ProcessOptions {
     fn default() -> Self {
         Self {
             force: false,
-            threshold: 0.25,
+            detection: DetectionConfig::default(),
             force_size: None,
             verbose: false,
             quiet: false,
+            backup_suffix: None,
+            jobs: None,
+            memory_budget: None,
+            verify: false,
+            residual_threshold: 0.25,
+            retry: false,
+            debug_dir: None,
         }
     }
 }
 
+/// Outcome of processing a single image file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessStatus {
+    /// Watermark removed and saved; verification (if enabled) found no residual.
+    Cleaned,
+    /// Output saved, but the watermark is still detected in the cleaned image.
+    CleanedWithResidual,
+    /// Left untouched (no watermark detected or image too small).
+    Skipped,
+    /// Failed to load, process, or save.
+    Failed,
+}
+
 /// Result of processing a single image file.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ProcessResult {
     /// Path of the processed file.
     pub path: PathBuf,
@@ -54,125 +120,526 @@ pub struct ProcessResult {
     pub success: bool,
     /// Whether the file was skipped (no watermark detected).
     pub skipped: bool,
+    /// Overall outcome, including whether a residual watermark remains.
+    pub status: ProcessStatus,
     /// Detection confidence score.
     pub confidence: f32,
+    /// Detection confidence on the cleaned image (`None` when not verified).
+    pub residual: Option<f32>,
+    /// Name of the watermark profile used (empty if the batch failed before
+    /// reaching the file).
+    pub profile: String,
+    /// Id of the profile's logo version used (empty if the batch failed
+    /// before reaching the file).
+    pub version: String,
     /// Human-readable status message.
     pub message: String,
 }
 
-/// The watermark engine holding pre-computed alpha maps.
+/// The watermark engine: a registry of [`WatermarkProfile`]s and the
+/// detect/remove pipeline that runs them.
 ///
-/// Create once with [`WatermarkEngine::new()`] and reuse for multiple images.
-/// The engine decodes and caches the embedded alpha maps at initialization.
+/// A new engine knows the built-in Gemini profile; more can be registered
+/// with [`with_profile`](Self::with_profile). By default, detection and
+/// [`process_file`](Self::process_file) try every registered profile and use
+/// the best-scoring one; [`use_profile`](Self::use_profile) pins one instead.
+/// Size, position, [`remove`](Self::remove) and [`apply`](Self::apply) use
+/// the active profile (the first registered unless pinned).
+///
+/// The built-in profile's alpha maps and [`BlendTable`](crate::blending::BlendTable)s
+/// are decoded once per process and shared by every engine, so creating an
+/// engine after the first is free and cloning one only bumps reference
+/// counts. Engines are `Send + Sync` and can also be shared behind an `Arc`.
+#[derive(Clone)]
 pub struct WatermarkEngine {
-    alpha_map_small: Vec<f32>,
-    alpha_map_large: Vec<f32>,
-    logo_value: f32,
+    profiles: Arc<[Arc<WatermarkProfile>]>,
+    profile: Arc<WatermarkProfile>,
+    version: usize,
+    auto_profile: bool,
+    auto_version: bool,
+    region: Option<RemovalRegion>,
+}
+
+/// Process-wide Gemini profile, decoded by the first successful
+/// [`WatermarkEngine::new`].
+static GEMINI_PROFILE: OnceLock<Arc<WatermarkProfile>> = OnceLock::new();
+
+/// The shared Gemini profile, decoding it on first use.
+///
+/// A failed decode is not cached, so the error is returned again on the next
+/// call rather than poisoning the process.
+fn gemini_profile() -> Result<Arc<WatermarkProfile>> {
+    if let Some(profile) = GEMINI_PROFILE.get() {
+        return Ok(Arc::clone(profile));
+    }
+    let profile = Arc::new(WatermarkProfile::gemini()?);
+    Ok(Arc::clone(GEMINI_PROFILE.get_or_init(|| profile)))
 }
 
 impl WatermarkEngine {
-    /// Create a new engine from embedded PNG data.
+    /// Create a new engine with the built-in Gemini profile.
     ///
-    /// Decodes the 48x48 and 96x96 alpha maps and caches them for reuse.
+    /// The first call decodes the 48x48 and 96x96 alpha maps and their
+    /// reverse blending tables; later calls reuse them.
     ///
     /// # Errors
     ///
-    /// Returns [`Error::AlphaMapDecode`] if the embedded PNGs cannot be decoded.
+    /// Returns [`Error::AlphaMapDecode`] if the embedded PNGs cannot be
+    /// decoded, or [`Error::AlphaMapSize`] if they have unexpected dimensions
+    /// (should never happen unless the binary data is corrupted).
+    pub fn new() -> Result<Self> {
+        let gemini = gemini_profile()?;
+        Ok(Self {
+            profiles: Arc::from([Arc::clone(&gemini)]),
+            profile: gemini,
+            version: 0,
+            auto_profile: true,
+            auto_version: true,
+            region: None,
+        })
+    }
+
+    /// Register another watermark profile.
     ///
-    /// # Panics
+    /// # Errors
     ///
-    /// Panics if the embedded alpha map PNGs have unexpected dimensions (should never
-    /// happen unless the binary data is corrupted).
-    pub fn new() -> Result<Self> {
-        let (alpha_small, w48, h48) = blending::calculate_alpha_map(alpha_maps::BG_48_PNG)?;
-        assert_eq!(w48, 48, "Small alpha map must be 48x48");
-        assert_eq!(h48, 48, "Small alpha map must be 48x48");
+    /// Returns [`Error::Config`] if a profile with the same name is already
+    /// registered.
+    pub fn with_profile(self, profile: WatermarkProfile) -> Result<Self> {
+        if self.profiles.iter().any(|p| p.name() == profile.name()) {
+            return Err(Error::Config(format!(
+                "profile `{}` is already registered",
+                profile.name()
+            )));
+        }
+        let profiles: Vec<_> = self
+            .profiles
+            .iter()
+            .cloned()
+            .chain([Arc::new(profile)])
+            .collect();
+        Ok(Self {
+            profiles: profiles.into(),
+            ..self
+        })
+    }
 
-        let (alpha_large, w96, h96) = blending::calculate_alpha_map(alpha_maps::BG_96_PNG)?;
-        assert_eq!(w96, 96, "Large alpha map must be 96x96");
-        assert_eq!(h96, 96, "Large alpha map must be 96x96");
+    /// Add a logo version to the registered profile called `profile`, such
+    /// as an older Gemini logo.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Config`] if no profile has that name or it already
+    /// has a version with the same id.
+    pub fn with_logo_version(self, profile: &str, version: LogoVersion) -> Result<Self> {
+        let index = self.profile_index(profile)?;
+        let extended =
+            Arc::new(WatermarkProfile::clone(&self.profiles[index]).with_version(version)?);
+        let mut profiles = self.profiles.to_vec();
+        profiles[index] = Arc::clone(&extended);
+        let active = if self.profile.name() == profile {
+            extended
+        } else {
+            Arc::clone(&self.profile)
+        };
+        Ok(Self {
+            profiles: profiles.into(),
+            profile: active,
+            ..self
+        })
+    }
+
+    /// Register `profile`, or add its logo versions to the registered profile
+    /// of the same name. This is how a profile file named `gemini` adds older
+    /// Gemini logos to the built-in profile.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Config`] if the registered profile already has one of
+    /// the versions, or uses a different logo value or large-logo cutoff.
+    pub fn merge_profile(self, profile: WatermarkProfile) -> Result<Self> {
+        let Some(registered) = self.profiles.iter().find(|p| p.name() == profile.name()) else {
+            return self.with_profile(profile);
+        };
+        if registered.large_above() != profile.large_above()
+            || (registered.logo_value() - profile.logo_value()).abs() > f32::EPSILON
+        {
+            return Err(Error::Config(format!(
+                "profile `{}` is already registered with a different logo_value or large_above",
+                profile.name()
+            )));
+        }
+        profile
+            .versions()
+            .cloned()
+            .try_fold(self, |engine, version| {
+                engine.with_logo_version(profile.name(), version)
+            })
+    }
+
+    /// Index of the registered profile called `name`.
+    fn profile_index(&self, name: &str) -> Result<usize> {
+        self.profiles
+            .iter()
+            .position(|p| p.name() == name)
+            .ok_or_else(|| {
+                let known: Vec<_> = self.profiles.iter().map(|p| p.name()).collect();
+                Error::Config(format!(
+                    "unknown profile `{name}` (available: {})",
+                    known.join(", ")
+                ))
+            })
+    }
 
+    /// Pin the registered profile called `name`, turning off automatic
+    /// profile selection. Its logo versions are still selected automatically.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Config`] if no profile has that name.
+    pub fn use_profile(self, name: &str) -> Result<Self> {
+        let profile = &self.profiles[self.profile_index(name)?];
         Ok(Self {
-            alpha_map_small: alpha_small,
-            alpha_map_large: alpha_large,
-            logo_value: 255.0,
+            auto_version: true,
+            ..self.pinned(profile, 0)
         })
     }
 
+    /// Pin the active profile's logo version `id`, turning off automatic
+    /// profile and version selection.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Config`] if the active profile has no such version.
+    pub fn use_logo_version(self, id: &str) -> Result<Self> {
+        let Some(version) = self.profile.version_index(id) else {
+            let known: Vec<_> = self.profile.versions().map(LogoVersion::id).collect();
+            return Err(Error::Config(format!(
+                "profile `{}` has no logo version `{id}` (available: {})",
+                self.profile.name(),
+                known.join(", ")
+            )));
+        };
+        Ok(self.pinned(&self.profile, version))
+    }
+
+    /// Place the watermark at an explicit region instead of the profile's
+    /// position, for detection, removal and [`apply`](Self::apply). A region
+    /// size rescales the logo.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Config`] if the region's size is zero.
+    pub fn with_region(self, region: RemovalRegion) -> Result<Self> {
+        if region.size == Some(0) {
+            return Err(Error::Config(
+                "watermark size must be at least 1 pixel".to_string(),
+            ));
+        }
+        Ok(Self {
+            region: Some(region),
+            ..self
+        })
+    }
+
+    /// The explicit watermark region, if one is set.
+    #[must_use]
+    pub fn removal_region(&self) -> Option<&RemovalRegion> {
+        self.region.as_ref()
+    }
+
+    /// The registered profiles, in registration order.
+    pub fn profiles(&self) -> impl Iterator<Item = &WatermarkProfile> {
+        self.profiles.iter().map(AsRef::as_ref)
+    }
+
+    /// The active profile.
+    #[must_use]
+    pub fn profile(&self) -> &WatermarkProfile {
+        &self.profile
+    }
+
+    /// The active logo version of the active profile.
+    #[must_use]
+    pub fn logo_version(&self) -> &LogoVersion {
+        self.profile.version_at(self.version)
+    }
+
+    /// Whether detection picks the best-scoring profile per image.
+    #[must_use]
+    pub fn auto_selects_profile(&self) -> bool {
+        self.auto_profile && self.profiles.len() > 1
+    }
+
+    /// Whether detection tries more than one profile or logo version.
+    fn selects_automatically(&self) -> bool {
+        self.auto_selects_profile() || (self.auto_version && self.profile.version_count() > 1)
+    }
+
+    /// This engine with `profile` and its logo version `version` pinned.
+    fn pinned(&self, profile: &Arc<WatermarkProfile>, version: usize) -> Self {
+        Self {
+            profiles: Arc::clone(&self.profiles),
+            profile: Arc::clone(profile),
+            version,
+            auto_profile: false,
+            auto_version: false,
+            region: self.region,
+        }
+    }
+
+    /// The engines detection tries: one pinned to each candidate profile and
+    /// logo version in registration order, or just this engine.
+    fn candidates(&self) -> Vec<Self> {
+        if !self.selects_automatically() {
+            return vec![self.clone()];
+        }
+        let profiles = if self.auto_selects_profile() {
+            &self.profiles[..]
+        } else {
+            std::slice::from_ref(&self.profile)
+        };
+        profiles
+            .iter()
+            .flat_map(|profile| {
+                (0..profile.version_count()).map(move |version| self.pinned(profile, version))
+            })
+            .collect()
+    }
+
+    /// Run `detect` with each candidate profile and logo version and return
+    /// the engine pinned to the best-scoring one with its detection. Ties go
+    /// to the profile and version registered first.
+    fn select_profile<E>(
+        &self,
+        mut detect: impl FnMut(&Self) -> std::result::Result<DetectionResult, E>,
+    ) -> std::result::Result<(Self, DetectionResult), E> {
+        let mut best: Option<(Self, DetectionResult)> = None;
+        for engine in self.candidates() {
+            let detection = detect(&engine)?;
+            if best
+                .as_ref()
+                .is_none_or(|(_, b)| detection.confidence > b.confidence)
+            {
+                best = Some((engine, detection));
+            }
+        }
+        Ok(best.expect("an engine has at least one profile"))
+    }
+
+    /// Tag a detection with the active profile and logo version.
+    fn tagged(&self, mut detection: DetectionResult) -> DetectionResult {
+        detection.profile = Some(self.profile.name().to_string());
+        detection.version = Some(self.logo_version().id().to_string());
+        detection
+    }
+
     /// Determine watermark size based on image dimensions.
     ///
+    /// For the Gemini profile:
     /// - **Large** (96x96, 64px margin): both width AND height > 1024
     /// - **Small** (48x48, 32px margin): otherwise (including 1024x1024)
     #[must_use]
-    #[allow(clippy::unused_self)] // method on `self` for API consistency
     pub fn watermark_size_for(&self, width: u32, height: u32) -> WatermarkSize {
-        if width > 1024 && height > 1024 {
-            WatermarkSize::Large
-        } else {
-            WatermarkSize::Small
-        }
+        self.profile.size_for(width, height)
+    }
+
+    /// The active logo version's alpha map for a watermark size.
+    #[must_use]
+    pub fn alpha_map(&self, size: WatermarkSize) -> &AlphaMap {
+        self.logo_version().logo(size).alpha_map()
+    }
+
+    /// The active logo version's cached blend table for a watermark size.
+    pub(crate) fn blend_table(&self, size: WatermarkSize) -> &BlendTable {
+        self.profile.table(self.version, size)
     }
 
     /// Get watermark config (size, margin, `alpha_map`) for given dimensions.
-    fn config(
+    ///
+    /// With a [`RemovalRegion`] size, the alpha map is the logo rescaled to
+    /// that size.
+    pub(crate) fn config(
         &self,
         width: u32,
         height: u32,
         force_size: Option<WatermarkSize>,
-    ) -> (u32, u32, &[f32]) {
+    ) -> (u32, u32, Cow<'_, AlphaMap>) {
         let size = force_size.unwrap_or_else(|| self.watermark_size_for(width, height));
-        match size {
-            WatermarkSize::Small => (48, 32, &self.alpha_map_small),
-            WatermarkSize::Large => (96, 64, &self.alpha_map_large),
+        let logo = self.logo_version().logo(size);
+        match self.region.and_then(|region| region.size) {
+            Some(scaled) if scaled != logo.size() => {
+                // `with_region` rejects zero sizes, the only resize error
+                let alpha_map = logo
+                    .alpha_map()
+                    .resize(scaled, scaled, ResizeFilter::Bilinear)
+                    .map_or(Cow::Borrowed(logo.alpha_map()), Cow::Owned);
+                (scaled, logo.margin(), alpha_map)
+            }
+            _ => (logo.size(), logo.margin(), Cow::Borrowed(logo.alpha_map())),
         }
     }
 
-    /// Calculate watermark position (top-left corner of watermark region).
-    #[allow(clippy::unused_self)]
-    fn position(&self, img_w: u32, img_h: u32, wm_size: u32, margin: u32) -> (u32, u32) {
+    /// Calculate watermark position (top-left corner of watermark region),
+    /// from the [`RemovalRegion`] if one is set.
+    pub(crate) fn position(&self, img_w: u32, img_h: u32, wm_size: u32, margin: u32) -> (u32, u32) {
+        if let Some(region) = &self.region {
+            return region.position(img_w, img_h, wm_size);
+        }
         let x = img_w.saturating_sub(wm_size + margin);
         let y = img_h.saturating_sub(wm_size + margin);
         (x, y)
     }
 
+    /// Why a watermark of `wm_size` (at `margin`) cannot be placed in an
+    /// image, or `None` if it fits.
+    fn misfit(&self, img_w: u32, img_h: u32, wm_size: u32, margin: u32) -> Option<String> {
+        match &self.region {
+            Some(region) if !region.fits(img_w, img_h, wm_size) => Some(format!(
+                "Region {region} does not fit the {img_w}x{img_h} image for a {wm_size}x{wm_size} watermark"
+            )),
+            None if img_w < wm_size + margin || img_h < wm_size + margin => Some(format!(
+                "Image too small ({img_w}x{img_h}) for {wm_size}x{wm_size} watermark"
+            )),
+            _ => None,
+        }
+    }
+
     /// Detect watermark in an image.
     ///
     /// Returns a [`DetectionResult`] with confidence scores from the
-    /// three-stage detection algorithm.
+    /// multi-stage detection algorithm, scored with `opts.detection`. When
+    /// the engine selects profiles automatically, the result is that of the
+    /// best-scoring profile, named in [`DetectionResult::profile`].
     #[must_use]
     pub fn detect(&self, image: &RgbImage, opts: &ProcessOptions) -> DetectionResult {
+        self.select_for_image(image, opts).1
+    }
+
+    /// The engine pinned to the best-scoring profile for `image`, with its
+    /// detection.
+    fn select_for_image(&self, image: &RgbImage, opts: &ProcessOptions) -> (Self, DetectionResult) {
+        let Ok(selected) = self
+            .select_profile(|engine| Ok::<_, Infallible>(engine.detect_with_profile(image, opts)));
+        selected
+    }
+
+    /// [`detect`](Self::detect) with the active profile only.
+    fn detect_with_profile(&self, image: &RgbImage, opts: &ProcessOptions) -> DetectionResult {
         let (wm_size, margin, alpha_map) =
             self.config(image.width(), image.height(), opts.force_size);
         let (pos_x, pos_y) = self.position(image.width(), image.height(), wm_size, margin);
 
-        detection::detect_watermark(
+        // The position is derived from the image size, so it cannot overflow
+        let detection = detection::detect_watermark(
             image,
-            alpha_map,
-            wm_size,
-            wm_size,
+            &alpha_map,
             pos_x,
             pos_y,
-            opts.threshold,
+            self.profile.logo_value(),
+            &opts.detection,
         )
+        .unwrap_or_default();
+        self.tagged(detection)
+    }
+
+    /// The engine to process `image` with and, when profiles are selected
+    /// automatically, the detection that picked it.
+    fn engine_for(
+        &self,
+        image: &RgbImage,
+        opts: &ProcessOptions,
+    ) -> (Self, Option<DetectionResult>) {
+        if self.selects_automatically() {
+            let (engine, detection) = self.select_for_image(image, opts);
+            (engine, Some(detection))
+        } else {
+            (self.clone(), None)
+        }
+    }
+
+    /// Detect watermark in an image file without decoding the whole image.
+    ///
+    /// Only the watermark region and the context detection compares it
+    /// against (a watermark-sized strip above and to the left) are decoded,
+    /// via [`decode::decode_region`]. With several candidate profiles or logo
+    /// versions, the union of their regions is decoded once. The result is
+    /// the same as loading the file and calling [`detect`](Self::detect).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or decoded.
+    pub fn detect_file(&self, path: &Path, opts: &ProcessOptions) -> Result<DetectionResult> {
+        let (w, h) = image::image_dimensions(path)?;
+
+        // Every region reaches the bottom-right corner, so the union starts
+        // at the smallest top-left corner of their context
+        let origin = self
+            .candidates()
+            .iter()
+            .filter_map(|engine| engine.placement_in(w, h, opts))
+            .map(|(size, x, y, _)| (x.saturating_sub(size), y.saturating_sub(size)))
+            .reduce(|(ax, ay), (bx, by)| (ax.min(bx), ay.min(by)));
+        let Some((x0, y0)) = origin else {
+            return Ok(self.tagged(DetectionResult::default()));
+        };
+        let region = decode::decode_region(path, x0, y0, w - x0, h - y0)?;
+
+        self.select_profile(|engine| {
+            let Some((_, pos_x, pos_y, alpha_map)) = engine.placement_in(w, h, opts) else {
+                return Ok(engine.tagged(DetectionResult::default()));
+            };
+            let detection = detection::detect_watermark(
+                &region,
+                &alpha_map,
+                pos_x - x0,
+                pos_y - y0,
+                engine.profile.logo_value(),
+                &opts.detection,
+            )?;
+            Ok(engine.tagged(detection))
+        })
+        .map(|(_, detection)| detection)
+    }
+
+    /// Watermark size, top-left corner and alpha map in a `width` x `height`
+    /// image, or `None` if the region starts outside it (only an explicit
+    /// region can).
+    fn placement_in(
+        &self,
+        width: u32,
+        height: u32,
+        opts: &ProcessOptions,
+    ) -> Option<(u32, u32, u32, Cow<'_, AlphaMap>)> {
+        let (wm_size, margin, alpha_map) = self.config(width, height, opts.force_size);
+        let (pos_x, pos_y) = self.position(width, height, wm_size, margin);
+        (pos_x < width && pos_y < height).then_some((wm_size, pos_x, pos_y, alpha_map))
     }
 
     /// Remove watermark from an image in-place.
     ///
-    /// Applies reverse alpha blending at the expected watermark position.
-    /// The `force_size` parameter overrides automatic size detection.
+    /// Applies reverse alpha blending at the expected watermark position,
+    /// using the active profile's precomputed [`BlendTable`](crate::blending::BlendTable)
+    /// for the watermark size. The `force_size` parameter overrides automatic
+    /// size detection.
     pub fn remove(&self, image: &mut RgbImage, force_size: Option<WatermarkSize>) {
-        let (wm_size, margin, alpha_map) = self.config(image.width(), image.height(), force_size);
+        let size =
+            force_size.unwrap_or_else(|| self.watermark_size_for(image.width(), image.height()));
+        let (wm_size, margin, alpha_map) = self.config(image.width(), image.height(), Some(size));
         let (pos_x, pos_y) = self.position(image.width(), image.height(), wm_size, margin);
 
-        blending::remove_watermark_alpha_blend(
-            image,
-            alpha_map,
-            wm_size,
-            wm_size,
-            pos_x,
-            pos_y,
-            self.logo_value,
-        );
+        match alpha_map {
+            Cow::Borrowed(_) => self.blend_table(size).remove(image, pos_x, pos_y),
+            // A region overflowing `u32` coordinates has nothing to remove
+            Cow::Owned(alpha_map) => blending::remove_watermark_alpha_blend(
+                image,
+                &alpha_map,
+                pos_x,
+                pos_y,
+                self.profile.logo_value(),
+            )
+            .unwrap_or_default(),
+        }
     }
 
     /// Process a single image file: load, detect, remove, save.
@@ -189,7 +656,11 @@ impl WatermarkEngine {
             path: input.to_path_buf(),
             success: false,
             skipped: false,
+            status: ProcessStatus::Failed,
             confidence: 0.0,
+            residual: None,
+            profile: self.profile.name().to_string(),
+            version: self.logo_version().id().to_string(),
             message: String::new(),
         };
 
@@ -205,36 +676,61 @@ impl WatermarkEngine {
         let mut rgb_img = dyn_img.to_rgb8();
         let (w, h) = (rgb_img.width(), rgb_img.height());
 
-        // Check image is large enough
-        let (wm_size, margin, _) = self.config(w, h, opts.force_size);
-        if w < wm_size + margin || h < wm_size + margin {
+        let (engine, mut detection) = self.engine_for(&rgb_img, opts);
+        result.profile = engine.profile.name().to_string();
+        result.version = engine.logo_version().id().to_string();
+
+        // Check the watermark fits in the image
+        let (wm_size, margin, _) = engine.config(w, h, opts.force_size);
+        if let Some(message) = engine.misfit(w, h, wm_size, margin) {
             result.skipped = true;
             result.success = true;
-            result.message = format!("Image too small ({w}x{h}) for {wm_size}x{wm_size} watermark");
+            result.status = ProcessStatus::Skipped;
+            result.message = message;
             return result;
         }
 
         // Detection (unless forced)
         if !opts.force {
-            let detection = self.detect(&rgb_img, opts);
+            let detection = detection
+                .take()
+                .unwrap_or_else(|| engine.detect_with_profile(&rgb_img, opts));
             result.confidence = detection.confidence;
 
-            if !detection.detected && detection.confidence < opts.threshold {
+            if !detection.detected {
                 result.skipped = true;
                 result.success = true;
+                result.status = ProcessStatus::Skipped;
                 result.message = format!(
-                    "No watermark detected ({:.0}% confidence, spatial={:.2}, grad={:.2}, var={:.2})",
+                    "No watermark detected ({:.0}% confidence, spatial={:.2}, grad={:.2}, var={:.2}, color={:.2}, model={:.2})",
                     detection.confidence * 100.0,
                     detection.spatial_score,
                     detection.gradient_score,
                     detection.variance_score,
+                    detection.color_score,
+                    detection.model_score,
                 );
+                let size = opts
+                    .force_size
+                    .unwrap_or_else(|| engine.watermark_size_for(w, h));
+                let (x, y) = engine.position(w, h, wm_size, margin);
+                if let Some(note) =
+                    engine.write_debug_artifacts(opts, input, &rgb_img, None, (size, x, y))
+                {
+                    result.message = format!("{} ({note})", result.message);
+                }
                 return result;
             }
         }
 
-        // Remove watermark
-        self.remove(&mut rgb_img, opts.force_size);
+        // Remove watermark, verifying (and retrying) if requested
+        let before = opts.debug_dir.is_some().then(|| rgb_img.clone());
+        let verification = engine.remove_verified(&mut rgb_img, opts);
+        result.residual = verification.residual;
+        let debug_note = before.and_then(|before| {
+            let after = Some(&rgb_img);
+            engine.write_debug_artifacts(opts, input, &before, after, verification.placement)
+        });
 
         // Save output
         if let Some(parent) = output.parent() {
@@ -246,10 +742,26 @@ impl WatermarkEngine {
             }
         }
 
+        // Back up the file about to be overwritten (in-place mode)
+        if let Some(suffix) = &opts.backup_suffix {
+            if let Err(e) = create_backup(output, suffix) {
+                result.message = format!("Failed to create backup: {e}");
+                return result;
+            }
+        }
+
         match save_image(&rgb_img, output) {
             Ok(()) => {
                 result.success = true;
-                result.message = "Watermark removed".to_string();
+                result.status = if verification.has_residual(opts) {
+                    ProcessStatus::CleanedWithResidual
+                } else {
+                    ProcessStatus::Cleaned
+                };
+                result.message = verification.message();
+                if let Some(note) = debug_note {
+                    result.message = format!("{} ({note})", result.message);
+                }
             }
             Err(e) => {
                 result.message = format!("Failed to save: {e}");
@@ -261,12 +773,9 @@ impl WatermarkEngine {
 
     /// Process all supported images in a directory.
     ///
-    /// Uses parallel iteration when the `cli` feature is enabled (via rayon).
-    /// Returns a [`ProcessResult`] for each image found.
-    ///
-    /// # Panics
-    ///
-    /// Panics if any directory entry has no filename (should not happen for regular files).
+    /// Uses parallel iteration when the `parallel` feature is enabled (via rayon),
+    /// bounded by [`ProcessOptions::jobs`] and [`ProcessOptions::memory_budget`].
+    /// Returns a [`ProcessResult`] for each image found, in file name order.
     #[must_use]
     pub fn process_directory(
         &self,
@@ -274,65 +783,173 @@ impl WatermarkEngine {
         output_dir: &Path,
         opts: &ProcessOptions,
     ) -> Vec<ProcessResult> {
-        let entries: Vec<_> = match std::fs::read_dir(input_dir) {
+        self.process_directory_with(input_dir, output_dir, opts, &(), &CancellationToken::new())
+    }
+
+    /// Process all supported images in a directory, reporting progress.
+    ///
+    /// Like [`process_directory`](Self::process_directory), but notifies
+    /// `observer` as files start and finish, and stops picking up new files
+    /// once `cancel` is cancelled. Returns a [`ProcessResult`] for each file
+    /// that was processed; cancelled files are only counted in the
+    /// [`BatchSummary`] passed to [`BatchObserver::batch_finished`].
+    /// Directory-level failures are reported as a single failed result.
+    #[must_use]
+    pub fn process_directory_with(
+        &self,
+        input_dir: &Path,
+        output_dir: &Path,
+        opts: &ProcessOptions,
+        observer: &dyn BatchObserver,
+        cancel: &CancellationToken,
+    ) -> Vec<ProcessResult> {
+        let mut inputs: Vec<PathBuf> = match std::fs::read_dir(input_dir) {
             Ok(rd) => rd
                 .filter_map(std::result::Result::ok)
-                .filter(|e| e.file_type().map(|ft| ft.is_file()).unwrap_or(false))
-                .filter(|e| is_supported_image(e.path().as_path()))
+                .filter(|e| e.file_type().is_ok_and(|ft| ft.is_file()))
+                .map(|e| e.path())
+                .filter(|p| is_supported_image(p))
                 .collect(),
             Err(e) => {
-                return vec![ProcessResult {
-                    path: input_dir.to_path_buf(),
-                    success: false,
-                    skipped: false,
-                    confidence: 0.0,
-                    message: format!("Failed to read directory: {e}"),
-                }];
+                return fail_batch(
+                    observer,
+                    input_dir,
+                    format!("Failed to read directory: {e}"),
+                );
             }
         };
+        inputs.sort();
 
         // Create output directory
         if !output_dir.exists() {
             if let Err(e) = std::fs::create_dir_all(output_dir) {
-                return vec![ProcessResult {
-                    path: output_dir.to_path_buf(),
-                    success: false,
-                    skipped: false,
-                    confidence: 0.0,
-                    message: format!("Failed to create output directory: {e}"),
-                }];
+                return fail_batch(
+                    observer,
+                    output_dir,
+                    format!("Failed to create output directory: {e}"),
+                );
             }
         }
 
-        #[cfg(feature = "cli")]
-        {
+        let total = inputs.len();
+        let items = inputs.into_iter().map(|input| {
+            let output = output_dir.join(input.file_name().unwrap_or_default());
+            (input, output)
+        });
+        self.run_batch(items, Some(total), opts, observer, cancel)
+    }
+
+    /// Process a stream of `(input, output)` path pairs.
+    ///
+    /// Pairs are pulled from `items` lazily as workers become free, so the
+    /// iterator may be backed by an external job queue. Processing runs in
+    /// parallel when the `parallel` feature is enabled, bounded by
+    /// [`ProcessOptions::jobs`] and [`ProcessOptions::memory_budget`].
+    ///
+    /// `observer` is notified as files start and finish. Once `cancel` is
+    /// cancelled no further pairs are pulled from `items`. Returns a
+    /// [`ProcessResult`] for each processed pair, in the order the pairs were
+    /// yielded.
+    #[must_use]
+    pub fn process_iter<I>(
+        &self,
+        items: I,
+        opts: &ProcessOptions,
+        observer: &dyn BatchObserver,
+        cancel: &CancellationToken,
+    ) -> Vec<ProcessResult>
+    where
+        I: IntoIterator<Item = (PathBuf, PathBuf)>,
+        I::IntoIter: Send,
+    {
+        self.run_batch(items.into_iter(), None, opts, observer, cancel)
+    }
+
+    /// Shared batch driver for [`process_directory_with`](Self::process_directory_with)
+    /// and [`process_iter`](Self::process_iter).
+    fn run_batch<I>(
+        &self,
+        items: I,
+        total: Option<usize>,
+        opts: &ProcessOptions,
+        observer: &dyn BatchObserver,
+        cancel: &CancellationToken,
+    ) -> Vec<ProcessResult>
+    where
+        I: Iterator<Item = (PathBuf, PathBuf)> + Send,
+    {
+        observer.batch_started(total);
+
+        let budget = opts.memory_budget.map(MemoryBudget::new);
+        let pulled = AtomicUsize::new(0);
+
+        // Stop pulling new work as soon as the batch is cancelled
+        let items = items
+            .take_while(|_| !cancel.is_cancelled())
+            .inspect(|_| {
+                pulled.fetch_add(1, Ordering::Relaxed);
+            })
+            .enumerate();
+
+        let process_item = |(index, (input, output)): (usize, (PathBuf, PathBuf))| {
+            let _permit = budget
+                .as_ref()
+                .map(|b| b.acquire(batch::estimate_decoded_bytes(&input)));
+            if cancel.is_cancelled() {
+                return None;
+            }
+            observer.file_started(&input);
+            let result = self.process_file(&input, &output, opts);
+            observer.file_finished(&result);
+            Some((index, result))
+        };
+
+        #[cfg(feature = "parallel")]
+        let mut indexed: Vec<(usize, ProcessResult)> = {
             use rayon::prelude::*;
-            entries
-                .par_iter()
-                .map(|entry| {
-                    let input_path = entry.path();
-                    let filename = input_path.file_name().unwrap();
-                    let output_path = output_dir.join(filename);
-                    self.process_file(&input_path, &output_path, opts)
-                })
-                .collect()
-        }
+            let run = || items.par_bridge().filter_map(process_item).collect();
+            // Fall back to the global pool if a dedicated one cannot be built
+            match opts
+                .jobs
+                .map(|n| rayon::ThreadPoolBuilder::new().num_threads(n).build())
+            {
+                Some(Ok(pool)) => pool.install(run),
+                _ => run(),
+            }
+        };
 
-        #[cfg(not(feature = "cli"))]
-        {
-            entries
-                .iter()
-                .map(|entry| {
-                    let input_path = entry.path();
-                    let filename = input_path.file_name().unwrap();
-                    let output_path = output_dir.join(filename);
-                    self.process_file(&input_path, &output_path, opts)
-                })
-                .collect()
-        }
+        #[cfg(not(feature = "parallel"))]
+        let mut indexed: Vec<(usize, ProcessResult)> = items.filter_map(process_item).collect();
+
+        indexed.sort_unstable_by_key(|(index, _)| *index);
+        let results: Vec<ProcessResult> = indexed.into_iter().map(|(_, r)| r).collect();
+
+        let total = total.unwrap_or_else(|| pulled.load(Ordering::Relaxed));
+        observer.batch_finished(&BatchSummary::from_results(total, &results));
+        results
     }
 }
 
+/// Report a batch that failed before any file could be processed.
+fn fail_batch(observer: &dyn BatchObserver, path: &Path, message: String) -> Vec<ProcessResult> {
+    let result = ProcessResult {
+        path: path.to_path_buf(),
+        success: false,
+        skipped: false,
+        status: ProcessStatus::Failed,
+        confidence: 0.0,
+        residual: None,
+        profile: String::new(),
+        version: String::new(),
+        message,
+    };
+    observer.batch_started(Some(1));
+    observer.file_finished(&result);
+    let results = vec![result];
+    observer.batch_finished(&BatchSummary::from_results(1, &results));
+    results
+}
+
 /// Check if a file has a supported image extension.
 #[must_use]
 pub fn is_supported_image(path: &Path) -> bool {
@@ -347,6 +964,11 @@ pub fn is_supported_image(path: &Path) -> bool {
 
 /// Save an RGB image with format-specific quality settings.
 ///
+/// The image is encoded into a temporary file in the destination directory and
+/// atomically renamed over `path`, so a crash or encoding error never leaves a
+/// truncated file behind. This makes it safe to overwrite the input in-place.
+/// A replaced file's permissions carry over to the new one.
+///
 /// # Errors
 ///
 /// Returns an error if the format is unsupported or writing fails.
@@ -354,25 +976,98 @@ pub fn save_image(img: &RgbImage, path: &Path) -> Result<()> {
     let format =
         ImageFormat::from_path(path).map_err(|e| Error::UnsupportedFormat(e.to_string()))?;
 
-    let dyn_img = DynamicImage::ImageRgb8(img.clone());
+    if !matches!(
+        format,
+        ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP | ImageFormat::Bmp
+    ) {
+        return Err(Error::UnsupportedFormat(format!("{format:?}")));
+    }
 
-    match format {
-        ImageFormat::Jpeg => {
-            let file = std::fs::File::create(path)?;
-            let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(file, 100);
-            encoder.encode_image(&dyn_img)?;
-        }
-        ImageFormat::Png | ImageFormat::WebP | ImageFormat::Bmp => {
-            dyn_img.save(path)?;
-        }
-        _ => {
-            return Err(Error::UnsupportedFormat(format!("{format:?}")));
-        }
+    let tmp_path = temp_path_for(path);
+    let written = write_encoded(img, &tmp_path, format)
+        .and_then(|()| copy_permissions(path, &tmp_path))
+        .and_then(|()| std::fs::rename(&tmp_path, path).map_err(Error::from));
+    if written.is_err() {
+        std::fs::remove_file(&tmp_path).ok();
+    }
+    written
+}
+
+/// Encode `img` as `format` into a new file at `path` and flush it to disk.
+fn write_encoded(img: &RgbImage, path: &Path, format: ImageFormat) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    if format == ImageFormat::Jpeg {
+        let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut writer, 100);
+        encoder.encode_image(img)?;
+    } else {
+        img.write_to(&mut writer, format)?;
+    }
+
+    writer.flush()?;
+    writer
+        .into_inner()
+        .map_err(std::io::IntoInnerError::into_error)?
+        .sync_all()?;
+    Ok(())
+}
+
+/// Give `to` the permissions of the file at `from`, if there is one.
+fn copy_permissions(from: &Path, to: &Path) -> Result<()> {
+    match std::fs::metadata(from) {
+        Ok(meta) => Ok(std::fs::set_permissions(to, meta.permissions())?),
+        // Nothing is being replaced
+        Err(_) => Ok(()),
     }
+}
+
+/// Temporary sibling path used while writing `path`.
+///
+/// Lives in the same directory so the final rename never crosses filesystems.
+/// The name is unique per call, so threads saving the same path never share
+/// a temporary file.
+fn temp_path_for(path: &Path) -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    path.with_file_name(format!(".{name}.{}.{n}.tmp", std::process::id()))
+}
+
+/// Path of the backup for `path` with the given suffix.
+///
+/// Example: `"photo.jpg"` with suffix `".bak"` becomes `"photo.jpg.bak"`.
+#[must_use]
+pub fn backup_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
 
+/// Copy `path` to its backup location before it gets overwritten.
+///
+/// Does nothing if `path` does not exist yet. An existing backup is never
+/// replaced, so processing the same file twice keeps the true original.
+fn create_backup(path: &Path, suffix: &str) -> Result<()> {
+    let backup = backup_path(path, suffix);
+    if path.exists() && !backup.exists() {
+        std::fs::copy(path, &backup)?;
+    }
     Ok(())
 }
 
+/// Restore `path` from its backup, removing the backup file.
+///
+/// Returns the path of the backup that was restored.
+///
+/// # Errors
+///
+/// Returns [`Error::Io`] if the backup does not exist or cannot be renamed.
+pub fn restore_backup(path: &Path, suffix: &str) -> Result<PathBuf> {
+    let backup = backup_path(path, suffix);
+    std::fs::rename(&backup, path)?;
+    Ok(backup)
+}
+
 /// Generate a default output path from an input path.
 ///
 /// Example: `"photo.jpg"` becomes `"photo_cleaned.jpg"`.
@@ -388,6 +1083,276 @@ pub fn default_output_path(input: &Path) -> PathBuf {
 mod tests {
     use super::*;
 
+    #[test]
+    fn engines_share_decoded_assets() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<WatermarkEngine>();
+
+        let a = WatermarkEngine::new().unwrap();
+        let b = WatermarkEngine::new().unwrap();
+        assert!(Arc::ptr_eq(&a.profile, &b.profile));
+        assert!(Arc::ptr_eq(&a.profile, &a.clone().profile));
+        assert_eq!(a.alpha_map(WatermarkSize::Large).width(), 96);
+        assert!(std::ptr::eq(
+            a.alpha_map(WatermarkSize::Small),
+            b.alpha_map(WatermarkSize::Small)
+        ));
+    }
+
+    /// A profile with a ring logo, unlike the Gemini sparkle.
+    fn ring_profile(name: &str) -> WatermarkProfile {
+        WatermarkProfile::with_primary(name, ring_version("v1"), 255.0).unwrap()
+    }
+
+    fn ring_version(id: &str) -> LogoVersion {
+        let logo = |size: u32| {
+            #[allow(clippy::cast_precision_loss)]
+            let half = size as f32 / 2.0;
+            let values = (0..size * size)
+                .map(|i| {
+                    #[allow(clippy::cast_precision_loss)]
+                    let (dx, dy) = ((i % size) as f32 - half, (i / size) as f32 - half);
+                    let r = (dx * dx + dy * dy).sqrt() / half;
+                    if (0.5..0.9).contains(&r) {
+                        0.5
+                    } else {
+                        0.0
+                    }
+                })
+                .collect();
+            let alpha_map = AlphaMap::new(values, size, size).unwrap();
+            crate::profile::LogoSpec::new(alpha_map, 20).unwrap()
+        };
+        LogoVersion::new(id, logo(40), logo(80)).unwrap()
+    }
+
+    /// Clean 400x300 image with enough texture for detection.
+    fn textured_400x300() -> RgbImage {
+        #[allow(clippy::cast_possible_truncation)]
+        RgbImage::from_fn(400, 300, |x, y| {
+            image::Rgb([(x % 200) as u8, (y % 200) as u8, ((x + y) % 150) as u8])
+        })
+    }
+
+    /// Stamp `stamper`'s logo onto [`textured_400x300`] and save it to `path`.
+    fn stamp_and_save(
+        stamper: &WatermarkEngine,
+        opts: &crate::SynthesisOptions,
+        path: &Path,
+    ) -> RgbImage {
+        let mut img = textured_400x300();
+        stamper.apply(&mut img, opts).unwrap();
+        img.save(path).unwrap();
+        img
+    }
+
+    #[test]
+    fn explicit_region_moves_and_scales_the_watermark() {
+        let dir = std::env::temp_dir().join("gwr_test_pf_region");
+        std::fs::create_dir_all(&dir).unwrap();
+        let engine = WatermarkEngine::new().unwrap();
+        let region = RemovalRegion::at(60, 40).with_size(64);
+        let placed = engine.clone().with_region(region).unwrap();
+        assert_eq!(placed.removal_region(), Some(&region));
+        assert!(engine.clone().with_region(region.with_size(0)).is_err());
+
+        let original = textured_400x300();
+        let input = dir.join("placed.png");
+        let img = stamp_and_save(&placed, &crate::SynthesisOptions::default(), &input);
+        assert_ne!(img.get_pixel(92, 72), original.get_pixel(92, 72));
+        let output = dir.join("placed_out.png");
+        let opts = ProcessOptions::default();
+
+        let result = engine.process_file(&input, &output, &opts);
+        assert_eq!(result.status, ProcessStatus::Skipped, "{}", result.message);
+
+        let detection = placed.detect_file(&input, &opts).unwrap();
+        assert!(detection.detected, "{detection:?}");
+        let result = placed.process_file(&input, &output, &opts);
+        assert_eq!(result.status, ProcessStatus::Cleaned, "{}", result.message);
+        let cleaned = image::open(&output).unwrap().to_rgb8();
+        let max_diff = cleaned
+            .pixels()
+            .zip(original.pixels())
+            .flat_map(|(a, b)| a.0.iter().zip(b.0).map(|(&a, b)| a.abs_diff(b)))
+            .max()
+            .unwrap();
+        assert!(max_diff <= 3, "max diff {max_diff}");
+
+        let outside = engine.with_region(RemovalRegion::at(380, 10)).unwrap();
+        let result = outside.process_file(&input, &output, &opts);
+        assert_eq!(result.status, ProcessStatus::Skipped);
+        assert!(result.message.contains("tl:380,10"), "{}", result.message);
+        assert!(!outside.detect_file(&input, &opts).unwrap().detected);
+        let far = outside.with_region(RemovalRegion::at(500, 10)).unwrap();
+        assert!(!far.detect_file(&input, &opts).unwrap().detected);
+
+        // A retry reports the region's logo size, not the profile's
+        let nudge = crate::SynthesisOptions {
+            offset: (2, -2),
+            ..crate::SynthesisOptions::default()
+        };
+        stamp_and_save(&placed, &nudge, &input);
+        let retry = ProcessOptions {
+            verify: true,
+            retry: true,
+            ..ProcessOptions::default()
+        };
+        let result = placed.process_file(&input, &output, &retry);
+        assert_eq!(result.status, ProcessStatus::Cleaned, "{}", result.message);
+        assert!(
+            result.message.contains("retry: 64x64"),
+            "{}",
+            result.message
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn detection_picks_the_stamped_logo_version() {
+        let dir = std::env::temp_dir().join("gwr_test_pf_versions");
+        std::fs::create_dir_all(&dir).unwrap();
+        let engine = WatermarkEngine::new()
+            .unwrap()
+            .with_logo_version("gemini", ring_version("2019"))
+            .unwrap();
+        assert!(!engine.auto_selects_profile());
+        let err = engine
+            .clone()
+            .with_logo_version("gemini", ring_version("2019"))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("already has"), "{err}");
+        let err = engine.clone().use_logo_version("2018").err().unwrap();
+        assert!(err.to_string().contains("available: v1, 2019"), "{err}");
+        let opts = ProcessOptions::default();
+
+        for version in ["v1", "2019"] {
+            let stamper = engine.clone().use_logo_version(version).unwrap();
+            assert_eq!(stamper.logo_version().id(), version);
+            let input = dir.join(format!("{version}.png"));
+            let img = stamp_and_save(&stamper, &crate::SynthesisOptions::default(), &input);
+
+            let detection = engine.detect(&img, &opts);
+            assert!(detection.detected, "{version}: {detection:?}");
+            assert_eq!(detection.profile.as_deref(), Some("gemini"));
+            assert_eq!(detection.version.as_deref(), Some(version));
+            // The versions' regions differ; one decode of their union serves all
+            let from_file = engine.detect_file(&input, &opts).unwrap();
+            assert_eq!(from_file.version, detection.version);
+            assert!((from_file.confidence - detection.confidence).abs() < 1e-6);
+
+            let output = dir.join(format!("{version}_out.png"));
+            let result = engine.process_file(&input, &output, &opts);
+            assert_eq!(result.status, ProcessStatus::Cleaned, "{}", result.message);
+            assert_eq!(result.version, version);
+        }
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn profiles_with_a_dim_logo_are_detected() {
+        let ring = WatermarkProfile::with_primary("dim", ring_version("v1"), 150.0).unwrap();
+        let engine = WatermarkEngine::new().unwrap().with_profile(ring).unwrap();
+        #[allow(clippy::cast_possible_truncation)]
+        let mut img = RgbImage::from_fn(400, 300, |x, y| {
+            image::Rgb([(x % 80) as u8, (y % 90) as u8, ((x + y) % 70) as u8])
+        });
+        engine
+            .clone()
+            .use_profile("dim")
+            .unwrap()
+            .apply(&mut img, &crate::SynthesisOptions::default())
+            .unwrap();
+
+        let detection = engine.detect(&img, &ProcessOptions::default());
+        assert!(detection.detected, "{detection:?}");
+        assert_eq!(detection.profile.as_deref(), Some("dim"));
+        assert!(detection.model_score > 0.9, "{detection:?}");
+        assert!(detection.color_score > 0.5, "{detection:?}");
+    }
+
+    #[test]
+    fn merge_profile_adds_versions_to_a_registered_profile() {
+        let engine = WatermarkEngine::new()
+            .unwrap()
+            .merge_profile(ring_profile("ring"))
+            .unwrap();
+        assert!(engine.auto_selects_profile());
+
+        let older = WatermarkProfile::with_primary("gemini", ring_version("2019"), 255.0).unwrap();
+        let engine = engine.merge_profile(older.clone()).unwrap();
+        let ids: Vec<_> = engine
+            .profiles()
+            .next()
+            .unwrap()
+            .versions()
+            .map(LogoVersion::id)
+            .collect();
+        assert_eq!(ids, ["v1", "2019"]);
+        let pinned = engine.clone().use_logo_version("2019").unwrap();
+        assert_eq!(pinned.config(800, 600, None).0, 40);
+
+        let err = engine.clone().merge_profile(older).err().unwrap();
+        assert!(err.to_string().contains("already has"), "{err}");
+        let dimmer = WatermarkProfile::with_primary("gemini", ring_version("2018"), 200.0).unwrap();
+        let err = engine.merge_profile(dimmer).err().unwrap();
+        assert!(err.to_string().contains("logo_value"), "{err}");
+    }
+
+    #[test]
+    fn profile_registry_rejects_duplicates_and_unknown_names() {
+        let engine = WatermarkEngine::new().unwrap();
+        assert!(!engine.auto_selects_profile());
+        let err = engine
+            .clone()
+            .with_profile(WatermarkProfile::gemini().unwrap())
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("already registered"), "{err}");
+
+        let engine = engine.with_profile(ring_profile("ring")).unwrap();
+        assert!(engine.auto_selects_profile());
+        let names: Vec<_> = engine.profiles().map(WatermarkProfile::name).collect();
+        assert_eq!(names, ["gemini", "ring"]);
+
+        let err = engine.clone().use_profile("acme").err().unwrap();
+        assert!(err.to_string().contains("available: gemini, ring"), "{err}");
+        let pinned = engine.use_profile("ring").unwrap();
+        assert!(!pinned.auto_selects_profile());
+        assert_eq!(pinned.profile().name(), "ring");
+        assert_eq!(pinned.config(800, 600, None).0, 40);
+    }
+
+    #[test]
+    fn auto_selection_picks_the_stamped_profile() {
+        let dir = std::env::temp_dir().join("gwr_test_pf_profiles");
+        std::fs::create_dir_all(&dir).unwrap();
+        let engine = WatermarkEngine::new()
+            .unwrap()
+            .with_profile(ring_profile("ring"))
+            .unwrap();
+        let opts = ProcessOptions::default();
+
+        for name in ["gemini", "ring"] {
+            let stamper = engine.clone().use_profile(name).unwrap();
+            let input = dir.join(format!("{name}.png"));
+            let img = stamp_and_save(&stamper, &crate::SynthesisOptions::default(), &input);
+
+            let detection = engine.detect(&img, &opts);
+            assert!(detection.detected, "{name}: {detection:?}");
+            assert_eq!(detection.profile.as_deref(), Some(name));
+
+            let result = engine.process_file(&input, &dir.join(format!("{name}_out.png")), &opts);
+            assert_eq!(result.status, ProcessStatus::Cleaned, "{}", result.message);
+            assert_eq!(result.profile, name);
+        }
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
     #[test]
     fn watermark_size_small_when_either_dim_lte_1024() {
         let engine = WatermarkEngine::new().unwrap();
@@ -482,6 +1447,55 @@ mod tests {
         std::fs::remove_dir_all(&dir).ok();
     }
 
+    #[test]
+    fn save_image_replaces_existing_file_without_leftovers() {
+        let dir = std::env::temp_dir().join("gwr_test_save_atomic");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.png");
+        std::fs::write(&path, "old contents").unwrap();
+
+        let img = RgbImage::new(10, 10);
+        save_image(&img, &path).unwrap();
+
+        assert_eq!(image::open(&path).unwrap().to_rgb8(), img);
+        let entries = std::fs::read_dir(&dir).unwrap().count();
+        assert_eq!(entries, 1, "Temporary file should be renamed away");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn temp_paths_are_unique_per_call() {
+        let path = Path::new("/tmp/photo.jpg");
+        let (a, b) = (temp_path_for(path), temp_path_for(path));
+        assert_ne!(a, b);
+        assert_eq!(a.parent(), path.parent());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn save_image_keeps_permissions_of_the_replaced_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join("gwr_test_save_permissions");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.png");
+        std::fs::write(&path, "old contents").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+
+        save_image(&RgbImage::new(10, 10), &path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn backup_path_appends_suffix() {
+        let p = backup_path(Path::new("/tmp/photo.jpg"), ".bak");
+        assert_eq!(p, PathBuf::from("/tmp/photo.jpg.bak"));
+    }
+
     /// Helper: create a small PNG test image in the given directory.
     fn create_test_png(dir: &Path, name: &str, w: u32, h: u32) -> PathBuf {
         let path = dir.join(name);
@@ -490,6 +1504,39 @@ mod tests {
         path
     }
 
+    #[test]
+    fn detect_file_matches_full_image_detection() {
+        let dir = std::env::temp_dir().join("gwr_test_detect_file");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let engine = WatermarkEngine::new().unwrap();
+        #[allow(clippy::cast_possible_truncation)]
+        let mut img = RgbImage::from_fn(1200, 1100, |x, y| {
+            image::Rgb([(x % 200) as u8, (y % 180) as u8, ((x + y) % 150) as u8])
+        });
+        engine
+            .apply(&mut img, &crate::SynthesisOptions::default())
+            .unwrap();
+        let opts = ProcessOptions::default();
+        let expected = engine.detect(&img, &opts);
+        assert!(expected.detected);
+
+        for name in ["stamped.png", "stamped.bmp"] {
+            let path = dir.join(name);
+            img.save(&path).unwrap();
+            let result = engine.detect_file(&path, &opts).unwrap();
+            assert!(result.detected, "{name}");
+            assert!(
+                (result.confidence - expected.confidence).abs() < 1e-6,
+                "{name}"
+            );
+            assert_eq!(result.reference, expected.reference, "{name}");
+        }
+        assert!(engine.detect_file(&dir.join("missing.png"), &opts).is_err());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
     #[test]
     fn process_file_on_valid_image() {
         let dir = std::env::temp_dir().join("gwr_test_pf_valid");
@@ -573,6 +1620,140 @@ mod tests {
         std::fs::remove_dir_all(&dir).ok();
     }
 
+    #[test]
+    fn process_file_reports_residual_and_retries() {
+        let dir = std::env::temp_dir().join("gwr_test_pf_residual");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let engine = WatermarkEngine::new().unwrap();
+        #[allow(clippy::cast_possible_truncation)]
+        let mut img = RgbImage::from_fn(400, 300, |x, y| {
+            image::Rgb([(x % 200) as u8, (y % 200) as u8, ((x + y) % 150) as u8])
+        });
+        let shifted = crate::SynthesisOptions {
+            offset: (2, -2),
+            ..crate::SynthesisOptions::default()
+        };
+        engine.apply(&mut img, &shifted).unwrap();
+        let input = dir.join("shifted.png");
+        img.save(&input).unwrap();
+        let output = dir.join("out.png");
+
+        let verify = ProcessOptions {
+            verify: true,
+            ..ProcessOptions::default()
+        };
+        let result = engine.process_file(&input, &output, &verify);
+        assert!(result.success);
+        assert_eq!(result.status, ProcessStatus::CleanedWithResidual);
+        assert!(result.residual.unwrap() > 0.25);
+
+        let opts = ProcessOptions {
+            retry: true,
+            ..verify
+        };
+        let result = engine.process_file(&input, &output, &opts);
+        assert_eq!(result.status, ProcessStatus::Cleaned, "{}", result.message);
+        assert!(result.message.contains("offset +2,-2"));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn process_file_writes_debug_artifacts() {
+        let dir = std::env::temp_dir().join("gwr_test_pf_debug");
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        let cleaned = create_test_png(&dir, "clean.png", 200, 200);
+
+        let engine = WatermarkEngine::new().unwrap();
+        let mut img = RgbImage::from_pixel(200, 200, image::Rgb([90, 110, 130]));
+        engine
+            .apply(&mut img, &crate::SynthesisOptions::default())
+            .unwrap();
+        let stamped = dir.join("stamped.png");
+        img.save(&stamped).unwrap();
+
+        let debug_dir = dir.join("debug");
+        let opts = ProcessOptions {
+            debug_dir: Some(debug_dir.clone()),
+            ..ProcessOptions::default()
+        };
+        let artifacts = |input: &Path| crate::debug::artifact_dir(&debug_dir, input);
+
+        let result = engine.process_file(&stamped, &dir.join("out.png"), &opts);
+        assert_eq!(result.status, ProcessStatus::Cleaned, "{}", result.message);
+        for name in [
+            "roi.png",
+            "overlay.png",
+            "sobel_roi.png",
+            "sobel_alpha.png",
+            "before_after.png",
+            "diff.png",
+        ] {
+            assert!(artifacts(&stamped).join(name).exists(), "{name}");
+        }
+        let pair = image::open(artifacts(&stamped).join("before_after.png")).unwrap();
+        assert_eq!((pair.width(), pair.height()), (96, 48));
+
+        // Skipped files get the detection artifacts only
+        let result = engine.process_file(&cleaned, &dir.join("out2.png"), &opts);
+        assert!(result.skipped);
+        assert!(artifacts(&cleaned).join("roi.png").exists());
+        assert!(!artifacts(&cleaned).join("diff.png").exists());
+
+        // A file with the same name in another folder gets its own artifacts
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        let other = create_test_png(&dir.join("sub"), "stamped.png", 200, 200);
+        let result = engine.process_file(&other, &dir.join("out3.png"), &opts);
+        assert!(result.skipped);
+        assert_ne!(artifacts(&other), artifacts(&stamped));
+        assert!(!artifacts(&other).join("diff.png").exists());
+        assert!(artifacts(&stamped).join("diff.png").exists());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn process_file_in_place_backs_up_and_restores() {
+        let dir = std::env::temp_dir().join("gwr_test_pf_in_place");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let input = dir.join("photo.png");
+        let mut original = RgbImage::new(200, 200);
+        for px in original.pixels_mut() {
+            *px = image::Rgb([90, 120, 150]);
+        }
+        original.save(&input).unwrap();
+        let original_bytes = std::fs::read(&input).unwrap();
+
+        let engine = WatermarkEngine::new().unwrap();
+        let opts = ProcessOptions {
+            force: true,
+            backup_suffix: Some(".bak".to_string()),
+            ..ProcessOptions::default()
+        };
+        let result = engine.process_file(&input, &input, &opts);
+        assert!(result.success, "{}", result.message);
+
+        let backup = backup_path(&input, ".bak");
+        assert_eq!(std::fs::read(&backup).unwrap(), original_bytes);
+        assert_ne!(image::open(&input).unwrap().to_rgb8(), original);
+
+        let restored_from = restore_backup(&input, ".bak").unwrap();
+        assert_eq!(restored_from, backup);
+        assert!(!backup.exists());
+        assert_eq!(image::open(&input).unwrap().to_rgb8(), original);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn restore_backup_fails_without_backup() {
+        let result = restore_backup(Path::new("/tmp/gwr_no_backup_here.png"), ".bak");
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
+
     #[test]
     fn process_directory_with_mixed_files() {
         let dir = std::env::temp_dir().join("gwr_test_pd_mixed");
@@ -600,6 +1781,203 @@ mod tests {
         std::fs::remove_dir_all(&out_dir).ok();
     }
 
+    #[test]
+    fn process_directory_with_reports_progress() {
+        use std::sync::Mutex;
+
+        #[derive(Default)]
+        struct Recorder {
+            started: Mutex<Vec<PathBuf>>,
+            finished: Mutex<Vec<PathBuf>>,
+            summary: Mutex<Option<BatchSummary>>,
+        }
+
+        impl BatchObserver for Recorder {
+            fn file_started(&self, path: &Path) {
+                self.started.lock().unwrap().push(path.to_path_buf());
+            }
+            fn file_finished(&self, result: &ProcessResult) {
+                self.finished.lock().unwrap().push(result.path.clone());
+            }
+            fn batch_finished(&self, summary: &BatchSummary) {
+                *self.summary.lock().unwrap() = Some(*summary);
+            }
+        }
+
+        let dir = std::env::temp_dir().join("gwr_test_pd_progress");
+        let out_dir = std::env::temp_dir().join("gwr_test_pd_progress_out");
+        std::fs::create_dir_all(&dir).unwrap();
+        create_test_png(&dir, "a.png", 200, 200);
+        create_test_png(&dir, "b.png", 200, 200);
+
+        let engine = WatermarkEngine::new().unwrap();
+        let opts = ProcessOptions {
+            force: true,
+            ..ProcessOptions::default()
+        };
+        let recorder = Recorder::default();
+        let results = engine.process_directory_with(
+            &dir,
+            &out_dir,
+            &opts,
+            &recorder,
+            &CancellationToken::new(),
+        );
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(recorder.started.lock().unwrap().len(), 2);
+        assert_eq!(recorder.finished.lock().unwrap().len(), 2);
+        let summary = recorder.summary.lock().unwrap().unwrap();
+        assert_eq!(summary.total, 2);
+        assert_eq!(summary.succeeded, 2);
+
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::remove_dir_all(&out_dir).ok();
+    }
+
+    #[test]
+    fn process_directory_with_jobs_and_memory_budget() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        /// Tracks how many files are between `file_started` and `file_finished`.
+        #[derive(Default)]
+        struct InFlight {
+            current: AtomicUsize,
+            peak: AtomicUsize,
+        }
+
+        impl BatchObserver for InFlight {
+            fn file_started(&self, _path: &Path) {
+                let now = self.current.fetch_add(1, Ordering::SeqCst) + 1;
+                self.peak.fetch_max(now, Ordering::SeqCst);
+            }
+            fn file_finished(&self, _result: &ProcessResult) {
+                self.current.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+
+        let dir = std::env::temp_dir().join("gwr_test_pd_budget");
+        let out_dir = std::env::temp_dir().join("gwr_test_pd_budget_out");
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["a.png", "b.png", "c.png", "d.png", "e.png", "f.png"] {
+            create_test_png(&dir, name, 200, 200);
+        }
+        let image_bytes = batch::estimate_decoded_bytes(&dir.join("a.png"));
+
+        let engine = WatermarkEngine::new().unwrap();
+        // Smaller than a single image: files must run one at a time; then
+        // room for exactly two images
+        for (budget, jobs, max_in_flight) in [(1024, 2, 1), (2 * image_bytes, 4, 2)] {
+            let opts = ProcessOptions {
+                force: true,
+                jobs: Some(jobs),
+                memory_budget: Some(budget),
+                ..ProcessOptions::default()
+            };
+            let in_flight = InFlight::default();
+            let results = engine.process_directory_with(
+                &dir,
+                &out_dir,
+                &opts,
+                &in_flight,
+                &CancellationToken::new(),
+            );
+
+            assert_eq!(results.len(), 6);
+            assert!(results.iter().all(|r| r.success));
+            let peak = in_flight.peak.load(Ordering::SeqCst);
+            assert!(
+                (1..=max_in_flight).contains(&peak),
+                "budget {budget}: {peak}"
+            );
+        }
+
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::remove_dir_all(&out_dir).ok();
+    }
+
+    #[test]
+    fn process_directory_with_stops_when_cancelled() {
+        let dir = std::env::temp_dir().join("gwr_test_pd_cancel");
+        let out_dir = std::env::temp_dir().join("gwr_test_pd_cancel_out");
+        std::fs::create_dir_all(&dir).unwrap();
+        create_test_png(&dir, "a.png", 200, 200);
+        create_test_png(&dir, "b.png", 200, 200);
+
+        let engine = WatermarkEngine::new().unwrap();
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let results =
+            engine.process_directory_with(&dir, &out_dir, &ProcessOptions::default(), &(), &cancel);
+
+        assert!(
+            results.is_empty(),
+            "No file should start after cancellation"
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::remove_dir_all(&out_dir).ok();
+    }
+
+    #[test]
+    fn process_iter_preserves_input_order() {
+        let dir = std::env::temp_dir().join("gwr_test_pi_order");
+        std::fs::create_dir_all(&dir).unwrap();
+        let pairs: Vec<(PathBuf, PathBuf)> = (0..4)
+            .map(|i| {
+                let input = create_test_png(&dir, &format!("in{i}.png"), 200, 200);
+                (input, dir.join(format!("out{i}.png")))
+            })
+            .collect();
+        let expected: Vec<PathBuf> = pairs.iter().map(|(input, _)| input.clone()).collect();
+
+        let engine = WatermarkEngine::new().unwrap();
+        let opts = ProcessOptions {
+            force: true,
+            ..ProcessOptions::default()
+        };
+        let results = engine.process_iter(pairs, &opts, &(), &CancellationToken::new());
+
+        let paths: Vec<PathBuf> = results.iter().map(|r| r.path.clone()).collect();
+        assert_eq!(paths, expected);
+        assert!(dir.join("out3.png").exists());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn process_iter_stops_pulling_after_cancel() {
+        let dir = std::env::temp_dir().join("gwr_test_pi_cancel");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = create_test_png(&dir, "in.png", 200, 200);
+
+        let cancel = CancellationToken::new();
+        let pulled = AtomicUsize::new(0);
+        let items = (0..100).map(|i| {
+            // Simulate a job queue that is cancelled after handing out one job
+            if pulled.fetch_add(1, Ordering::Relaxed) == 1 {
+                cancel.cancel();
+            }
+            (input.clone(), dir.join(format!("out{i}.png")))
+        });
+
+        let engine = WatermarkEngine::new().unwrap();
+        let opts = ProcessOptions {
+            force: true,
+            jobs: Some(1),
+            ..ProcessOptions::default()
+        };
+        let results = engine.process_iter(items, &opts, &(), &cancel);
+
+        assert!(results.len() <= 2);
+        assert!(
+            pulled.load(Ordering::Relaxed) < 100,
+            "Iterator should not be drained after cancellation"
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
     #[test]
     fn process_directory_on_nonexistent_dir() {
         let engine = WatermarkEngine::new().unwrap();
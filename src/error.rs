@@ -7,6 +7,34 @@ pub enum Error {
     #[error("failed to decode alpha map PNG: {0}")]
     AlphaMapDecode(image::ImageError),
 
+    /// An embedded alpha map PNG decoded to unexpected dimensions.
+    #[error("alpha map is {width}x{height}, expected {expected}x{expected}")]
+    AlphaMapSize {
+        /// Decoded width in pixels.
+        width: u32,
+        /// Decoded height in pixels.
+        height: u32,
+        /// Expected width and height in pixels.
+        expected: u32,
+    },
+
+    /// Alpha map values do not describe a valid map.
+    #[error("invalid alpha map: {0}")]
+    InvalidAlphaMap(String),
+
+    /// A watermark placement extends past `u32` coordinates.
+    #[error("watermark region {width}x{height} at ({x}, {y}) overflows image coordinates")]
+    InvalidRegion {
+        /// X coordinate of the top-left corner.
+        x: u32,
+        /// Y coordinate of the top-left corner.
+        y: u32,
+        /// Width in pixels.
+        width: u32,
+        /// Height in pixels.
+        height: u32,
+    },
+
     /// The image is too small to contain a watermark at the expected position.
     #[error("image too small ({width}x{height}) for {wm_size}x{wm_size} watermark")]
     ImageTooSmall {
@@ -29,6 +57,32 @@ pub enum Error {
     /// An error occurred during image processing (load, save, encode).
     #[error("image processing error: {0}")]
     Image(#[from] image::ImageError),
+
+    /// Two images that must match in size have different dimensions.
+    #[error("image is {width}x{height} but reference is {expected_width}x{expected_height}")]
+    DimensionMismatch {
+        /// Width of the image being measured.
+        width: u32,
+        /// Height of the image being measured.
+        height: u32,
+        /// Width of the reference image.
+        expected_width: u32,
+        /// Height of the reference image.
+        expected_height: u32,
+    },
+
+    /// A function argument is out of range or inconsistent.
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+
+    /// A configuration file or value is invalid.
+    #[error("invalid config: {0}")]
+    Config(String),
+
+    /// Failed to set up watching a directory for new files.
+    #[cfg(feature = "watch")]
+    #[error("watch error: {0}")]
+    Watch(#[from] notify::Error),
 }
 
 /// A specialized `Result` type for this crate.
@@ -0,0 +1,356 @@
+//! Detection accuracy evaluation on labeled images.
+//!
+//! Score a set of images known to be watermarked or clean with
+//! [`WatermarkEngine::score_images`], then sweep the detection threshold with
+//! [`evaluate`] to get ROC and precision-recall curves, their areas, and the
+//! threshold that meets a target false-positive rate:
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use gemini_watermark_removal::{evaluation, ProcessOptions, WatermarkEngine};
+//!
+//! let engine = WatermarkEngine::new()?;
+//! let images = evaluation::labeled_images(Path::new("watermarked/"), Path::new("clean/"))?;
+//! let scores: Vec<_> = engine
+//!     .score_images(&images, &ProcessOptions::default())
+//!     .into_iter()
+//!     .collect::<Result<_, _>>()?;
+//! let eval = evaluation::evaluate(&scores)?;
+//! if let Some(point) = eval.threshold_for_fpr(0.01) {
+//!     println!("threshold {:.3}: TPR {:.1}%", point.threshold, point.true_positive_rate * 100.0);
+//! }
+//! # Ok::<(), gemini_watermark_removal::Error>(())
+//! ```
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::engine::{is_supported_image, ProcessOptions, WatermarkEngine};
+use crate::error::{Error, Result};
+
+/// Detection confidence of one labeled image.
+#[derive(Debug, Clone)]
+pub struct LabeledScore {
+    /// Path of the scored image.
+    pub path: PathBuf,
+    /// Ground truth: whether the image carries a watermark.
+    pub watermarked: bool,
+    /// Detection confidence (0.0-1.0).
+    pub confidence: f32,
+}
+
+/// Detection rates when images with `confidence >= threshold` count as detected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OperatingPoint {
+    /// Detection threshold. The first point of a curve uses `f32::INFINITY`
+    /// (nothing detected).
+    pub threshold: f32,
+    /// Fraction of watermarked images detected (also the recall).
+    pub true_positive_rate: f64,
+    /// Fraction of clean images wrongly detected.
+    pub false_positive_rate: f64,
+    /// Fraction of detections that are watermarked (1.0 when nothing is detected).
+    pub precision: f64,
+}
+
+/// ROC and precision-recall curves over every distinct threshold.
+#[derive(Debug, Clone)]
+pub struct Evaluation {
+    /// Number of watermarked images.
+    pub positives: usize,
+    /// Number of clean images.
+    pub negatives: usize,
+    /// Operating points from the strictest threshold to the most lenient.
+    pub points: Vec<OperatingPoint>,
+    /// Area under the ROC curve (0.5 = chance, 1.0 = perfect separation).
+    pub roc_auc: f64,
+    /// Area under the precision-recall curve, as average precision.
+    pub pr_auc: f64,
+}
+
+impl Evaluation {
+    /// The operating point with the highest true-positive rate whose
+    /// false-positive rate does not exceed `target_fpr`, or `None` if no
+    /// threshold achieves it. Among equally sensitive points the strictest
+    /// threshold (lowest false-positive rate) wins.
+    #[must_use]
+    pub fn threshold_for_fpr(&self, target_fpr: f64) -> Option<OperatingPoint> {
+        let mut best: Option<OperatingPoint> = None;
+        for &point in &self.points {
+            if !point.threshold.is_finite() || point.false_positive_rate > target_fpr {
+                continue;
+            }
+            if best.is_none_or(|b| point.true_positive_rate > b.true_positive_rate) {
+                best = Some(point);
+            }
+        }
+        best
+    }
+
+    /// Rates obtained with a given detection `threshold`.
+    #[must_use]
+    pub fn at_threshold(&self, threshold: f32) -> OperatingPoint {
+        // Points are sorted by descending threshold; the last one at or above
+        // `threshold` detects exactly the same images.
+        let point = self
+            .points
+            .iter()
+            .take_while(|p| p.threshold >= threshold)
+            .last()
+            .unwrap_or(&self.points[0]);
+        OperatingPoint {
+            threshold,
+            ..*point
+        }
+    }
+
+    /// Write the curves as CSV with a header row:
+    /// `threshold,tpr,fpr,precision,recall`.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from `writer`.
+    pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "threshold,tpr,fpr,precision,recall")?;
+        for p in &self.points {
+            writeln!(
+                writer,
+                "{},{:.6},{:.6},{:.6},{:.6}",
+                p.threshold,
+                p.true_positive_rate,
+                p.false_positive_rate,
+                p.precision,
+                p.true_positive_rate
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Sweep the detection threshold over `scores` and compute the curves.
+///
+/// # Errors
+///
+/// Returns [`Error::InvalidArgument`] unless there is at least one
+/// watermarked and one clean image.
+pub fn evaluate(scores: &[LabeledScore]) -> Result<Evaluation> {
+    let positives = scores.iter().filter(|s| s.watermarked).count();
+    let negatives = scores.len() - positives;
+    if positives == 0 || negatives == 0 {
+        return Err(Error::InvalidArgument(format!(
+            "evaluation needs watermarked and clean images, got {positives} and {negatives}"
+        )));
+    }
+
+    let mut sorted: Vec<(f32, bool)> = scores
+        .iter()
+        .map(|s| (s.confidence, s.watermarked))
+        .collect();
+    sorted.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    #[allow(clippy::cast_precision_loss)]
+    let rate = |count: usize, total: usize| count as f64 / total as f64;
+    let mut points = vec![OperatingPoint {
+        threshold: f32::INFINITY,
+        true_positive_rate: 0.0,
+        false_positive_rate: 0.0,
+        precision: 1.0,
+    }];
+    let (mut tp, mut fp) = (0, 0);
+    for (i, &(confidence, watermarked)) in sorted.iter().enumerate() {
+        if watermarked {
+            tp += 1;
+        } else {
+            fp += 1;
+        }
+        // Emit one point per distinct score, after all ties are counted
+        if sorted
+            .get(i + 1)
+            .is_some_and(|next| next.0.total_cmp(&confidence).is_eq())
+        {
+            continue;
+        }
+        points.push(OperatingPoint {
+            threshold: confidence,
+            true_positive_rate: rate(tp, positives),
+            false_positive_rate: rate(fp, negatives),
+            precision: rate(tp, tp + fp),
+        });
+    }
+
+    let mut roc_auc = 0.0;
+    let mut pr_auc = 0.0;
+    for pair in points.windows(2) {
+        let (prev, next) = (pair[0], pair[1]);
+        let recall_step = next.true_positive_rate - prev.true_positive_rate;
+        roc_auc += (next.false_positive_rate - prev.false_positive_rate)
+            * (next.true_positive_rate + prev.true_positive_rate)
+            / 2.0;
+        pr_auc += recall_step * next.precision;
+    }
+
+    Ok(Evaluation {
+        positives,
+        negatives,
+        points,
+        roc_auc,
+        pr_auc,
+    })
+}
+
+/// List the supported images in a folder of watermarked images and a folder
+/// of clean images, labeled accordingly and sorted by path within each folder.
+///
+/// # Errors
+///
+/// Returns [`Error::Io`] if either directory cannot be read.
+pub fn labeled_images(watermarked_dir: &Path, clean_dir: &Path) -> Result<Vec<(PathBuf, bool)>> {
+    let mut images = Vec::new();
+    for (dir, watermarked) in [(watermarked_dir, true), (clean_dir, false)] {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() && is_supported_image(&path) {
+                files.push(path);
+            }
+        }
+        files.sort();
+        images.extend(files.into_iter().map(|path| (path, watermarked)));
+    }
+    Ok(images)
+}
+
+impl WatermarkEngine {
+    /// Run detection on each labeled image file, in parallel when the
+    /// `parallel` feature is enabled.
+    ///
+    /// Only the watermark corner of each file is decoded (see
+    /// [`detect_file`](WatermarkEngine::detect_file)).
+    ///
+    /// Scoring uses `opts.force_size` and the weights in `opts.detection`; the
+    /// detection threshold itself does not affect the confidence. Results are
+    /// returned in input order, with an error for each image that failed to load.
+    #[must_use]
+    pub fn score_images(
+        &self,
+        images: &[(PathBuf, bool)],
+        opts: &ProcessOptions,
+    ) -> Vec<Result<LabeledScore>> {
+        let score = |(path, watermarked): &(PathBuf, bool)| {
+            Ok(LabeledScore {
+                path: path.clone(),
+                watermarked: *watermarked,
+                confidence: self.detect_file(path, opts)?.confidence,
+            })
+        };
+
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::prelude::*;
+            images.par_iter().map(score).collect()
+        }
+
+        #[cfg(not(feature = "parallel"))]
+        images.iter().map(score).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scores(labeled: &[(f32, bool)]) -> Vec<LabeledScore> {
+        labeled
+            .iter()
+            .map(|&(confidence, watermarked)| LabeledScore {
+                path: PathBuf::new(),
+                watermarked,
+                confidence,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn perfect_separation_has_unit_areas() {
+        let eval = evaluate(&scores(&[
+            (0.9, true),
+            (0.8, true),
+            (0.2, false),
+            (0.1, false),
+        ]))
+        .unwrap();
+        assert!((eval.roc_auc - 1.0).abs() < 1e-12);
+        assert!((eval.pr_auc - 1.0).abs() < 1e-12);
+        assert_eq!((eval.positives, eval.negatives), (2, 2));
+
+        // 0.2 also qualifies, but 0.8 reaches full recall without false positives
+        let point = eval.threshold_for_fpr(0.5).unwrap();
+        assert!((point.threshold - 0.8).abs() < f32::EPSILON);
+        assert!((point.true_positive_rate - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn roc_auc_matches_pairwise_ranking() {
+        // Of the 6 positive/negative pairs, 4 are ranked correctly, 1 tied
+        let eval = evaluate(&scores(&[
+            (0.9, true),
+            (0.5, false),
+            (0.5, true),
+            (0.4, true),
+            (0.3, false),
+        ]))
+        .unwrap();
+        assert!((eval.roc_auc - 4.5 / 6.0).abs() < 1e-12, "{}", eval.roc_auc);
+
+        // Ties collapse into one point
+        assert_eq!(eval.points.len(), 5);
+        let last = eval.points.last().unwrap();
+        assert!((last.true_positive_rate - 1.0).abs() < 1e-12);
+        assert!((last.false_positive_rate - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn target_fpr_picks_most_sensitive_threshold() {
+        let eval = evaluate(&scores(&[
+            (0.9, false),
+            (0.8, true),
+            (0.6, true),
+            (0.4, false),
+            (0.3, true),
+        ]))
+        .unwrap();
+        assert!(eval.threshold_for_fpr(0.0).is_none());
+
+        let point = eval.threshold_for_fpr(0.5).unwrap();
+        assert!((point.threshold - 0.6).abs() < f32::EPSILON);
+        // A looser target reaches full recall, at the strictest such threshold
+        let full = eval.threshold_for_fpr(1.0).unwrap();
+        assert!((full.threshold - 0.3).abs() < f32::EPSILON);
+        assert!((point.precision - 2.0 / 3.0).abs() < 1e-12);
+
+        let current = eval.at_threshold(0.5);
+        assert!((current.true_positive_rate - point.true_positive_rate).abs() < 1e-12);
+        assert!(eval.at_threshold(0.95).true_positive_rate.abs() < 1e-12);
+    }
+
+    #[test]
+    fn evaluate_requires_both_classes() {
+        assert!(matches!(
+            evaluate(&scores(&[(0.5, true)])),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(evaluate(&[]), Err(Error::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn csv_has_header_and_one_row_per_point() {
+        let eval = evaluate(&scores(&[(0.7, true), (0.2, false)])).unwrap();
+        let mut out = Vec::new();
+        eval.write_csv(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "threshold,tpr,fpr,precision,recall");
+        assert_eq!(lines.len(), eval.points.len() + 1);
+        assert!(lines[2].starts_with("0.7,1.000000,0.000000"));
+    }
+}
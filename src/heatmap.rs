@@ -0,0 +1,483 @@
+//! Sliding-window detection heatmap.
+//!
+//! [`ncc_heatmap`] computes the normalized cross-correlation (NCC) between the
+//! alpha map and every placement of it inside a search region, showing where
+//! logo-like patterns appear rather than scoring one fixed position.
+//!
+//! The numerator is a cross-correlation with the zero-mean template, computed
+//! tile by tile with a 2D FFT (overlap-save); the per-window normalization
+//! comes from integral images of the region and its square. Cost is roughly
+//! `O(W * H * log N)` for a tile size `N`, instead of `O(W * H * w * h)` for a
+//! direct sliding window.
+
+use image::{Rgb, RgbImage};
+
+use crate::alpha_map::AlphaMap;
+use crate::detection;
+use crate::engine::{WatermarkEngine, WatermarkSize};
+
+/// Windows with a per-pixel variance below this are treated as flat (score 0),
+/// mirroring the zero-denominator guard in spatial detection.
+const MIN_WINDOW_VARIANCE: f64 = 1e-6;
+
+/// Rectangle of the image to search, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchRegion {
+    /// Left edge.
+    pub x: u32,
+    /// Top edge.
+    pub y: u32,
+    /// Width.
+    pub width: u32,
+    /// Height.
+    pub height: u32,
+}
+
+impl SearchRegion {
+    /// The whole image.
+    #[must_use]
+    pub fn full(image: &RgbImage) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width: image.width(),
+            height: image.height(),
+        }
+    }
+}
+
+/// A local maximum of the heatmap.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Peak {
+    /// X coordinate of the watermark's top-left corner in the image.
+    pub x: u32,
+    /// Y coordinate of the watermark's top-left corner in the image.
+    pub y: u32,
+    /// NCC score in `[-1, 1]`.
+    pub score: f32,
+}
+
+/// NCC scores for every placement of the watermark inside a search region.
+///
+/// Entry `(u, v)` is the score with the watermark's top-left corner at image
+/// coordinates `(origin_x + u, origin_y + v)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heatmap {
+    /// Image X coordinate of entry `(0, 0)`.
+    pub origin_x: u32,
+    /// Image Y coordinate of entry `(0, 0)`.
+    pub origin_y: u32,
+    /// Number of placements per row.
+    pub width: u32,
+    /// Number of rows.
+    pub height: u32,
+    /// Width of the watermark that was searched for.
+    pub wm_width: u32,
+    /// Height of the watermark that was searched for.
+    pub wm_height: u32,
+    /// Row-major scores in `[-1, 1]`, `width * height` entries.
+    pub scores: Vec<f32>,
+}
+
+impl Heatmap {
+    /// Score with the watermark's top-left corner at image coordinates `(x, y)`.
+    #[must_use]
+    pub fn score_at(&self, x: u32, y: u32) -> Option<f32> {
+        let u = x.checked_sub(self.origin_x)?;
+        let v = y.checked_sub(self.origin_y)?;
+        (u < self.width && v < self.height).then(|| self.scores[(v * self.width + u) as usize])
+    }
+
+    /// Up to `limit` strongest local maxima, best first.
+    ///
+    /// Peaks closer than half the watermark size to a stronger peak are
+    /// suppressed, so one logo yields one peak.
+    #[must_use]
+    pub fn peaks(&self, limit: usize) -> Vec<Peak> {
+        let (cols, rows) = (self.width, self.height);
+        let at = |u: u32, v: u32| self.scores[(v * cols + u) as usize];
+
+        let mut candidates: Vec<(u32, u32, f32)> = Vec::new();
+        for v in 0..rows {
+            for u in 0..cols {
+                let score = at(u, v);
+                let is_max = (v.saturating_sub(1)..(v + 2).min(rows)).all(|nv| {
+                    (u.saturating_sub(1)..(u + 2).min(cols)).all(|nu| at(nu, nv) <= score)
+                });
+                if is_max {
+                    candidates.push((u, v, score));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| b.2.total_cmp(&a.2));
+
+        let min_dist = (self.wm_width.min(self.wm_height) / 2).max(1);
+        let mut peaks: Vec<Peak> = Vec::new();
+        for (u, v, score) in candidates {
+            if peaks.len() == limit {
+                break;
+            }
+            let (x, y) = (self.origin_x + u, self.origin_y + v);
+            if peaks
+                .iter()
+                .all(|p| p.x.abs_diff(x).max(p.y.abs_diff(y)) >= min_dist)
+            {
+                peaks.push(Peak { x, y, score });
+            }
+        }
+        peaks
+    }
+
+    /// Render positive scores with a black-red-yellow-white color ramp.
+    #[must_use]
+    pub fn to_image(&self) -> RgbImage {
+        RgbImage::from_fn(self.width, self.height, |u, v| {
+            let s = f64::from(self.scores[(v * self.width + u) as usize].max(0.0));
+            let channel = |offset: f64| {
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let c = ((3.0 * s - offset).clamp(0.0, 1.0) * 255.0).round() as u8;
+                c
+            };
+            Rgb([channel(0.0), channel(1.0), channel(2.0)])
+        })
+    }
+}
+
+/// Compute the NCC of `alpha_map` at every placement inside `region`.
+///
+/// Scores match the spatial stage of detection (NCC of the grayscale region
+/// against the alpha map) up to floating-point error. The region is clipped
+/// to the image; if it is smaller than the watermark, the heatmap is all
+/// zeros.
+#[must_use]
+pub fn ncc_heatmap(image: &RgbImage, alpha_map: &AlphaMap, region: SearchRegion) -> Heatmap {
+    let (wm_width, wm_height) = (alpha_map.width(), alpha_map.height());
+    let rx = region.x.min(image.width());
+    let ry = region.y.min(image.height());
+    let rw = region.width.min(image.width() - rx);
+    let rh = region.height.min(image.height() - ry);
+
+    let mut heatmap = Heatmap {
+        origin_x: rx,
+        origin_y: ry,
+        width: (rw + 1).saturating_sub(wm_width),
+        height: (rh + 1).saturating_sub(wm_height),
+        wm_width,
+        wm_height,
+        scores: Vec::new(),
+    };
+    let (out_w, out_h) = (heatmap.width as usize, heatmap.height as usize);
+    heatmap.scores = vec![0.0; out_w * out_h];
+    if out_w == 0 || out_h == 0 {
+        return heatmap;
+    }
+
+    let (tw, th) = (wm_width as usize, wm_height as usize);
+    let count = f64::from(wm_width) * f64::from(wm_height);
+
+    // Zero-mean template, so the correlation numerator ignores the window mean
+    let alpha_map = alpha_map.values();
+    let mean_t = alpha_map.iter().map(|&a| f64::from(a)).sum::<f64>() / count;
+    let template: Vec<f64> = alpha_map.iter().map(|&a| f64::from(a) - mean_t).collect();
+    let norm_t = template.iter().map(|t| t * t).sum::<f64>().sqrt();
+    if norm_t < 1e-10 {
+        return heatmap;
+    }
+
+    let gray: Vec<f64> = detection::region_to_grayscale(image, rx, ry, rw, rh)
+        .into_iter()
+        .map(f64::from)
+        .collect();
+    let (rw, rh) = (rw as usize, rh as usize);
+    let sums = IntegralImage::new(&gray, rw, rh, |g| g);
+    let squares = IntegralImage::new(&gray, rw, rh, |g| g * g);
+
+    // Tile size: large enough that most of each tile yields valid outputs
+    let n = (tw.max(th) * 4).next_power_of_two();
+    let (step_x, step_y) = (n - tw + 1, n - th + 1);
+
+    let mut template_f = Spectrum::new(n);
+    for y in 0..th {
+        for x in 0..tw {
+            template_f.re[y * n + x] = template[y * tw + x];
+        }
+    }
+    template_f.fft2d(false);
+
+    let mut tile = Spectrum::new(n);
+    for ty in (0..out_h).step_by(step_y) {
+        for tx in (0..out_w).step_by(step_x) {
+            tile.clear();
+            for y in 0..n.min(rh - ty) {
+                let row = (ty + y) * rw + tx;
+                let len = n.min(rw - tx);
+                tile.re[y * n..y * n + len].copy_from_slice(&gray[row..row + len]);
+            }
+            tile.fft2d(false);
+            tile.multiply_conj(&template_f);
+            tile.fft2d(true);
+
+            for v in 0..step_y.min(out_h - ty) {
+                for u in 0..step_x.min(out_w - tx) {
+                    let (x, y) = (tx + u, ty + v);
+                    let sum = sums.rect(x, y, tw, th);
+                    let variance = squares.rect(x, y, tw, th) - sum * sum / count;
+                    if variance < MIN_WINDOW_VARIANCE * count {
+                        continue;
+                    }
+                    let score = tile.re[v * n + u] / (variance.sqrt() * norm_t);
+                    #[allow(clippy::cast_possible_truncation)]
+                    {
+                        heatmap.scores[y * out_w + x] = score.clamp(-1.0, 1.0) as f32;
+                    }
+                }
+            }
+        }
+    }
+
+    heatmap
+}
+
+impl WatermarkEngine {
+    /// Detection heatmap of the watermark over the whole image.
+    ///
+    /// `force_size` selects the alpha map; by default it is chosen from the
+    /// image dimensions as for removal.
+    #[must_use]
+    pub fn heatmap(&self, image: &RgbImage, force_size: Option<WatermarkSize>) -> Heatmap {
+        let (_, _, alpha_map) = self.config(image.width(), image.height(), force_size);
+        ncc_heatmap(image, &alpha_map, SearchRegion::full(image))
+    }
+}
+
+/// Summed-area table for O(1) rectangle sums.
+struct IntegralImage {
+    stride: usize,
+    table: Vec<f64>,
+}
+
+impl IntegralImage {
+    fn new(data: &[f64], width: usize, height: usize, f: impl Fn(f64) -> f64) -> Self {
+        let stride = width + 1;
+        let mut table = vec![0.0; stride * (height + 1)];
+        for y in 0..height {
+            let mut row_sum = 0.0;
+            for x in 0..width {
+                row_sum += f(data[y * width + x]);
+                table[(y + 1) * stride + x + 1] = table[y * stride + x + 1] + row_sum;
+            }
+        }
+        Self { stride, table }
+    }
+
+    fn rect(&self, x: usize, y: usize, w: usize, h: usize) -> f64 {
+        let at = |x: usize, y: usize| self.table[y * self.stride + x];
+        at(x + w, y + h) - at(x, y + h) - at(x + w, y) + at(x, y)
+    }
+}
+
+/// Square complex buffer of side `n` (a power of two) for 2D FFTs.
+struct Spectrum {
+    n: usize,
+    re: Vec<f64>,
+    im: Vec<f64>,
+}
+
+impl Spectrum {
+    fn new(n: usize) -> Self {
+        Self {
+            n,
+            re: vec![0.0; n * n],
+            im: vec![0.0; n * n],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.re.fill(0.0);
+        self.im.fill(0.0);
+    }
+
+    /// Multiply element-wise by the complex conjugate of `other`, turning a
+    /// product of spectra into a cross-correlation.
+    fn multiply_conj(&mut self, other: &Self) {
+        for i in 0..self.re.len() {
+            let (a, b) = (self.re[i], self.im[i]);
+            let (c, d) = (other.re[i], -other.im[i]);
+            self.re[i] = a * c - b * d;
+            self.im[i] = a * d + b * c;
+        }
+    }
+
+    /// In-place 2D FFT: rows, then columns.
+    fn fft2d(&mut self, inverse: bool) {
+        let n = self.n;
+        for row in 0..n {
+            let range = row * n..(row + 1) * n;
+            fft(&mut self.re[range.clone()], &mut self.im[range], inverse);
+        }
+        let (mut col_re, mut col_im) = (vec![0.0; n], vec![0.0; n]);
+        for col in 0..n {
+            for y in 0..n {
+                col_re[y] = self.re[y * n + col];
+                col_im[y] = self.im[y * n + col];
+            }
+            fft(&mut col_re, &mut col_im, inverse);
+            for y in 0..n {
+                self.re[y * n + col] = col_re[y];
+                self.im[y * n + col] = col_im[y];
+            }
+        }
+    }
+}
+
+/// Iterative radix-2 Cooley-Tukey FFT. The inverse is scaled by `1/n`.
+fn fft(re: &mut [f64], im: &mut [f64], inverse: bool) {
+    let n = re.len();
+    debug_assert!(n.is_power_of_two());
+
+    // Bit-reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        #[allow(clippy::cast_precision_loss)]
+        let angle = sign * std::f64::consts::TAU / len as f64;
+        let half = len / 2;
+        for start in (0..n).step_by(len) {
+            for k in 0..half {
+                #[allow(clippy::cast_precision_loss)]
+                let (wi, wr) = (angle * k as f64).sin_cos();
+                let (a, b) = (start + k, start + k + half);
+                let tr = re[b] * wr - im[b] * wi;
+                let ti = re[b] * wi + im[b] * wr;
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+        }
+        len <<= 1;
+    }
+
+    if inverse {
+        #[allow(clippy::cast_precision_loss)]
+        let scale = 1.0 / n as f64;
+        for v in re.iter_mut().chain(im.iter_mut()) {
+            *v *= scale;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synthesis::SynthesisOptions;
+
+    fn textured(w: u32, h: u32) -> RgbImage {
+        #[allow(clippy::cast_possible_truncation)]
+        RgbImage::from_fn(w, h, |x, y| {
+            let v = (x * 7 + y * 13 + (x * y) % 17) % 256;
+            image::Rgb([v as u8, (255 - v) as u8, ((x + y) % 256) as u8])
+        })
+    }
+
+    #[test]
+    fn fft_round_trips() {
+        let mut re: Vec<f64> = (0..16).map(f64::from).collect();
+        let mut im = vec![0.0; 16];
+        let original = re.clone();
+        fft(&mut re, &mut im, false);
+        assert!((re[0] - 120.0).abs() < 1e-9, "DC term is the sum");
+        fft(&mut re, &mut im, true);
+        for (a, b) in re.iter().zip(&original) {
+            assert!((a - b).abs() < 1e-9);
+        }
+        assert!(im.iter().all(|v| v.abs() < 1e-9));
+    }
+
+    #[test]
+    fn heatmap_matches_direct_ncc() {
+        let image = textured(70, 60);
+        #[allow(clippy::cast_precision_loss)]
+        let values = (0..12 * 10)
+            .map(|i| ((i * 37) % 23) as f32 / 23.0)
+            .collect();
+        let alpha = AlphaMap::new(values, 12, 10).unwrap();
+        let heatmap = ncc_heatmap(&image, &alpha, SearchRegion::full(&image));
+        assert_eq!((heatmap.width, heatmap.height), (59, 51));
+
+        for (x, y) in [(0, 0), (5, 7), (58, 50), (31, 12)] {
+            let gray = detection::region_to_grayscale(&image, x, y, 12, 10);
+            let direct = detection::ncc(&gray, alpha.values());
+            let fast = heatmap.score_at(x, y).unwrap();
+            assert!(
+                (direct - fast).abs() < 1e-3,
+                "({x},{y}): {direct} vs {fast}"
+            );
+        }
+    }
+
+    #[test]
+    fn heatmap_peak_finds_shifted_logo() {
+        let engine = WatermarkEngine::new().unwrap();
+        let mut image = RgbImage::from_pixel(300, 240, image::Rgb([60, 80, 100]));
+        let opts = SynthesisOptions {
+            offset: (-150, -90),
+            ..SynthesisOptions::default()
+        };
+        engine.apply(&mut image, &opts).unwrap();
+
+        let heatmap = engine.heatmap(&image, None);
+        let peaks = heatmap.peaks(3);
+        // Default position is (300 - 80, 240 - 80)
+        assert_eq!((peaks[0].x, peaks[0].y), (70, 70));
+        assert!(peaks[0].score > 0.95);
+        assert!(peaks.iter().skip(1).all(|p| p.score < peaks[0].score));
+
+        let rendered = heatmap.to_image();
+        assert_eq!(rendered.dimensions(), (heatmap.width, heatmap.height));
+        assert_eq!(*rendered.get_pixel(70, 70), image::Rgb([255, 255, 255]));
+    }
+
+    #[test]
+    fn heatmap_of_region_smaller_than_watermark_is_empty() {
+        let image = textured(40, 40);
+        let alpha = AlphaMap::new(vec![0.5; 48 * 48], 48, 48).unwrap();
+        let heatmap = ncc_heatmap(&image, &alpha, SearchRegion::full(&image));
+        assert_eq!((heatmap.width, heatmap.height), (0, 0));
+        assert!(heatmap.peaks(5).is_empty());
+    }
+
+    #[test]
+    fn heatmap_respects_search_region() {
+        let image = textured(120, 100);
+        let values = (0..16)
+            .map(|i| if i % 3 == 0 { 1.0 } else { 0.0 })
+            .collect();
+        let alpha = AlphaMap::new(values, 4, 4).unwrap();
+        let region = SearchRegion {
+            x: 30,
+            y: 20,
+            width: 40,
+            height: 500,
+        };
+        let heatmap = ncc_heatmap(&image, &alpha, region);
+        assert_eq!((heatmap.origin_x, heatmap.origin_y), (30, 20));
+        assert_eq!((heatmap.width, heatmap.height), (37, 77));
+        assert_eq!(heatmap.score_at(29, 20), None);
+        assert!(heatmap.score_at(30, 20).is_some());
+    }
+}
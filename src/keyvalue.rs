@@ -0,0 +1,113 @@
+//! Reader for the line-based `key = value` files behind
+//! [`DetectionConfig::load`](crate::detection::DetectionConfig::load) and
+//! [`WatermarkProfile::load`](crate::profile::WatermarkProfile::load).
+//!
+//! Every line is blank, a `[section]` header or a `key = value` pair. A `#`
+//! at the start of a line or after whitespace starts a comment, so a `#`
+//! inside a value such as `logos/#2.png` is kept. A value wrapped in double
+//! quotes keeps its surrounding spaces and any ` #`; there are no escapes.
+
+use std::fmt;
+
+use crate::error::{Error, Result};
+
+/// A non-blank line of a `key = value` file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Line<'a> {
+    /// A `[name]` header, with the name trimmed.
+    Section(&'a str),
+    /// A `key = value` pair, trimmed and with the value's quotes removed.
+    Pair(&'a str, &'a str),
+}
+
+/// The non-blank lines of `text` with their 1-based line numbers.
+pub(crate) fn lines(text: &str) -> impl Iterator<Item = Result<(usize, Line<'_>)>> + '_ {
+    text.lines().enumerate().filter_map(|(index, raw)| {
+        let line_no = index + 1;
+        match parse_line(raw) {
+            Ok(line) => line.map(|line| Ok((line_no, line))),
+            Err(message) => Some(Err(line_error(line_no, message))),
+        }
+    })
+}
+
+/// An [`Error::Config`] for the given line.
+pub(crate) fn line_error(line_no: usize, message: impl fmt::Display) -> Error {
+    Error::Config(format!("line {line_no}: {message}"))
+}
+
+fn parse_line(raw: &str) -> std::result::Result<Option<Line<'_>>, String> {
+    let line = strip_comment(raw).trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if let Some(rest) = line.strip_prefix('[') {
+        return match rest.strip_suffix(']') {
+            Some(name) => Ok(Some(Line::Section(name.trim()))),
+            None => Err(format!("expected `[section]`, got `{line}`")),
+        };
+    }
+    let Some((key, value)) = line.split_once('=') else {
+        return Err(format!("expected `key = value`, got `{line}`"));
+    };
+    let value = value.trim();
+    let value = match value.strip_prefix('"') {
+        Some(rest) => rest
+            .strip_suffix('"')
+            .ok_or_else(|| format!("unterminated quote in `{value}`"))?,
+        None => value,
+    };
+    Ok(Some(Line::Pair(key.trim(), value)))
+}
+
+/// `line` up to its comment, if any.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut after_space = true;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted && after_space => return &line[..index],
+            _ => {}
+        }
+        after_space = c.is_whitespace();
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Vec<(usize, Line<'_>)>> {
+        lines(text).collect()
+    }
+
+    #[test]
+    fn reads_sections_pairs_and_comments() {
+        let text = "# header\n[profile]\n\nname = acme # trailing\nsmall=logos/#2.png\n  # indented\nlarge = \"my logo #3.png\"\nempty =\n";
+        assert_eq!(
+            parse(text).unwrap(),
+            [
+                (2, Line::Section("profile")),
+                (4, Line::Pair("name", "acme")),
+                (5, Line::Pair("small", "logos/#2.png")),
+                (7, Line::Pair("large", "my logo #3.png")),
+                (8, Line::Pair("empty", "")),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_malformed_lines_with_their_number() {
+        for (text, expected) in [
+            ("a = 1\nbogus", "line 2: expected `key = value`"),
+            ("[profile", "line 1: expected `[section]`"),
+            ("\n\nsmall = \"a.png", "line 3: unterminated quote"),
+        ] {
+            let err = parse(text).unwrap_err();
+            assert!(matches!(err, Error::Config(_)), "{text:?}: {err:?}");
+            assert!(err.to_string().contains(expected), "{text:?}: {err}");
+        }
+    }
+}
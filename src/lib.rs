@@ -17,9 +17,10 @@
 //!
 //! # Detection
 //!
-//! Before removal, a three-stage detection algorithm checks whether a watermark
-//! is present (spatial NCC, gradient NCC, variance analysis). Images without
-//! detected watermarks can be automatically skipped to protect originals.
+//! Before removal, a multi-stage detection algorithm checks whether a watermark
+//! is present (spatial NCC, gradient NCC, variance analysis, color and model
+//! fit). Images without detected watermarks can be automatically skipped to
+//! protect originals.
 //!
 //! ```no_run
 //! use gemini_watermark_removal::{WatermarkEngine, ProcessOptions};
@@ -30,17 +31,85 @@
 //! let result = engine.detect(&img, &opts);
 //! println!("Detected: {}, confidence: {:.0}%", result.detected, result.confidence * 100.0);
 //! ```
+//!
+//! [`WatermarkEngine::detect_file`] runs the same detection straight from a
+//! file, decoding only the watermark corner (see [`decode`]).
+//!
+//! # Profiles
+//!
+//! The engine is built around a registry of named [`WatermarkProfile`]s, each
+//! describing one corner logo. Gemini is built in; other logos can be added
+//! with [`WatermarkEngine::with_profile`], after which detection picks the
+//! best-scoring profile per image unless one is pinned with
+//! [`WatermarkEngine::use_profile`]. A profile can also hold several
+//! versions of its logo ([`profile::LogoVersion`]), for images stamped before
+//! a redesign; the best-scoring version is used for removal.
+//!
+//! When the logo is not where the profile expects it, a [`RemovalRegion`]
+//! passed to [`WatermarkEngine::with_region`] gives its position (relative to
+//! any corner) and size explicitly.
+//!
+//! # Batch Processing
+//!
+//! [`WatermarkEngine::process_directory`] cleans a whole folder, and
+//! [`WatermarkEngine::process_iter`] takes any iterator of `(input, output)` pairs,
+//! such as one fed by a job queue.
+//!
+//! # Test Fixtures
+//!
+//! [`WatermarkEngine::apply`] stamps the logo onto a clean image (with optional
+//! offset, scale, noise and JPEG re-encoding) to build ground-truth pairs for
+//! regression tests.
+//!
+//! # Quality Metrics
+//!
+//! The [`metrics`] module measures removal quality with PSNR and SSIM against
+//! a reference, and [`WatermarkEngine::quality_report`] adds a reference-free
+//! residual score that re-runs detection on the cleaned image.
+//!
+//! # Threshold Tuning
+//!
+//! The [`evaluation`] module scores folders of known watermarked and clean
+//! images and computes ROC and precision-recall curves, so the detection
+//! threshold can be picked for a target false-positive rate.
+//!
+//! # Features
+//!
+//! - `parallel` (default): process batches on a rayon thread pool.
+//! - `cli` (default): build the `gemini-watermark` command-line tool.
+//! - `watch` (default): [`watch`] a directory and clean new images as they arrive.
 
 #![deny(missing_docs)]
 
+pub mod alpha_map;
 mod alpha_maps;
+pub mod batch;
 pub mod blending;
+mod debug;
+pub mod decode;
 pub mod detection;
 mod engine;
 pub mod error;
+pub mod evaluation;
+pub mod heatmap;
+mod keyvalue;
+pub mod metrics;
+pub mod profile;
+pub mod region;
+pub mod synthesis;
+mod verify;
+#[cfg(feature = "watch")]
+pub mod watch;
 
+pub use alpha_map::AlphaMap;
+pub use batch::{BatchObserver, BatchSummary, CancellationToken};
+pub use detection::DetectionConfig;
 pub use engine::{
-    default_output_path, is_supported_image, save_image, ProcessOptions, ProcessResult,
-    WatermarkEngine, WatermarkSize,
+    backup_path, default_output_path, is_supported_image, restore_backup, save_image,
+    ProcessOptions, ProcessResult, ProcessStatus, WatermarkEngine, WatermarkSize,
 };
 pub use error::{Error, Result};
+pub use metrics::QualityReport;
+pub use profile::WatermarkProfile;
+pub use region::RemovalRegion;
+pub use synthesis::SynthesisOptions;
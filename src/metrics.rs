@@ -0,0 +1,317 @@
+//! Image quality metrics for judging removal results.
+//!
+//! [`psnr`] and [`ssim`] compare a cleaned image against a reference (for
+//! example the clean original used with [`WatermarkEngine::apply`]).
+//! [`WatermarkEngine::quality_report`] combines them with a reference-free
+//! residual score: detection re-run on the cleaned image, which stays high
+//! when a visible trace of the logo is left behind.
+
+use image::RgbImage;
+
+use crate::engine::{ProcessOptions, WatermarkEngine};
+use crate::error::{Error, Result};
+
+/// Stabilizing constants from the SSIM paper, for 8-bit data.
+const SSIM_C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const SSIM_C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+/// SSIM window size and step in pixels.
+const SSIM_WINDOW: u32 = 8;
+const SSIM_STEP: u32 = 4;
+
+/// Quality of a cleaned image, with or without a reference.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QualityReport {
+    /// PSNR over the whole image in dB (`None` without a reference).
+    pub psnr: Option<f64>,
+    /// PSNR over the watermark region only, in dB (`None` without a reference).
+    pub region_psnr: Option<f64>,
+    /// Mean SSIM over the whole image (`None` without a reference).
+    pub ssim: Option<f64>,
+    /// Detection confidence on the cleaned image; lower means less of the
+    /// logo remains.
+    pub residual_score: f32,
+    /// Whether the residual is still strong enough to count as a detection.
+    pub residual_detected: bool,
+}
+
+/// Peak signal-to-noise ratio between two images, in dB.
+///
+/// Computed over all RGB channels. Identical images return infinity.
+///
+/// # Errors
+///
+/// Returns [`Error::DimensionMismatch`] if the images differ in size.
+pub fn psnr(image: &RgbImage, reference: &RgbImage) -> Result<f64> {
+    check_dimensions(image, reference)?;
+    Ok(region_psnr(
+        image,
+        reference,
+        0,
+        0,
+        image.width(),
+        image.height(),
+    ))
+}
+
+/// PSNR over a rectangle, clipped to the image bounds.
+fn region_psnr(
+    image: &RgbImage,
+    reference: &RgbImage,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> f64 {
+    let x2 = x.saturating_add(width).min(image.width());
+    let y2 = y.saturating_add(height).min(image.height());
+
+    let mut sum_sq = 0.0f64;
+    let mut count = 0u64;
+    for py in y..y2 {
+        for px in x..x2 {
+            let pixel = image.get_pixel(px, py);
+            let expected = reference.get_pixel(px, py);
+            for ch in 0..3 {
+                let diff = f64::from(pixel[ch]) - f64::from(expected[ch]);
+                sum_sq += diff * diff;
+            }
+            count += 3;
+        }
+    }
+
+    if count == 0 || sum_sq == 0.0 {
+        return f64::INFINITY;
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let mse = sum_sq / count as f64;
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+/// Mean structural similarity (SSIM) between two images, in `[-1, 1]`.
+///
+/// Computed on luma over 8x8 windows placed every 4 pixels. Images smaller
+/// than one window are compared as a single window.
+///
+/// # Errors
+///
+/// Returns [`Error::DimensionMismatch`] if the images differ in size.
+pub fn ssim(image: &RgbImage, reference: &RgbImage) -> Result<f64> {
+    check_dimensions(image, reference)?;
+
+    let (w, h) = image.dimensions();
+    if w == 0 || h == 0 {
+        return Ok(1.0);
+    }
+    let image_luma = luma(image);
+    let reference_luma = luma(reference);
+
+    let win_w = SSIM_WINDOW.min(w);
+    let win_h = SSIM_WINDOW.min(h);
+    let mut total = 0.0f64;
+    let mut windows = 0u32;
+    let mut y = 0;
+    loop {
+        let mut x = 0;
+        loop {
+            total += window_ssim(&image_luma, &reference_luma, w, (x, y), (win_w, win_h));
+            windows += 1;
+            if x + win_w >= w {
+                break;
+            }
+            x = (x + SSIM_STEP).min(w - win_w);
+        }
+        if y + win_h >= h {
+            break;
+        }
+        y = (y + SSIM_STEP).min(h - win_h);
+    }
+
+    Ok(total / f64::from(windows))
+}
+
+/// SSIM of a single window.
+fn window_ssim(
+    first: &[f64],
+    second: &[f64],
+    stride: u32,
+    origin: (u32, u32),
+    size: (u32, u32),
+) -> f64 {
+    let ((x, y), (w, h)) = (origin, size);
+    let count = f64::from(w * h);
+    let idx = |px: u32, py: u32| (py * stride + px) as usize;
+
+    let (mut sum_a, mut sum_b) = (0.0, 0.0);
+    for py in y..y + h {
+        for px in x..x + w {
+            sum_a += first[idx(px, py)];
+            sum_b += second[idx(px, py)];
+        }
+    }
+    let (mean_a, mean_b) = (sum_a / count, sum_b / count);
+
+    let (mut var_a, mut var_b, mut cov) = (0.0, 0.0, 0.0);
+    for py in y..y + h {
+        for px in x..x + w {
+            let da = first[idx(px, py)] - mean_a;
+            let db = second[idx(px, py)] - mean_b;
+            var_a += da * da;
+            var_b += db * db;
+            cov += da * db;
+        }
+    }
+    let (var_a, var_b, cov) = (var_a / count, var_b / count, cov / count);
+
+    ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * cov + SSIM_C2))
+        / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1) * (var_a + var_b + SSIM_C2))
+}
+
+/// BT.601 luma, matching the grayscale conversion used by detection.
+fn luma(image: &RgbImage) -> Vec<f64> {
+    image
+        .pixels()
+        .map(|p| 0.299 * f64::from(p[0]) + 0.587 * f64::from(p[1]) + 0.114 * f64::from(p[2]))
+        .collect()
+}
+
+fn check_dimensions(image: &RgbImage, reference: &RgbImage) -> Result<()> {
+    if image.dimensions() == reference.dimensions() {
+        Ok(())
+    } else {
+        Err(Error::DimensionMismatch {
+            width: image.width(),
+            height: image.height(),
+            expected_width: reference.width(),
+            expected_height: reference.height(),
+        })
+    }
+}
+
+impl WatermarkEngine {
+    /// Measure how well the watermark was removed from `cleaned`.
+    ///
+    /// The residual score is always computed. PSNR (whole image and
+    /// watermark region) and SSIM are filled in when a `reference` is given.
+    /// `opts.force_size` selects the watermark region and `opts.detection`
+    /// scores the residual.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::DimensionMismatch`] if `reference` differs in size
+    /// from `cleaned`.
+    pub fn quality_report(
+        &self,
+        cleaned: &RgbImage,
+        reference: Option<&RgbImage>,
+        opts: &ProcessOptions,
+    ) -> Result<QualityReport> {
+        let residual = self.detect(cleaned, opts);
+        let mut report = QualityReport {
+            psnr: None,
+            region_psnr: None,
+            ssim: None,
+            residual_score: residual.confidence,
+            residual_detected: residual.detected,
+        };
+
+        if let Some(reference) = reference {
+            let (w, h) = cleaned.dimensions();
+            let (wm_size, margin, _) = self.config(w, h, opts.force_size);
+            let (x, y) = self.position(w, h, wm_size, margin);
+
+            report.psnr = Some(psnr(cleaned, reference)?);
+            report.region_psnr = Some(region_psnr(cleaned, reference, x, y, wm_size, wm_size));
+            report.ssim = Some(ssim(cleaned, reference)?);
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synthesis::SynthesisOptions;
+
+    fn gradient(w: u32, h: u32) -> RgbImage {
+        #[allow(clippy::cast_possible_truncation)]
+        RgbImage::from_fn(w, h, |x, y| {
+            image::Rgb([(x % 200) as u8, (y % 200) as u8, ((x + y) % 150) as u8])
+        })
+    }
+
+    #[test]
+    fn psnr_of_identical_images_is_infinite() {
+        let img = gradient(32, 32);
+        assert!(psnr(&img, &img).unwrap().is_infinite());
+    }
+
+    #[test]
+    fn psnr_matches_known_mse() {
+        let a = RgbImage::from_pixel(10, 10, image::Rgb([100, 100, 100]));
+        let b = RgbImage::from_pixel(10, 10, image::Rgb([110, 110, 110]));
+        // MSE = 100 -> 10 * log10(65025 / 100)
+        let expected = 10.0 * (65025.0f64 / 100.0).log10();
+        assert!((psnr(&a, &b).unwrap() - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ssim_is_one_for_identical_and_lower_for_distorted() {
+        let img = gradient(64, 48);
+        assert!((ssim(&img, &img).unwrap() - 1.0).abs() < 1e-9);
+
+        let mut noisy = img.clone();
+        for (i, px) in noisy.pixels_mut().enumerate() {
+            if i % 3 == 0 {
+                px[0] = 255 - px[0];
+            }
+        }
+        let score = ssim(&noisy, &img).unwrap();
+        assert!(score < 0.9, "score = {score}");
+
+        // Smaller than one window
+        let tiny = gradient(5, 3);
+        assert!((ssim(&tiny, &tiny).unwrap() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn metrics_reject_mismatched_dimensions() {
+        let a = gradient(10, 10);
+        let b = gradient(10, 11);
+        assert!(matches!(psnr(&a, &b), Err(Error::DimensionMismatch { .. })));
+        assert!(matches!(ssim(&a, &b), Err(Error::DimensionMismatch { .. })));
+    }
+
+    #[test]
+    fn quality_report_tracks_removal() {
+        let engine = WatermarkEngine::new().unwrap();
+        let opts = ProcessOptions::default();
+        let original = gradient(400, 300);
+        let mut stamped = original.clone();
+        engine
+            .apply(&mut stamped, &SynthesisOptions::default())
+            .unwrap();
+
+        let before = engine
+            .quality_report(&stamped, Some(&original), &opts)
+            .unwrap();
+        assert!(before.residual_detected);
+
+        let mut cleaned = stamped.clone();
+        engine.remove(&mut cleaned, None);
+        let after = engine
+            .quality_report(&cleaned, Some(&original), &opts)
+            .unwrap();
+        assert!(!after.residual_detected);
+        assert!(after.residual_score < before.residual_score);
+        assert!(after.region_psnr.unwrap() > before.region_psnr.unwrap());
+        assert!(after.psnr.unwrap() > 40.0);
+        assert!(after.ssim.unwrap() > before.ssim.unwrap());
+
+        let reference_free = engine.quality_report(&cleaned, None, &opts).unwrap();
+        assert_eq!(reference_free.psnr, None);
+        assert_eq!(reference_free.ssim, None);
+        assert!((reference_free.residual_score - after.residual_score).abs() < f32::EPSILON);
+    }
+}
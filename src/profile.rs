@@ -0,0 +1,615 @@
+//! Watermark profiles.
+//!
+//! A [`WatermarkProfile`] describes one semi-transparent corner logo: its
+//! alpha maps, where it sits, and the color it was blended with. The same
+//! reverse alpha blending and detection pipeline handles any logo stamped
+//! this way, so a [`WatermarkEngine`](crate::WatermarkEngine) can hold several
+//! profiles and pick the best-scoring one per image. The built-in Gemini
+//! profile is [`WatermarkProfile::gemini`].
+
+use std::path::{Path, PathBuf};
+
+use crate::alpha_map::AlphaMap;
+use crate::alpha_maps;
+use crate::blending::BlendTable;
+use crate::engine::WatermarkSize;
+use crate::error::{Error, Result};
+use crate::keyvalue::{self, Line};
+
+/// Name of the built-in Gemini profile.
+pub const GEMINI: &str = "gemini";
+
+/// Id of the logo version a profile is created with by
+/// [`WatermarkProfile::new`].
+pub const DEFAULT_VERSION: &str = "v1";
+
+/// Image side above which (in both dimensions) the large logo is used by
+/// default, as Gemini does.
+const DEFAULT_LARGE_ABOVE: u32 = 1024;
+
+/// Logo color of a white logo.
+const WHITE: f32 = 255.0;
+
+/// One logo size: a square alpha map and its distance from the image corner.
+#[derive(Debug, Clone)]
+pub struct LogoSpec {
+    alpha_map: AlphaMap,
+    margin: u32,
+}
+
+impl LogoSpec {
+    /// A logo whose bottom-right corner sits `margin` pixels from the right
+    /// and bottom image edges.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidAlphaMap`] if the alpha map is not square: the
+    /// removal pipeline (verification, retries, synthesis) assumes a square
+    /// watermark region.
+    pub fn new(alpha_map: AlphaMap, margin: u32) -> Result<Self> {
+        if alpha_map.width() != alpha_map.height() {
+            return Err(Error::InvalidAlphaMap(format!(
+                "profile logos must be square, got {}x{}",
+                alpha_map.width(),
+                alpha_map.height()
+            )));
+        }
+        Ok(Self { alpha_map, margin })
+    }
+
+    /// The logo's alpha map.
+    #[must_use]
+    pub fn alpha_map(&self) -> &AlphaMap {
+        &self.alpha_map
+    }
+
+    /// Width and height of the logo in pixels.
+    #[must_use]
+    pub fn size(&self) -> u32 {
+        self.alpha_map.width()
+    }
+
+    /// Distance from the right and bottom image edges in pixels.
+    #[must_use]
+    pub fn margin(&self) -> u32 {
+        self.margin
+    }
+}
+
+/// One version of a profile's logo: a small and a large [`LogoSpec`].
+///
+/// Brands redraw their logos over time, and an image stamped with an older
+/// logo does not correlate with the current one. A [`WatermarkProfile`] can
+/// hold several versions; the engine detects against each and removes with
+/// the best-scoring one.
+#[derive(Debug, Clone)]
+pub struct LogoVersion {
+    id: String,
+    small: LogoSpec,
+    large: LogoSpec,
+}
+
+impl LogoVersion {
+    /// A logo version called `id`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Config`] if the id is not a valid name (see
+    /// [`WatermarkProfile::new`]).
+    pub fn new(id: impl Into<String>, small: LogoSpec, large: LogoSpec) -> Result<Self> {
+        let id = id.into();
+        check_name("logo version", &id)?;
+        Ok(Self { id, small, large })
+    }
+
+    /// The version's id.
+    #[must_use]
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The logo for a watermark size.
+    #[must_use]
+    pub fn logo(&self, size: WatermarkSize) -> &LogoSpec {
+        match size {
+            WatermarkSize::Small => &self.small,
+            WatermarkSize::Large => &self.large,
+        }
+    }
+}
+
+/// A [`LogoVersion`] with its reverse blending tables.
+#[derive(Debug, Clone)]
+struct VersionEntry {
+    version: LogoVersion,
+    table_small: BlendTable,
+    table_large: BlendTable,
+}
+
+impl VersionEntry {
+    fn new(version: LogoVersion, logo_value: f32) -> Self {
+        Self {
+            table_small: BlendTable::new(version.small.alpha_map(), logo_value),
+            table_large: BlendTable::new(version.large.alpha_map(), logo_value),
+            version,
+        }
+    }
+}
+
+/// A named watermark design the engine can detect and remove.
+///
+/// A profile has a small and a large logo, mirroring how Gemini scales its
+/// watermark with the image: the large one is used when both image
+/// dimensions exceed [`large_above`](Self::large_above). Both are anchored
+/// to the bottom-right corner. A profile can carry several [`LogoVersion`]s;
+/// the first is the primary one. The reverse blending tables for every
+/// version and size are built once when the version is added.
+///
+/// # Profile file
+///
+/// [`WatermarkProfile::load`] reads a plain-text file of `key = value` lines,
+/// like a [`DetectionConfig`](crate::detection::DetectionConfig) file: a `#`
+/// at the start of a line or after whitespace starts a comment, values may be
+/// double-quoted, and an optional `[profile]` header is allowed. Logo
+/// captures are images of the logo rendered on black (see
+/// [`AlphaMap::from_capture`]), with paths relative to the profile file.
+/// `name`, `small` and `small_margin` are required; the large logo defaults
+/// to the small one. Further logo versions follow in `[version.ID]`
+/// sections, whose margins default to the primary version's:
+///
+/// ```text
+/// [profile]
+/// name = acme
+/// version = 2024
+/// small = acme-32.png
+/// small_margin = 16
+/// large = acme-64.png
+/// large_margin = 32
+/// large_above = 1024
+/// logo_value = 255
+///
+/// [version.2019]
+/// small = acme-2019-32.png
+/// large = acme-2019-64.png
+/// ```
+#[derive(Debug, Clone)]
+pub struct WatermarkProfile {
+    name: String,
+    versions: Vec<VersionEntry>,
+    large_above: u32,
+    logo_value: f32,
+}
+
+impl WatermarkProfile {
+    /// A profile with the given logos as its primary version (id
+    /// [`DEFAULT_VERSION`]), blended toward `logo_value` (255 for a white
+    /// logo). The large logo is used above 1024 px by default.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Config`] if the name is empty, `auto` (reserved for
+    /// automatic selection) or contains characters other than ASCII letters,
+    /// digits, `-`, `_` and `.`, or if `logo_value` is not in `[0, 255]`.
+    pub fn new(
+        name: impl Into<String>,
+        small: LogoSpec,
+        large: LogoSpec,
+        logo_value: f32,
+    ) -> Result<Self> {
+        Self::with_primary(
+            name,
+            LogoVersion::new(DEFAULT_VERSION, small, large)?,
+            logo_value,
+        )
+    }
+
+    /// A profile whose primary version is `version`.
+    ///
+    /// # Errors
+    ///
+    /// See [`new`](Self::new).
+    pub fn with_primary(
+        name: impl Into<String>,
+        version: LogoVersion,
+        logo_value: f32,
+    ) -> Result<Self> {
+        let name = name.into();
+        check_name("profile", &name)?;
+        if !(0.0..=255.0).contains(&logo_value) {
+            return Err(Error::Config(format!(
+                "logo_value must be between 0 and 255, got {logo_value}"
+            )));
+        }
+        Ok(Self {
+            name,
+            versions: vec![VersionEntry::new(version, logo_value)],
+            large_above: DEFAULT_LARGE_ABOVE,
+            logo_value,
+        })
+    }
+
+    /// The built-in Gemini profile: a white sparkle, 48x48 with a 32 px
+    /// margin, or 96x96 with a 64 px margin when both sides exceed 1024 px.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::AlphaMapDecode`] if the embedded PNGs cannot be
+    /// decoded, or [`Error::AlphaMapSize`] if they have unexpected dimensions
+    /// (should never happen unless the binary data is corrupted).
+    pub fn gemini() -> Result<Self> {
+        let small = LogoSpec::new(decode_embedded(alpha_maps::BG_48_PNG, 48)?, 32)?;
+        let large = LogoSpec::new(decode_embedded(alpha_maps::BG_96_PNG, 96)?, 64)?;
+        Self::new(GEMINI, small, large, WHITE)
+    }
+
+    /// Add another version of the logo.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Config`] if the profile already has a version with
+    /// the same id.
+    pub fn with_version(mut self, version: LogoVersion) -> Result<Self> {
+        if self.version(version.id()).is_some() {
+            return Err(Error::Config(format!(
+                "profile `{}` already has logo version `{}`",
+                self.name,
+                version.id()
+            )));
+        }
+        self.versions
+            .push(VersionEntry::new(version, self.logo_value));
+        Ok(self)
+    }
+
+    /// Use the large logo when both image dimensions exceed `pixels`.
+    #[must_use]
+    pub fn with_large_above(mut self, pixels: u32) -> Self {
+        self.large_above = pixels;
+        self
+    }
+
+    /// Load a profile file (see the [type-level docs](Self) for the format).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if the file or a capture cannot be read,
+    /// [`Error::Config`] if the file is malformed, and the errors of
+    /// [`AlphaMap::from_capture`] and [`LogoSpec::new`] for bad captures.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        Self::parse(&text, base).map_err(|e| match e {
+            Error::Config(msg) => Error::Config(format!("{}: {msg}", path.display())),
+            other => other,
+        })
+    }
+
+    /// Parse profile text, resolving capture paths against `base`.
+    ///
+    /// # Errors
+    ///
+    /// See [`load`](Self::load).
+    pub fn parse(text: &str, base: &Path) -> Result<Self> {
+        let mut name = None;
+        let mut primary_id = DEFAULT_VERSION.to_string();
+        let mut primary = VersionKeys::default();
+        let mut others: Vec<(String, VersionKeys)> = Vec::new();
+        let mut large_above = DEFAULT_LARGE_ABOVE;
+        let mut logo_value = WHITE;
+
+        for line in keyvalue::lines(text) {
+            let (line_no, line) = line?;
+            let (key, value) = match line {
+                Line::Section("profile") if others.is_empty() => continue,
+                Line::Section(section) => {
+                    let Some(id) = section.strip_prefix("version.") else {
+                        return Err(keyvalue::line_error(
+                            line_no,
+                            format!("unexpected section `[{section}]`"),
+                        ));
+                    };
+                    others.push((id.trim().to_string(), VersionKeys::default()));
+                    continue;
+                }
+                Line::Pair(key, value) => (key, value),
+            };
+            let number = |value: &str| {
+                value.parse::<u32>().map_err(|_| {
+                    keyvalue::line_error(line_no, format!("`{value}` is not a whole number"))
+                })
+            };
+            let in_version = !others.is_empty();
+            let keys = others.last_mut().map_or(&mut primary, |(_, keys)| keys);
+            match key {
+                "small" => keys.small = Some(base.join(value)),
+                "large" => keys.large = Some(base.join(value)),
+                "small_margin" => keys.small_margin = Some(number(value)?),
+                "large_margin" => keys.large_margin = Some(number(value)?),
+                "name" if !in_version => name = Some(value.to_string()),
+                "version" if !in_version => primary_id = value.to_string(),
+                "large_above" if !in_version => large_above = number(value)?,
+                "logo_value" if !in_version => {
+                    logo_value = value.parse().map_err(|_| {
+                        keyvalue::line_error(line_no, format!("`{value}` is not a number"))
+                    })?;
+                }
+                _ => {
+                    return Err(keyvalue::line_error(
+                        line_no,
+                        format!("unknown key `{key}`"),
+                    ))
+                }
+            }
+        }
+
+        let name = name.ok_or_else(|| Error::Config("missing `name`".to_string()))?;
+        let primary_margin = primary.small_margin;
+        let primary = primary.build(primary_id, None)?;
+        let mut profile = Self::with_primary(name, primary, logo_value)?;
+        for (id, keys) in others {
+            profile = profile.with_version(keys.build(id, primary_margin)?)?;
+        }
+        Ok(profile.with_large_above(large_above))
+    }
+
+    /// The profile's name.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Logo color the alpha maps blend toward (255 for white).
+    #[must_use]
+    pub fn logo_value(&self) -> f32 {
+        self.logo_value
+    }
+
+    /// Image side above which (in both dimensions) the large logo is used.
+    #[must_use]
+    pub fn large_above(&self) -> u32 {
+        self.large_above
+    }
+
+    /// The logo versions, primary first.
+    pub fn versions(&self) -> impl Iterator<Item = &LogoVersion> {
+        self.versions.iter().map(|entry| &entry.version)
+    }
+
+    /// The logo version with the given id.
+    #[must_use]
+    pub fn version(&self, id: &str) -> Option<&LogoVersion> {
+        self.versions().find(|version| version.id() == id)
+    }
+
+    /// The primary version's logo for a watermark size.
+    #[must_use]
+    pub fn logo(&self, size: WatermarkSize) -> &LogoSpec {
+        self.versions[0].version.logo(size)
+    }
+
+    /// The watermark size this profile uses for an image.
+    #[must_use]
+    pub fn size_for(&self, width: u32, height: u32) -> WatermarkSize {
+        if width > self.large_above && height > self.large_above {
+            WatermarkSize::Large
+        } else {
+            WatermarkSize::Small
+        }
+    }
+
+    /// Number of logo versions (at least one).
+    pub(crate) fn version_count(&self) -> usize {
+        self.versions.len()
+    }
+
+    /// The logo version at `index` (registration order).
+    pub(crate) fn version_at(&self, index: usize) -> &LogoVersion {
+        &self.versions[index].version
+    }
+
+    /// Index of the logo version with the given id.
+    pub(crate) fn version_index(&self, id: &str) -> Option<usize> {
+        self.versions().position(|version| version.id() == id)
+    }
+
+    /// Reverse blending table for a logo version and watermark size.
+    pub(crate) fn table(&self, version: usize, size: WatermarkSize) -> &BlendTable {
+        let entry = &self.versions[version];
+        match size {
+            WatermarkSize::Small => &entry.table_small,
+            WatermarkSize::Large => &entry.table_large,
+        }
+    }
+}
+
+/// Logo keys of one version in a profile file.
+#[derive(Default)]
+struct VersionKeys {
+    small: Option<PathBuf>,
+    small_margin: Option<u32>,
+    large: Option<PathBuf>,
+    large_margin: Option<u32>,
+}
+
+impl VersionKeys {
+    /// Load the captures into a [`LogoVersion`]. The small margin falls back
+    /// to `default_margin`, the large logo and margin to the small ones.
+    fn build(self, id: String, default_margin: Option<u32>) -> Result<LogoVersion> {
+        let missing = |key: &str| Error::Config(format!("missing `{key}` for logo version `{id}`"));
+        let small_path = self.small.ok_or_else(|| missing("small"))?;
+        let small_margin = self
+            .small_margin
+            .or(default_margin)
+            .ok_or_else(|| missing("small_margin"))?;
+        let small = LogoSpec::new(load_capture(&small_path)?, small_margin)?;
+        let large_margin = self.large_margin.unwrap_or(small_margin);
+        let large = match self.large {
+            Some(path) => LogoSpec::new(load_capture(&path)?, large_margin)?,
+            None => LogoSpec {
+                margin: large_margin,
+                ..small.clone()
+            },
+        };
+        LogoVersion::new(id, small, large)
+    }
+}
+
+/// Check that a profile or version name is usable on the command line.
+fn check_name(kind: &str, name: &str) -> Result<()> {
+    let valid_char = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.');
+    if name.is_empty() || name == "auto" || !name.chars().all(valid_char) {
+        return Err(Error::Config(format!("invalid {kind} name `{name}`")));
+    }
+    Ok(())
+}
+
+/// Decode an embedded alpha map and check that it is `size` x `size`.
+fn decode_embedded(png_bytes: &[u8], size: u32) -> Result<AlphaMap> {
+    let alpha_map = AlphaMap::from_capture(png_bytes)?;
+    if alpha_map.width() != size || alpha_map.height() != size {
+        return Err(Error::AlphaMapSize {
+            width: alpha_map.width(),
+            height: alpha_map.height(),
+            expected: size,
+        });
+    }
+    Ok(alpha_map)
+}
+
+fn load_capture(path: &Path) -> Result<AlphaMap> {
+    AlphaMap::from_capture(&std::fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disc(size: u32) -> AlphaMap {
+        #[allow(clippy::cast_precision_loss)]
+        let half = size as f32 / 2.0;
+        let values = (0..size * size)
+            .map(|i| {
+                #[allow(clippy::cast_precision_loss)]
+                let (dx, dy) = ((i % size) as f32 - half, (i / size) as f32 - half);
+                if dx * dx + dy * dy < half * half {
+                    0.4
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        AlphaMap::new(values, size, size).unwrap()
+    }
+
+    #[test]
+    fn gemini_profile_matches_embedded_maps() {
+        let gemini = WatermarkProfile::gemini().unwrap();
+        assert_eq!(gemini.name(), GEMINI);
+        assert_eq!(gemini.logo(WatermarkSize::Small).size(), 48);
+        assert_eq!(gemini.logo(WatermarkSize::Large).margin(), 64);
+        assert_eq!(gemini.size_for(1024, 2000), WatermarkSize::Small);
+        assert_eq!(gemini.size_for(1025, 1025), WatermarkSize::Large);
+
+        let err = decode_embedded(alpha_maps::BG_48_PNG, 96).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                Error::AlphaMapSize {
+                    width: 48,
+                    expected: 96,
+                    ..
+                }
+            ),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn new_validates_name_logo_value_and_shape() {
+        let logo = || LogoSpec::new(disc(8), 4).unwrap();
+        assert!(WatermarkProfile::new("acme-v2.1", logo(), logo(), 255.0).is_ok());
+        for name in ["", "auto", "has space", "a/b"] {
+            let err = WatermarkProfile::new(name, logo(), logo(), 255.0).unwrap_err();
+            assert!(matches!(err, Error::Config(_)), "{name:?}: {err:?}");
+        }
+        assert!(WatermarkProfile::new("acme", logo(), logo(), 300.0).is_err());
+
+        let wide = AlphaMap::new(vec![0.5; 8], 4, 2).unwrap();
+        assert!(matches!(
+            LogoSpec::new(wide, 0),
+            Err(Error::InvalidAlphaMap(_))
+        ));
+    }
+
+    #[test]
+    fn load_reads_captures_relative_to_the_file() {
+        let dir = std::env::temp_dir().join(format!("gwr_profile_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        disc(16).save_png(&dir.join("small #1.png")).unwrap();
+        let path = dir.join("acme.conf");
+        std::fs::write(
+            &path,
+            "[profile]\nname = acme  # test logo\nsmall = \"small #1.png\"\nsmall_margin = 8\nlarge_above = 500\nlogo_value = 240\n",
+        )
+        .unwrap();
+
+        let profile = WatermarkProfile::load(&path).unwrap();
+        assert_eq!(profile.name(), "acme");
+        assert_eq!(profile.large_above(), 500);
+        assert!((profile.logo_value() - 240.0).abs() < f32::EPSILON);
+        // Without a large logo, both sizes share the small one
+        let large = profile.logo(WatermarkSize::Large);
+        assert_eq!((large.size(), large.margin()), (16, 8));
+
+        assert_eq!(profile.versions().count(), 1);
+        assert_eq!(profile.version(DEFAULT_VERSION).unwrap().id(), "v1");
+
+        disc(8).save_png(&dir.join("old#2019.png")).unwrap();
+        std::fs::write(
+            &path,
+            "name = acme\nversion = 2024\nsmall = \"small #1.png\"\nsmall_margin = 8\n\n[version.2019]\nsmall = old#2019.png\nlarge_margin = 4\n",
+        )
+        .unwrap();
+        let profile = WatermarkProfile::load(&path).unwrap();
+        let ids: Vec<_> = profile.versions().map(LogoVersion::id).collect();
+        assert_eq!(ids, ["2024", "2019"]);
+        let old = profile.version("2019").unwrap();
+        let (small, large) = (
+            old.logo(WatermarkSize::Small),
+            old.logo(WatermarkSize::Large),
+        );
+        assert_eq!((small.size(), small.margin()), (8, 8));
+        assert_eq!((large.size(), large.margin()), (8, 4));
+        assert_eq!(profile.logo(WatermarkSize::Small).size(), 16);
+
+        std::fs::write(
+            &path,
+            "name = acme\nsmall = \"small #1.png\"\nsmall_margin = 8\n[version.old]\nname = x\n",
+        )
+        .unwrap();
+        let err = WatermarkProfile::load(&path).unwrap_err();
+        assert!(
+            err.to_string().contains("line 5: unknown key `name`"),
+            "{err}"
+        );
+        std::fs::write(
+            &path,
+            "name = acme\nsmall = \"small #1.png\"\nsmall_margin = 8\n[version.v1]\nsmall = old#2019.png\n",
+        )
+        .unwrap();
+        let err = WatermarkProfile::load(&path).unwrap_err();
+        assert!(
+            err.to_string().contains("already has logo version `v1`"),
+            "{err}"
+        );
+
+        std::fs::write(&path, "name = acme\nsmall = \"small #1.png\"\n").unwrap();
+        let err = WatermarkProfile::load(&path).unwrap_err();
+        assert!(err.to_string().contains("small_margin"), "{err}");
+        std::fs::write(&path, "name = acme\ncolor = red\n").unwrap();
+        let err = WatermarkProfile::load(&path).unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{err}");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
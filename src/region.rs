@@ -0,0 +1,199 @@
+//! Explicit watermark placement.
+//!
+//! By default the engine puts the logo where the active profile says it is
+//! (bottom-right, at the profile's margin and size). A [`RemovalRegion`]
+//! overrides that for images where the logo was moved or rescaled: it names
+//! the corner the offsets are measured from and, optionally, the logo's side
+//! length in pixels. Apply one with
+//! [`WatermarkEngine::with_region`](crate::WatermarkEngine::with_region).
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::Error;
+
+/// Image corner a [`RemovalRegion`] is measured from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Anchor {
+    /// Offsets from the left and top edges to the logo's top-left corner.
+    #[default]
+    TopLeft,
+    /// Offsets from the right and top edges to the logo's top-right corner.
+    TopRight,
+    /// Offsets from the left and bottom edges to the logo's bottom-left corner.
+    BottomLeft,
+    /// Offsets from the right and bottom edges to the logo's bottom-right
+    /// corner, like a profile's margin.
+    BottomRight,
+}
+
+impl Anchor {
+    fn prefix(self) -> &'static str {
+        match self {
+            Self::TopLeft => "tl",
+            Self::TopRight => "tr",
+            Self::BottomLeft => "bl",
+            Self::BottomRight => "br",
+        }
+    }
+}
+
+/// Where the watermark is, and optionally how large, in an image.
+///
+/// Parses from `X,Y` (top-left corner in pixels) or an anchored form
+/// `tl:X,Y`, `tr:X,Y`, `bl:X,Y` or `br:X,Y`, where the offsets are measured
+/// from the named corner; `br:32,32` is the default Gemini placement for
+/// small images.
+///
+/// ```
+/// use gemini_watermark_removal::region::{Anchor, RemovalRegion};
+///
+/// let region: RemovalRegion = "br:32,32".parse().unwrap();
+/// assert_eq!(region, RemovalRegion::anchored(Anchor::BottomRight, 32, 32));
+/// assert_eq!(region.with_size(64).position(1000, 800, 64), (904, 704));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RemovalRegion {
+    /// Corner the offsets are measured from.
+    pub anchor: Anchor,
+    /// Horizontal offset from the anchor corner in pixels.
+    pub x: u32,
+    /// Vertical offset from the anchor corner in pixels.
+    pub y: u32,
+    /// Logo side length in pixels (`None` keeps the profile's logo size).
+    /// Other sizes rescale the profile's alpha map.
+    pub size: Option<u32>,
+}
+
+impl RemovalRegion {
+    /// A logo whose top-left corner is at `(x, y)`.
+    #[must_use]
+    pub fn at(x: u32, y: u32) -> Self {
+        Self::anchored(Anchor::TopLeft, x, y)
+    }
+
+    /// A logo `x` and `y` pixels in from the `anchor` corner.
+    #[must_use]
+    pub fn anchored(anchor: Anchor, x: u32, y: u32) -> Self {
+        Self {
+            anchor,
+            x,
+            y,
+            size: None,
+        }
+    }
+
+    /// Set the logo's side length in pixels.
+    #[must_use]
+    pub fn with_size(mut self, size: u32) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Top-left corner of a `size` x `size` logo in a `width` x `height`
+    /// image. Offsets from the right or bottom saturate at the image edge;
+    /// use [`fits`](Self::fits) to check that the logo lies inside.
+    #[must_use]
+    pub fn position(&self, width: u32, height: u32, size: u32) -> (u32, u32) {
+        let from_far =
+            |extent: u32, offset: u32| extent.saturating_sub(offset.saturating_add(size));
+        match self.anchor {
+            Anchor::TopLeft => (self.x, self.y),
+            Anchor::TopRight => (from_far(width, self.x), self.y),
+            Anchor::BottomLeft => (self.x, from_far(height, self.y)),
+            Anchor::BottomRight => (from_far(width, self.x), from_far(height, self.y)),
+        }
+    }
+
+    /// Whether a `size` x `size` logo lies fully inside a `width` x `height`
+    /// image.
+    #[must_use]
+    pub fn fits(&self, width: u32, height: u32, size: u32) -> bool {
+        let inside =
+            |extent: u32, offset: u32| offset.checked_add(size).is_some_and(|end| end <= extent);
+        inside(width, self.x) && inside(height, self.y)
+    }
+}
+
+impl fmt::Display for RemovalRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{},{}", self.anchor.prefix(), self.x, self.y)?;
+        if let Some(size) = self.size {
+            write!(f, " ({size}x{size})")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for RemovalRegion {
+    type Err = Error;
+
+    /// Parse `X,Y` or `tl|tr|bl|br:X,Y` (without a size).
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let invalid = || {
+            Error::Config(format!(
+                "invalid position `{s}`: expected X,Y or tl|tr|bl|br:X,Y"
+            ))
+        };
+        let (anchor, offsets) = match s.split_once(':') {
+            Some((prefix, offsets)) => {
+                let anchor = match prefix.trim().to_ascii_lowercase().as_str() {
+                    "tl" => Anchor::TopLeft,
+                    "tr" => Anchor::TopRight,
+                    "bl" => Anchor::BottomLeft,
+                    "br" => Anchor::BottomRight,
+                    _ => return Err(invalid()),
+                };
+                (anchor, offsets)
+            }
+            None => (Anchor::TopLeft, s),
+        };
+        let (x, y) = offsets.split_once(',').ok_or_else(invalid)?;
+        let x = x.trim().parse().map_err(|_| invalid())?;
+        let y = y.trim().parse().map_err(|_| invalid())?;
+        Ok(Self::anchored(anchor, x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_plain_and_anchored_positions() {
+        assert_eq!(
+            "10,20".parse::<RemovalRegion>().unwrap(),
+            RemovalRegion::at(10, 20)
+        );
+        assert_eq!(
+            " TR: 5 , 6".parse::<RemovalRegion>().unwrap(),
+            RemovalRegion::anchored(Anchor::TopRight, 5, 6)
+        );
+        for bad in ["", "10", "10,", "-1,2", "xx:1,2", "br:1;2"] {
+            let err = bad.parse::<RemovalRegion>().unwrap_err();
+            assert!(matches!(err, Error::Config(_)), "{bad:?}: {err:?}");
+        }
+
+        let region = RemovalRegion::anchored(Anchor::BottomLeft, 3, 4);
+        assert_eq!(region.to_string(), "bl:3,4");
+        assert_eq!(region.to_string().parse::<RemovalRegion>().unwrap(), region);
+        assert_eq!(region.with_size(9).to_string(), "bl:3,4 (9x9)");
+    }
+
+    #[test]
+    fn position_is_measured_from_the_anchor_corner() {
+        let (w, h, size) = (200, 100, 20);
+        let at = |anchor| RemovalRegion::anchored(anchor, 10, 5).position(w, h, size);
+        assert_eq!(at(Anchor::TopLeft), (10, 5));
+        assert_eq!(at(Anchor::TopRight), (170, 5));
+        assert_eq!(at(Anchor::BottomLeft), (10, 75));
+        assert_eq!(at(Anchor::BottomRight), (170, 75));
+
+        let region = RemovalRegion::anchored(Anchor::BottomRight, 10, 90);
+        assert!(!region.fits(w, h, size));
+        assert_eq!(region.position(w, h, size), (170, 0));
+        assert!(RemovalRegion::at(180, 80).fits(w, h, size));
+        assert!(!RemovalRegion::at(181, 80).fits(w, h, size));
+        assert!(!RemovalRegion::at(u32::MAX, 0).fits(w, h, size));
+    }
+}
@@ -0,0 +1,279 @@
+//! Forward watermark synthesis for building test fixtures.
+//!
+//! [`WatermarkEngine::apply`] stamps the Gemini logo onto a clean image the
+//! same way Gemini does, so the untouched original and the stamped copy form a
+//! ground-truth pair for regression tests and accuracy measurements. Optional
+//! offset, scale, noise and JPEG re-encoding reproduce the variations seen in
+//! real-world images.
+
+use std::borrow::Cow;
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::{ImageFormat, RgbImage};
+
+use crate::alpha_map::ResizeFilter;
+use crate::blending;
+use crate::engine::{WatermarkEngine, WatermarkSize};
+use crate::error::{Error, Result};
+
+/// Options controlling how a synthetic watermark is applied.
+#[derive(Debug, Clone)]
+pub struct SynthesisOptions {
+    /// Force a specific watermark size instead of choosing from the image dimensions.
+    pub force_size: Option<WatermarkSize>,
+    /// Pixel offset `(dx, dy)` added to the engine's computed position.
+    pub offset: (i32, i32),
+    /// Scale factor for the logo (1.0 = native size). The scaled logo keeps
+    /// the standard margin from the bottom-right corner.
+    pub scale: f32,
+    /// Standard deviation of Gaussian noise added to every pixel, in 0-255 units.
+    pub noise: f32,
+    /// Seed for the noise generator; the same seed yields the same image.
+    pub seed: u64,
+    /// Re-encode the result as JPEG at this quality (1-100).
+    pub jpeg_quality: Option<u8>,
+}
+
+impl Default for SynthesisOptions {
+    fn default() -> Self {
+        Self {
+            force_size: None,
+            offset: (0, 0),
+            scale: 1.0,
+            noise: 0.0,
+            seed: 0,
+            jpeg_quality: None,
+        }
+    }
+}
+
+impl WatermarkEngine {
+    /// Stamp the watermark onto a clean image in-place.
+    ///
+    /// With default options this is the exact inverse of
+    /// [`remove`](Self::remove): the logo is blended at the position the
+    /// engine expects it. Noise is applied after blending and JPEG
+    /// re-encoding last, mirroring how images degrade after generation.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidArgument`] if `scale` is not a positive finite
+    /// number, and
+    /// [`Error::Image`] if JPEG re-encoding fails.
+    pub fn apply(&self, image: &mut RgbImage, opts: &SynthesisOptions) -> Result<()> {
+        if !(opts.scale.is_finite() && opts.scale > 0.0) {
+            return Err(Error::InvalidArgument(format!(
+                "synthesis scale must be positive, got {}",
+                opts.scale
+            )));
+        }
+
+        let (img_w, img_h) = image.dimensions();
+        let (wm_size, margin, alpha_map) = self.config(img_w, img_h, opts.force_size);
+
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )]
+        let scaled = ((wm_size as f32 * opts.scale).round() as u32).max(1);
+        let alpha_map = if scaled == wm_size {
+            alpha_map
+        } else {
+            Cow::Owned(alpha_map.resize(scaled, scaled, ResizeFilter::Bilinear)?)
+        };
+
+        let (pos_x, pos_y) = self.position(img_w, img_h, scaled, margin);
+        let shift = |pos: u32, delta: i32| {
+            u32::try_from((i64::from(pos) + i64::from(delta)).max(0)).unwrap_or(u32::MAX)
+        };
+        let pos_x = shift(pos_x, opts.offset.0);
+        let pos_y = shift(pos_y, opts.offset.1);
+
+        // Skip the region when the offset leaves it too far off-image to clip
+        if pos_x.checked_add(scaled).is_some() && pos_y.checked_add(scaled).is_some() {
+            blending::apply_watermark_alpha_blend(
+                image,
+                &alpha_map,
+                pos_x,
+                pos_y,
+                self.profile().logo_value(),
+            )?;
+        }
+
+        if opts.noise > 0.0 {
+            add_gaussian_noise(image, opts.noise, opts.seed);
+        }
+
+        if let Some(quality) = opts.jpeg_quality {
+            *image = jpeg_round_trip(image, quality.clamp(1, 100))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Add zero-mean Gaussian noise with standard deviation `sigma` to every channel.
+fn add_gaussian_noise(image: &mut RgbImage, sigma: f32, seed: u64) {
+    let mut rng = SplitMix64(seed);
+    for px in image.pixels_mut() {
+        for ch in 0..3 {
+            let noisy = f32::from(px[ch]) + rng.next_gaussian() * sigma;
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            {
+                px[ch] = noisy.round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+}
+
+/// Encode as JPEG at `quality` and decode again.
+fn jpeg_round_trip(image: &RgbImage, quality: u8) -> Result<RgbImage> {
+    let mut encoded = Vec::new();
+    image.write_with_encoder(JpegEncoder::new_with_quality(&mut encoded, quality))?;
+    let decoded = image::load(Cursor::new(encoded), ImageFormat::Jpeg)?;
+    Ok(decoded.to_rgb8())
+}
+
+/// Small deterministic PRNG, so fixtures are reproducible across platforms
+/// and do not depend on an external crate.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform sample in `(0, 1]`.
+    fn next_unit(&mut self) -> f64 {
+        #[allow(clippy::cast_precision_loss)]
+        let unit = ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64;
+        unit
+    }
+
+    /// Standard normal sample (Box-Muller).
+    fn next_gaussian(&mut self) -> f32 {
+        let (u1, u2) = (self.next_unit(), self.next_unit());
+        let z = (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos();
+        #[allow(clippy::cast_possible_truncation)]
+        let z = z as f32;
+        z
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProcessOptions;
+
+    fn gradient(w: u32, h: u32) -> RgbImage {
+        #[allow(clippy::cast_possible_truncation)]
+        RgbImage::from_fn(w, h, |x, y| {
+            image::Rgb([(x % 200) as u8, (y % 200) as u8, ((x + y) % 150) as u8])
+        })
+    }
+
+    fn max_diff(a: &RgbImage, b: &RgbImage) -> u8 {
+        a.pixels()
+            .zip(b.pixels())
+            .flat_map(|(p, q)| (0..3).map(move |c| p[c].abs_diff(q[c])))
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn apply_then_remove_recovers_original() {
+        let engine = WatermarkEngine::new().unwrap();
+        let original = gradient(400, 300);
+        let mut img = original.clone();
+
+        engine
+            .apply(&mut img, &SynthesisOptions::default())
+            .unwrap();
+        assert!(max_diff(&img, &original) > 50, "Logo should be visible");
+
+        let opts = ProcessOptions::default();
+        assert!(engine.detect(&img, &opts).detected);
+
+        engine.remove(&mut img, None);
+        assert!(max_diff(&img, &original) <= 2);
+    }
+
+    #[test]
+    fn apply_with_offset_moves_the_logo() {
+        let engine = WatermarkEngine::new().unwrap();
+        let original = gradient(400, 300);
+        let mut img = original.clone();
+        let opts = SynthesisOptions {
+            offset: (-100, -100),
+            ..SynthesisOptions::default()
+        };
+        engine.apply(&mut img, &opts).unwrap();
+
+        // Default region (320..368, 220..268) is untouched
+        let corner = image::imageops::crop_imm(&img, 320, 220, 48, 48).to_image();
+        let clean = image::imageops::crop_imm(&original, 320, 220, 48, 48).to_image();
+        assert_eq!(corner, clean);
+        assert!(max_diff(&img, &original) > 50);
+    }
+
+    #[test]
+    fn apply_with_scale_and_large_offset_stays_in_bounds() {
+        let engine = WatermarkEngine::new().unwrap();
+        let mut img = gradient(100, 100);
+        let opts = SynthesisOptions {
+            scale: 1.5,
+            offset: (1_000, i32::MIN),
+            ..SynthesisOptions::default()
+        };
+        engine.apply(&mut img, &opts).unwrap();
+
+        let opts = SynthesisOptions {
+            scale: 0.0,
+            ..SynthesisOptions::default()
+        };
+        assert!(matches!(
+            engine.apply(&mut img, &opts),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn noise_and_jpeg_are_deterministic_for_a_seed() {
+        let engine = WatermarkEngine::new().unwrap();
+        let opts = SynthesisOptions {
+            noise: 3.0,
+            seed: 42,
+            jpeg_quality: Some(85),
+            ..SynthesisOptions::default()
+        };
+
+        let mut a = gradient(200, 200);
+        let mut b = gradient(200, 200);
+        engine.apply(&mut a, &opts).unwrap();
+        engine.apply(&mut b, &opts).unwrap();
+        assert_eq!(a, b);
+
+        let mut c = gradient(200, 200);
+        let other_seed = SynthesisOptions { seed: 7, ..opts };
+        engine.apply(&mut c, &other_seed).unwrap();
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn gaussian_noise_has_expected_spread() {
+        let mut rng = SplitMix64(1);
+        let samples: Vec<f32> = (0..10_000).map(|_| rng.next_gaussian()).collect();
+        #[allow(clippy::cast_precision_loss)]
+        let n = samples.len() as f32;
+        let mean = samples.iter().sum::<f32>() / n;
+        let var = samples.iter().map(|s| (s - mean).powi(2)).sum::<f32>() / n;
+        assert!(mean.abs() < 0.05, "mean = {mean}");
+        assert!((var - 1.0).abs() < 0.1, "var = {var}");
+    }
+}
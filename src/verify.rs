@@ -0,0 +1,545 @@
+//! Post-removal verification and retry.
+//!
+//! After reverse blending, detection is re-run on the cleaned region. A
+//! strong remaining correlation with the alpha map means the removal used the
+//! wrong size, position or logo value. With [`ProcessOptions::retry`] the
+//! engine then searches nearby alternatives (other size, then offsets, then
+//! dimmer logo values) and keeps the one that leaves the smallest residual.
+
+use std::borrow::Cow;
+
+use image::RgbImage;
+
+use crate::blending;
+use crate::detection::{self, DetectionConfig};
+use crate::engine::{ProcessOptions, WatermarkEngine, WatermarkSize};
+
+/// Largest position offset tried in each direction, in pixels.
+const MAX_OFFSET: i32 = 2;
+
+/// Offsets from the profile's logo value tried after size and position, for
+/// logos dimmed by re-encoding or color conversion. They move the value toward
+/// mid-gray: down for a bright logo, up for a dark one.
+const LOGO_VALUE_OFFSETS: [f32; 4] = [5.0, 10.0, 15.0, 25.0];
+
+/// Fainter ghosts, in 8-bit levels at full alpha, are not reported as a
+/// residual: they are below what 8-bit output can show.
+const MIN_GHOST_LEVELS: f32 = 2.0;
+
+/// How much of the logo an attempt left behind.
+#[derive(Debug, Clone, Copy, Default)]
+struct Residual {
+    /// Detection confidence for a bright or dark ghost of the logo.
+    confidence: f32,
+    /// Correlation of the cleaned region's edges with the logo's edges; finer
+    /// grained than `confidence`, used to rank attempts.
+    edges: f32,
+}
+
+/// Parameters of one removal attempt.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Attempt {
+    size: WatermarkSize,
+    dx: i32,
+    dy: i32,
+    logo_value: f32,
+}
+
+/// Outcome of [`WatermarkEngine::remove_verified`].
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Verification {
+    /// Residual confidence of the kept attempt (`None` when not verified).
+    pub(crate) residual: Option<f32>,
+    /// The kept attempt and its logo size in pixels, if it differs from the
+    /// default removal.
+    retried: Option<(Attempt, u32)>,
+    /// Number of removal attempts made.
+    attempts: usize,
+    /// Watermark size and top-left corner of the kept attempt.
+    pub(crate) placement: (WatermarkSize, u32, u32),
+}
+
+impl Verification {
+    /// Whether the residual exceeds the options' residual threshold.
+    pub(crate) fn has_residual(&self, opts: &ProcessOptions) -> bool {
+        self.residual.is_some_and(|r| r > opts.residual_threshold)
+    }
+
+    /// Human-readable summary for [`ProcessResult::message`](crate::ProcessResult::message).
+    pub(crate) fn message(&self) -> String {
+        let mut message = "Watermark removed".to_string();
+        if let Some((a, size)) = self.retried {
+            message = format!(
+                "{message} (retry: {size}x{size}, offset {:+},{:+}, logo {:.0})",
+                a.dx, a.dy, a.logo_value
+            );
+        }
+        if let Some(residual) = self.residual {
+            message = format!("{message}, residual {:.0}%", residual * 100.0);
+            if self.attempts > 1 {
+                message = format!("{message} after {} attempts", self.attempts);
+            }
+        }
+        message
+    }
+}
+
+impl WatermarkEngine {
+    /// Remove the watermark, then verify and optionally retry per `opts`.
+    pub(crate) fn remove_verified(
+        &self,
+        image: &mut RgbImage,
+        opts: &ProcessOptions,
+    ) -> Verification {
+        let default = Attempt {
+            size: opts
+                .force_size
+                .unwrap_or_else(|| self.watermark_size_for(image.width(), image.height())),
+            dx: 0,
+            dy: 0,
+            logo_value: self.profile().logo_value(),
+        };
+
+        if !opts.verify {
+            self.remove_attempt(image, default);
+            return Verification {
+                residual: None,
+                retried: None,
+                attempts: 1,
+                placement: self.placement(image, default),
+            };
+        }
+
+        let original = opts.retry.then(|| image.clone());
+        self.remove_attempt(image, default);
+        let mut best = (default, self.residual(image, default, default, opts));
+        let mut attempts = 1;
+
+        if let Some(original) = original {
+            if best.1.confidence > opts.residual_threshold {
+                let mut last = default;
+                // Greedy search: each stage starts from the best attempt so far
+                for stage in 0..3 {
+                    let size_forced = opts.force_size.is_some();
+                    for attempt in candidates(stage, best.0, default.logo_value, size_forced) {
+                        if !self.fits(image, attempt) {
+                            continue;
+                        }
+                        restore_region(image, &original, self.region(image, last));
+                        self.remove_attempt(image, attempt);
+                        last = attempt;
+                        attempts += 1;
+
+                        let residual = self.residual(image, attempt, default, opts);
+                        if residual.edges < best.1.edges {
+                            best = (attempt, residual);
+                        }
+                    }
+                    if best.1.confidence <= opts.residual_threshold {
+                        break;
+                    }
+                }
+                if last != best.0 {
+                    restore_region(image, &original, self.region(image, last));
+                    self.remove_attempt(image, best.0);
+                }
+            }
+        }
+
+        let (w, h) = image.dimensions();
+        Verification {
+            residual: Some(best.1.confidence),
+            retried: (best.0 != default).then(|| (best.0, self.config(w, h, Some(best.0.size)).0)),
+            attempts,
+            placement: self.placement(image, best.0),
+        }
+    }
+
+    /// Size and top-left corner of an attempt that fits the image.
+    fn placement(&self, image: &RgbImage, attempt: Attempt) -> (WatermarkSize, u32, u32) {
+        let (x, y, _) = self.region(image, attempt);
+        (
+            attempt.size,
+            u32::try_from(x).unwrap_or_default(),
+            u32::try_from(y).unwrap_or_default(),
+        )
+    }
+
+    /// Watermark rectangle `(x, y, size)` for an attempt, before bounds checks.
+    fn region(&self, image: &RgbImage, attempt: Attempt) -> (i64, i64, u32) {
+        let (w, h) = image.dimensions();
+        let (wm_size, margin, _) = self.config(w, h, Some(attempt.size));
+        let (x, y) = self.position(w, h, wm_size, margin);
+        (
+            i64::from(x) + i64::from(attempt.dx),
+            i64::from(y) + i64::from(attempt.dy),
+            wm_size,
+        )
+    }
+
+    /// Whether the attempt's watermark lies fully inside the image.
+    fn fits(&self, image: &RgbImage, attempt: Attempt) -> bool {
+        let (x, y, size) = self.region(image, attempt);
+        x >= 0
+            && y >= 0
+            && x + i64::from(size) <= i64::from(image.width())
+            && y + i64::from(size) <= i64::from(image.height())
+    }
+
+    fn remove_attempt(&self, image: &mut RgbImage, attempt: Attempt) {
+        let (x, y, size) = self.region(image, attempt);
+        let (wm_size, _, alpha_map) =
+            self.config(image.width(), image.height(), Some(attempt.size));
+        debug_assert_eq!(size, wm_size);
+        let (Ok(x), Ok(y)) = (u32::try_from(x), u32::try_from(y)) else {
+            return;
+        };
+        match alpha_map {
+            // The cached table holds the nominal logo at the profile's logo value
+            Cow::Borrowed(_)
+                if (attempt.logo_value - self.profile().logo_value()).abs() < f32::EPSILON =>
+            {
+                self.blend_table(attempt.size).remove(image, x, y);
+            }
+            // Attempts stay within the image, so the region cannot overflow
+            _ => {
+                blending::remove_watermark_alpha_blend(image, &alpha_map, x, y, attempt.logo_value)
+                    .ok();
+            }
+        }
+    }
+
+    /// Residual after an attempt: the stronger of the residuals at the
+    /// attempt's own position and at the default position, so an attempt that
+    /// misses the real logo cannot hide it by looking elsewhere.
+    fn residual(
+        &self,
+        image: &RgbImage,
+        attempt: Attempt,
+        default: Attempt,
+        opts: &ProcessOptions,
+    ) -> Residual {
+        let own = self.residual_at(image, attempt, opts);
+        if attempt.size == default.size && attempt.dx == default.dx && attempt.dy == default.dy {
+            own
+        } else {
+            let other = self.residual_at(image, default, opts);
+            Residual {
+                confidence: own.confidence.max(other.confidence),
+                edges: own.edges.max(other.edges),
+            }
+        }
+    }
+
+    /// Residual of a bright or dark copy of the logo at the attempt's
+    /// position. Over-subtraction (wrong position or logo value) leaves a dark
+    /// ghost, which detection only sees in the inverted image.
+    fn residual_at(&self, image: &RgbImage, attempt: Attempt, opts: &ProcessOptions) -> Residual {
+        let (x, y, size) = self.region(image, attempt);
+        let (_, _, alpha_map) = self.config(image.width(), image.height(), Some(attempt.size));
+        let (Ok(x), Ok(y)) = (u32::try_from(x), u32::try_from(y)) else {
+            return Residual::default();
+        };
+
+        // Crop the watermark with every neighborhood detection reads: the
+        // strips above and to the left (variance references) and the ring
+        // around it (variance reference and model fit background)
+        let before = size.max(detection::RING_WIDTH);
+        let (left, top) = (x.saturating_sub(before), y.saturating_sub(before));
+        let right = (x + size + detection::RING_WIDTH).min(image.width());
+        let bottom = (y + size + detection::RING_WIDTH).min(image.height());
+        let mut window =
+            image::imageops::crop_imm(image, left, top, right - left, bottom - top).to_image();
+        let (wx, wy) = (x - left, y - top);
+        // A ghost left by a mismatched removal is not a blend toward the logo
+        // value, so the color and model fits cannot see it; only the shape
+        // and texture stages score the residual
+        let config = DetectionConfig {
+            color_weight: 0.0,
+            model_weight: 0.0,
+            threshold: opts.residual_threshold,
+            ..opts.detection
+        };
+        let logo_value = self.profile().logo_value();
+        let detect = |img: &RgbImage| {
+            detection::detect_watermark(img, &alpha_map, wx, wy, logo_value, &config)
+                .map_or(0.0, |result| result.confidence)
+        };
+
+        // Edge magnitude is sign-free, so bright and dark ghosts both count
+        let n = size as usize;
+        let gray = detection::region_to_grayscale(&window, wx, wy, size, size);
+        let edges = detection::ncc(
+            &detection::sobel_magnitude(&gray, n, n),
+            &detection::sobel_magnitude(alpha_map.values(), n, n),
+        )
+        .max(0.0);
+
+        // Shape scores ignore amplitude, so a sub-quantization ghost such as
+        // the rounding error of an exact removal on a flat background would
+        // correlate with the logo
+        if ghost_levels(&gray, alpha_map.values()).abs() < MIN_GHOST_LEVELS {
+            return Residual {
+                confidence: 0.0,
+                edges,
+            };
+        }
+        let bright = detect(&window);
+        image::imageops::invert(&mut window);
+        Residual {
+            confidence: bright.max(detect(&window)),
+            edges,
+        }
+    }
+}
+
+/// Least-squares slope of the region's luminance on the alpha map, in 8-bit
+/// levels: how bright (positive) or dark (negative) a copy of the logo at full
+/// alpha the region contains.
+fn ghost_levels(gray: &[f32], alpha: &[f32]) -> f32 {
+    let n = gray.len().min(alpha.len());
+    if n == 0 {
+        return 0.0;
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let count = n as f64;
+    let mean_g = gray[..n].iter().map(|&v| f64::from(v)).sum::<f64>() / count;
+    let mean_a = alpha[..n].iter().map(|&v| f64::from(v)).sum::<f64>() / count;
+    let (mut cov, mut var) = (0.0, 0.0);
+    for (&g, &a) in gray[..n].iter().zip(&alpha[..n]) {
+        let da = f64::from(a) - mean_a;
+        cov += (f64::from(g) - mean_g) * da;
+        var += da * da;
+    }
+    if var <= f64::EPSILON {
+        return 0.0;
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    let levels = (cov / var * 255.0) as f32;
+    levels
+}
+
+/// Alternatives to try in a search stage, derived from the best attempt so far
+/// and the profile's `logo_value`.
+fn candidates(stage: usize, best: Attempt, logo_value: f32, size_forced: bool) -> Vec<Attempt> {
+    match stage {
+        0 if !size_forced => {
+            let size = match best.size {
+                WatermarkSize::Small => WatermarkSize::Large,
+                WatermarkSize::Large => WatermarkSize::Small,
+            };
+            vec![Attempt { size, ..best }]
+        }
+        1 => (-MAX_OFFSET..=MAX_OFFSET)
+            .flat_map(|dy| (-MAX_OFFSET..=MAX_OFFSET).map(move |dx| (dx, dy)))
+            .filter(|&(dx, dy)| (dx, dy) != (best.dx, best.dy))
+            .map(|(dx, dy)| Attempt { dx, dy, ..best })
+            .collect(),
+        2 => LOGO_VALUE_OFFSETS
+            .iter()
+            .map(|&offset| {
+                let toward_gray = if logo_value >= 127.5 { -offset } else { offset };
+                (logo_value + toward_gray).clamp(0.0, 255.0)
+            })
+            .filter(|&v| (v - best.logo_value).abs() > f32::EPSILON)
+            .map(|logo_value| Attempt { logo_value, ..best })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Copy a square region back from `original`, clipped to the image.
+fn restore_region(image: &mut RgbImage, original: &RgbImage, region: (i64, i64, u32)) {
+    let (x, y, size) = region;
+    let x0 = u32::try_from(x.max(0)).unwrap_or(u32::MAX);
+    let y0 = u32::try_from(y.max(0)).unwrap_or(u32::MAX);
+    let x1 = u32::try_from((x + i64::from(size)).max(0))
+        .unwrap_or(u32::MAX)
+        .min(image.width());
+    let y1 = u32::try_from((y + i64::from(size)).max(0))
+        .unwrap_or(u32::MAX)
+        .min(image.height());
+    for py in y0..y1 {
+        for px in x0..x1 {
+            image.put_pixel(px, py, *original.get_pixel(px, py));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synthesis::SynthesisOptions;
+
+    fn stamped(opts: &SynthesisOptions) -> (WatermarkEngine, RgbImage) {
+        let engine = WatermarkEngine::new().unwrap();
+        #[allow(clippy::cast_possible_truncation)]
+        let mut img = RgbImage::from_fn(400, 300, |x, y| {
+            image::Rgb([(x % 200) as u8, (y % 200) as u8, ((x + y) % 150) as u8])
+        });
+        engine.apply(&mut img, opts).unwrap();
+        (engine, img)
+    }
+
+    #[test]
+    fn verification_reports_low_residual_for_exact_removal() {
+        let (engine, mut img) = stamped(&SynthesisOptions::default());
+        let opts = ProcessOptions {
+            verify: true,
+            ..ProcessOptions::default()
+        };
+        let verification = engine.remove_verified(&mut img, &opts);
+
+        assert!(verification.residual.is_some());
+        assert!(!verification.has_residual(&opts));
+        assert_eq!(verification.retried, None);
+        assert_eq!(verification.attempts, 1);
+        assert!(verification.message().starts_with("Watermark removed"));
+    }
+
+    #[test]
+    fn verification_flags_shifted_logo_without_retry() {
+        let (engine, mut img) = stamped(&SynthesisOptions {
+            offset: (2, -2),
+            ..SynthesisOptions::default()
+        });
+        let opts = ProcessOptions {
+            verify: true,
+            ..ProcessOptions::default()
+        };
+        let verification = engine.remove_verified(&mut img, &opts);
+        assert!(verification.has_residual(&opts), "{verification:?}");
+    }
+
+    #[test]
+    fn retry_finds_shifted_logo() {
+        let (engine, mut img) = stamped(&SynthesisOptions {
+            offset: (2, -2),
+            ..SynthesisOptions::default()
+        });
+        let opts = ProcessOptions {
+            verify: true,
+            retry: true,
+            ..ProcessOptions::default()
+        };
+        let verification = engine.remove_verified(&mut img, &opts);
+
+        assert!(!verification.has_residual(&opts), "{verification:?}");
+        let (retried, size) = verification.retried.unwrap();
+        assert_eq!((retried.dx, retried.dy, size), (2, -2, 48));
+        assert!(verification.message().contains("48x48, offset +2,-2"));
+
+        // The kept attempt is what ends up in the image
+        assert!(engine.detect(&img, &opts).confidence <= opts.residual_threshold);
+    }
+
+    #[test]
+    fn verification_disabled_reports_no_residual() {
+        let (engine, mut img) = stamped(&SynthesisOptions::default());
+        let opts = ProcessOptions {
+            verify: false,
+            ..ProcessOptions::default()
+        };
+        let verification = engine.remove_verified(&mut img, &opts);
+        assert_eq!(verification.residual, None);
+        assert_eq!(verification.message(), "Watermark removed");
+    }
+
+    #[test]
+    fn offset_candidates_skip_current_position() {
+        let best = Attempt {
+            size: WatermarkSize::Small,
+            dx: 0,
+            dy: 0,
+            logo_value: 255.0,
+        };
+        let offsets = candidates(1, best, 255.0, false);
+        assert_eq!(offsets.len(), 24);
+        assert!(!offsets.contains(&best));
+        assert!(candidates(0, best, 255.0, true).is_empty());
+    }
+
+    #[test]
+    fn logo_value_candidates_are_offsets_from_the_profile() {
+        let best = Attempt {
+            size: WatermarkSize::Small,
+            dx: 0,
+            dy: 0,
+            logo_value: 255.0,
+        };
+        let values = |logo_value| -> Vec<f32> {
+            candidates(2, best, logo_value, false)
+                .iter()
+                .map(|a| a.logo_value)
+                .collect()
+        };
+        assert_eq!(values(255.0), [250.0, 245.0, 240.0, 230.0]);
+        assert_eq!(values(180.0), [175.0, 170.0, 165.0, 155.0]);
+        assert_eq!(values(20.0), [25.0, 30.0, 35.0, 45.0]);
+        // The attempt already in use is not repeated
+        let dimmed = Attempt {
+            logo_value: 245.0,
+            ..best
+        };
+        assert_eq!(candidates(2, dimmed, 255.0, false).len(), 3);
+    }
+
+    #[test]
+    fn residual_matches_detection_on_the_full_image() {
+        let (engine, mut img) = stamped(&SynthesisOptions {
+            offset: (2, -2),
+            ..SynthesisOptions::default()
+        });
+        let attempt = Attempt {
+            size: WatermarkSize::Small,
+            dx: 0,
+            dy: 0,
+            logo_value: 255.0,
+        };
+        engine.remove_attempt(&mut img, attempt);
+        let opts = ProcessOptions::default();
+        let residual = engine.residual_at(&img, attempt, &opts);
+
+        // The crop keeps every reference region and the model fit ring
+        let config = DetectionConfig {
+            color_weight: 0.0,
+            model_weight: 0.0,
+            threshold: opts.residual_threshold,
+            ..opts.detection
+        };
+        let alpha_map = engine.alpha_map(WatermarkSize::Small);
+        let detect = |img: &RgbImage| {
+            detection::detect_watermark(img, alpha_map, 320, 220, 255.0, &config)
+                .unwrap()
+                .confidence
+        };
+        let mut inverted = img.clone();
+        image::imageops::invert(&mut inverted);
+        let full = detect(&img).max(detect(&inverted));
+        assert!(full > opts.residual_threshold, "{full}");
+        assert!(
+            (residual.confidence - full).abs() < 1e-6,
+            "{residual:?} vs {full}"
+        );
+    }
+
+    #[test]
+    fn rounding_error_is_not_a_ghost() {
+        let engine = WatermarkEngine::new().unwrap();
+        let mut img = RgbImage::from_pixel(200, 200, image::Rgb([90, 110, 130]));
+        engine
+            .apply(&mut img, &SynthesisOptions::default())
+            .unwrap();
+        let opts = ProcessOptions {
+            verify: true,
+            ..ProcessOptions::default()
+        };
+        let verification = engine.remove_verified(&mut img, &opts);
+        assert_eq!(verification.residual, Some(0.0));
+
+        let alpha = [0.0, 0.5, 1.0, 0.5];
+        let levels = ghost_levels(&[0.5, 0.45, 0.4, 0.45], &alpha);
+        assert!((levels + 0.1 * 255.0).abs() < 1e-3, "{levels}");
+        assert!(ghost_levels(&[0.3; 4], &alpha).abs() < f32::EPSILON);
+    }
+}
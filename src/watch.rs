@@ -0,0 +1,330 @@
+//! Watch a directory and clean new images as they arrive.
+//!
+//! Uses the platform's native file notifications (inotify on Linux) and falls
+//! back to polling when they are unavailable, e.g. on network filesystems.
+//! A file is only processed once its size and modification time have stopped
+//! changing for [`WatchOptions::settle`], so partially copied files are never read.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime};
+
+use notify::{EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::batch::{BatchObserver, BatchSummary, CancellationToken};
+use crate::engine::{is_supported_image, ProcessOptions, WatermarkEngine};
+use crate::error::{Error, Result};
+
+/// Options controlling directory watching.
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    /// How long a file must stay unchanged before it is processed.
+    pub settle: Duration,
+    /// Scan interval for the polling fallback.
+    pub poll_interval: Duration,
+    /// Always poll instead of using native file notifications.
+    pub force_polling: bool,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            settle: Duration::from_secs(2),
+            poll_interval: Duration::from_secs(1),
+            force_polling: false,
+        }
+    }
+}
+
+/// Files seen by the watcher that are still being written.
+#[derive(Debug, Default)]
+struct PendingFiles {
+    files: HashMap<PathBuf, FileState>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FileState {
+    len: u64,
+    modified: Option<SystemTime>,
+    stable_since: Instant,
+}
+
+impl PendingFiles {
+    /// Record activity on `path`, restarting its settle timer.
+    fn touch(&mut self, path: PathBuf, now: Instant) {
+        let Ok(meta) = std::fs::metadata(&path) else {
+            return;
+        };
+        self.files.insert(
+            path,
+            FileState {
+                len: meta.len(),
+                modified: meta.modified().ok(),
+                stable_since: now,
+            },
+        );
+    }
+
+    /// Remove and return the files that have not changed for `settle`.
+    fn take_settled(&mut self, now: Instant, settle: Duration) -> Vec<PathBuf> {
+        let mut settled = Vec::new();
+        self.files.retain(|path, state| {
+            let Ok(meta) = std::fs::metadata(path) else {
+                // Deleted or renamed away before it settled
+                return false;
+            };
+            let modified = meta.modified().ok();
+            if meta.len() != state.len || modified != state.modified {
+                state.len = meta.len();
+                state.modified = modified;
+                state.stable_since = now;
+                return true;
+            }
+            if now.duration_since(state.stable_since) >= settle {
+                settled.push(path.clone());
+                return false;
+            }
+            true
+        });
+        settled.sort();
+        settled
+    }
+
+    fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+impl WatermarkEngine {
+    /// Watch `input_dir` and clean each new supported image into `output_dir`.
+    ///
+    /// Only files created or modified after the watch starts are processed.
+    /// Each one goes through [`process_file`](Self::process_file) once it has
+    /// settled, and the result is reported to `observer`. Runs until `cancel`
+    /// is cancelled.
+    ///
+    /// `output_dir` must differ from `input_dir`, otherwise cleaned outputs
+    /// would be picked up again as new files.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidArgument`] if the directories are identical,
+    /// [`Error::Io`] if they cannot be created or resolved, and
+    /// [`Error::Watch`] if neither native notifications nor polling can be set up.
+    pub fn watch_directory(
+        &self,
+        input_dir: &Path,
+        output_dir: &Path,
+        opts: &ProcessOptions,
+        watch: &WatchOptions,
+        observer: &dyn BatchObserver,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        std::fs::create_dir_all(output_dir)?;
+        if input_dir.canonicalize()? == output_dir.canonicalize()? {
+            return Err(Error::InvalidArgument(
+                "watch output directory must differ from the input directory".to_string(),
+            ));
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = new_watcher(tx, watch)?;
+        watcher.watch(input_dir, RecursiveMode::NonRecursive)?;
+
+        observer.batch_started(None);
+        let mut pending = PendingFiles::default();
+        // Running totals: a watch can run indefinitely, so results are not kept
+        let mut summary = BatchSummary::default();
+        // Wake up often enough to notice settled files and cancellation promptly
+        let tick = (watch.settle / 4).clamp(Duration::from_millis(10), Duration::from_millis(250));
+
+        while !cancel.is_cancelled() {
+            match rx.recv_timeout(tick) {
+                Ok(Ok(event)) => {
+                    if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                        let now = Instant::now();
+                        for path in event.paths {
+                            if is_supported_image(&path) && path.is_file() {
+                                pending.touch(path, now);
+                            }
+                        }
+                    }
+                }
+                // Transient notification errors (e.g. queue overflow) are not fatal
+                Ok(Err(_)) | Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+
+            if pending.is_empty() {
+                continue;
+            }
+            for input in pending.take_settled(Instant::now(), watch.settle) {
+                if cancel.is_cancelled() {
+                    break;
+                }
+                let output = output_dir.join(input.file_name().unwrap_or_default());
+                observer.file_started(&input);
+                let result = self.process_file(&input, &output, opts);
+                observer.file_finished(&result);
+                summary.total += 1;
+                summary.record(&result);
+            }
+        }
+
+        observer.batch_finished(&summary);
+        Ok(())
+    }
+}
+
+/// Create a native watcher, falling back to polling if it is unavailable.
+fn new_watcher(
+    tx: mpsc::Sender<notify::Result<notify::Event>>,
+    watch: &WatchOptions,
+) -> Result<Box<dyn Watcher>> {
+    let config = notify::Config::default().with_poll_interval(watch.poll_interval);
+    if !watch.force_polling {
+        if let Ok(watcher) = RecommendedWatcher::new(tx.clone(), config) {
+            return Ok(Box::new(watcher));
+        }
+    }
+    Ok(Box::new(PollWatcher::new(tx, config)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::ProcessResult;
+    use image::RgbImage;
+    use std::sync::Mutex;
+
+    #[test]
+    fn pending_file_settles_after_quiet_period() {
+        let dir = std::env::temp_dir().join("gwr_test_watch_settle");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.png");
+        std::fs::write(&path, "partial").unwrap();
+
+        let start = Instant::now();
+        let settle = Duration::from_secs(2);
+        let mut pending = PendingFiles::default();
+        pending.touch(path.clone(), start);
+
+        assert!(pending
+            .take_settled(start + Duration::from_secs(1), settle)
+            .is_empty());
+
+        // Growing file restarts the timer
+        std::fs::write(&path, "partial, now complete").unwrap();
+        let grown = start + Duration::from_secs(3);
+        assert!(pending.take_settled(grown, settle).is_empty());
+        assert!(pending
+            .take_settled(grown + Duration::from_secs(1), settle)
+            .is_empty());
+
+        let settled = pending.take_settled(grown + settle, settle);
+        assert_eq!(settled, vec![path]);
+        assert!(pending.is_empty());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn pending_file_dropped_when_deleted() {
+        let dir = std::env::temp_dir().join("gwr_test_watch_deleted");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.png");
+        std::fs::write(&path, "data").unwrap();
+
+        let now = Instant::now();
+        let mut pending = PendingFiles::default();
+        pending.touch(path.clone(), now);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(pending.take_settled(now, Duration::ZERO).is_empty());
+        assert!(pending.is_empty());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn watch_directory_rejects_same_input_and_output() {
+        let dir = std::env::temp_dir().join("gwr_test_watch_same_dir");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let engine = WatermarkEngine::new().unwrap();
+        let result = engine.watch_directory(
+            &dir,
+            &dir,
+            &ProcessOptions::default(),
+            &WatchOptions::default(),
+            &(),
+            &CancellationToken::new(),
+        );
+        assert!(
+            matches!(result, Err(Error::InvalidArgument(_))),
+            "{result:?}"
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn watch_directory_processes_new_file_with_polling() {
+        struct StopAfterFirst {
+            cancel: CancellationToken,
+            finished: Mutex<Vec<ProcessResult>>,
+        }
+
+        impl BatchObserver for StopAfterFirst {
+            fn file_finished(&self, result: &ProcessResult) {
+                self.finished.lock().unwrap().push(result.clone());
+                self.cancel.cancel();
+            }
+        }
+
+        let dir = std::env::temp_dir().join("gwr_test_watch_poll_in");
+        let out_dir = std::env::temp_dir().join("gwr_test_watch_poll_out");
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let observer = StopAfterFirst {
+            cancel: CancellationToken::new(),
+            finished: Mutex::new(Vec::new()),
+        };
+        let watch = WatchOptions {
+            settle: Duration::from_millis(100),
+            poll_interval: Duration::from_millis(20),
+            force_polling: true,
+        };
+        let opts = ProcessOptions {
+            force: true,
+            ..ProcessOptions::default()
+        };
+        let engine = WatermarkEngine::new().unwrap();
+
+        std::thread::scope(|scope| {
+            let watcher = scope.spawn(|| {
+                engine.watch_directory(&dir, &out_dir, &opts, &watch, &observer, &observer.cancel)
+            });
+            std::thread::sleep(Duration::from_millis(200));
+            RgbImage::new(200, 200).save(dir.join("new.png")).unwrap();
+
+            // Safety net so a broken watcher cannot hang the test suite
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while !watcher.is_finished() && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            observer.cancel.cancel();
+            watcher.join().unwrap().unwrap();
+        });
+
+        let finished = observer.finished.lock().unwrap();
+        assert_eq!(finished.len(), 1, "New file should be processed once");
+        assert!(finished[0].success, "{}", finished[0].message);
+        assert!(out_dir.join("new.png").exists());
+
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::remove_dir_all(&out_dir).ok();
+    }
+}
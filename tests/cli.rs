@@ -0,0 +1,189 @@
+//! End-to-end tests of the `gemini-watermark` binary.
+
+use std::path::Path;
+use std::process::Command;
+
+use gemini_watermark_removal::alpha_map::AlphaMap;
+use gemini_watermark_removal::profile::{LogoSpec, LogoVersion};
+use gemini_watermark_removal::{SynthesisOptions, WatermarkEngine};
+use image::RgbImage;
+
+/// Ring-shaped alpha map, unlike the current Gemini sparkle.
+fn ring(size: u32) -> AlphaMap {
+    #[allow(clippy::cast_precision_loss)]
+    let half = size as f32 / 2.0;
+    let values = (0..size * size)
+        .map(|i| {
+            #[allow(clippy::cast_precision_loss)]
+            let (dx, dy) = ((i % size) as f32 - half, (i / size) as f32 - half);
+            let r = (dx * dx + dy * dy).sqrt() / half;
+            if (0.5..0.9).contains(&r) {
+                0.5
+            } else {
+                0.0
+            }
+        })
+        .collect();
+    AlphaMap::new(values, size, size).unwrap()
+}
+
+fn run(args: &[&Path]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_gemini-watermark"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn profile_file_adds_an_older_gemini_logo_version() {
+    let dir = std::env::temp_dir().join("gwr_test_cli_versions");
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir_all(&dir).unwrap();
+    ring(40).save_png(&dir.join("ring-40.png")).unwrap();
+    ring(80).save_png(&dir.join("ring-80.png")).unwrap();
+    let profile = dir.join("gemini-2019.conf");
+    std::fs::write(
+        &profile,
+        "name = gemini\nversion = 2019\nsmall = ring-40.png\nsmall_margin = 20\nlarge = ring-80.png\nlarge_margin = 20\n",
+    )
+    .unwrap();
+
+    // Stamp the older logo with the library
+    let spec = |size| LogoSpec::new(ring(size), 20).unwrap();
+    let stamper = WatermarkEngine::new()
+        .unwrap()
+        .with_logo_version(
+            "gemini",
+            LogoVersion::new("2019", spec(40), spec(80)).unwrap(),
+        )
+        .unwrap()
+        .use_logo_version("2019")
+        .unwrap();
+    #[allow(clippy::cast_possible_truncation)]
+    let original = RgbImage::from_fn(400, 300, |x, y| {
+        image::Rgb([(x % 200) as u8, (y % 200) as u8, ((x + y) % 150) as u8])
+    });
+    let mut img = original.clone();
+    stamper
+        .apply(&mut img, &SynthesisOptions::default())
+        .unwrap();
+    let input = dir.join("old.png");
+    img.save(&input).unwrap();
+    let output = dir.join("old_out.png");
+
+    // Without the profile file the older logo is not found
+    let out = run(&[&input, Path::new("-o"), &output]);
+    assert!(out.status.success(), "{out:?}");
+    assert!(
+        String::from_utf8_lossy(&out.stderr).contains("[SKIP]"),
+        "{out:?}"
+    );
+
+    for pin in [false, true] {
+        let mut args = vec![
+            &*input,
+            Path::new("-o"),
+            &output,
+            Path::new("--profile-file"),
+            &profile,
+        ];
+        if pin {
+            args.extend([
+                Path::new("--profile"),
+                Path::new("gemini"),
+                Path::new("--logo-version"),
+                Path::new("2019"),
+            ]);
+        }
+        let out = run(&args);
+        assert!(out.status.success(), "{out:?}");
+        assert!(
+            String::from_utf8_lossy(&out.stderr).contains("[OK]"),
+            "{out:?}"
+        );
+
+        let cleaned = image::open(&output).unwrap().to_rgb8();
+        let max_diff = cleaned
+            .pixels()
+            .zip(original.pixels())
+            .flat_map(|(a, b)| a.0.iter().zip(b.0).map(|(&a, b)| a.abs_diff(b)))
+            .max()
+            .unwrap();
+        assert!(max_diff <= 3, "pinned {pin}: max diff {max_diff}");
+        std::fs::remove_file(&output).unwrap();
+    }
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[cfg(all(unix, feature = "watch"))]
+#[test]
+fn watch_stops_with_a_summary_on_ctrl_c() {
+    use std::process::Stdio;
+    use std::time::{Duration, Instant};
+
+    let dir = std::env::temp_dir().join("gwr_test_cli_watch_ctrl_c");
+    std::fs::remove_dir_all(&dir).ok();
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    std::fs::create_dir_all(&input).unwrap();
+
+    let child = Command::new(env!("CARGO_BIN_EXE_gemini-watermark"))
+        .args(["watch", "--settle-ms", "50", "-o"])
+        .args([&output, &input])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(500));
+
+    // Files appear complete: written beside the watched folder, then moved in
+    let engine = WatermarkEngine::new().unwrap();
+    for name in ["a.png", "b.png"] {
+        #[allow(clippy::cast_possible_truncation)]
+        let mut img = RgbImage::from_fn(400, 300, |x, y| {
+            image::Rgb([(x % 200) as u8, (y % 200) as u8, ((x + y) % 150) as u8])
+        });
+        engine
+            .apply(&mut img, &SynthesisOptions::default())
+            .unwrap();
+        img.save(dir.join(name)).unwrap();
+        std::fs::rename(dir.join(name), input.join(name)).unwrap();
+    }
+    let deadline = Instant::now() + Duration::from_secs(20);
+    while std::fs::read_dir(&output).map_or(0, Iterator::count) < 2 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    let interrupted = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(interrupted.success());
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success(), "{out:?}");
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout).lines().count(),
+        2,
+        "{out:?}"
+    );
+    assert!(
+        String::from_utf8_lossy(&out.stderr).contains("[Summary] Processed: 2"),
+        "{out:?}"
+    );
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn logo_version_needs_a_named_profile() {
+    let out = run(&[
+        Path::new("missing.png"),
+        Path::new("--logo-version"),
+        Path::new("2019"),
+    ]);
+    assert!(!out.status.success(), "{out:?}");
+    assert!(
+        String::from_utf8_lossy(&out.stderr).contains("--logo-version needs a --profile"),
+        "{out:?}"
+    );
+}
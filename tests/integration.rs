@@ -71,3 +71,74 @@ fn force_size_overrides_detection() {
     // Should run without error even though image is small
     assert!(!result.detected);
 }
+
+#[test]
+fn synthesized_fixture_is_cleaned_by_process_file() {
+    use gemini_watermark_removal::SynthesisOptions;
+
+    let dir = std::env::temp_dir().join("gwr_integration_synthesized");
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("stamped.png");
+    let output = dir.join("cleaned.png");
+
+    let engine = WatermarkEngine::new().unwrap();
+    #[allow(clippy::cast_possible_truncation)]
+    let original = RgbImage::from_fn(640, 480, |x, y| {
+        image::Rgb([(x / 4) as u8, (y / 3) as u8, ((x + y) / 8) as u8])
+    });
+    let mut stamped = original.clone();
+    engine
+        .apply(&mut stamped, &SynthesisOptions::default())
+        .unwrap();
+    stamped.save(&input).unwrap();
+
+    let result = engine.process_file(&input, &output, &ProcessOptions::default());
+    assert!(result.success && !result.skipped, "{}", result.message);
+
+    let cleaned = image::open(&output).unwrap().to_rgb8();
+    let report = engine
+        .quality_report(&cleaned, Some(&original), &ProcessOptions::default())
+        .unwrap();
+    assert!(!report.residual_detected);
+    assert!(report.region_psnr.unwrap() > 40.0);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn evaluation_separates_labeled_folders() {
+    use gemini_watermark_removal::{evaluation, SynthesisOptions};
+
+    let dir = std::env::temp_dir().join("gwr_integration_evaluation");
+    let (watermarked, clean) = (dir.join("watermarked"), dir.join("clean"));
+    std::fs::create_dir_all(&watermarked).unwrap();
+    std::fs::create_dir_all(&clean).unwrap();
+
+    let engine = WatermarkEngine::new().unwrap();
+    for i in 0..3u32 {
+        #[allow(clippy::cast_possible_truncation)]
+        let original = RgbImage::from_fn(400, 300, |x, y| {
+            image::Rgb([((x + i * 40) / 3) as u8, (y / 2) as u8, ((x + y) / 4) as u8])
+        });
+        original.save(clean.join(format!("{i}.png"))).unwrap();
+        let mut stamped = original;
+        engine
+            .apply(&mut stamped, &SynthesisOptions::default())
+            .unwrap();
+        stamped.save(watermarked.join(format!("{i}.png"))).unwrap();
+    }
+
+    let images = evaluation::labeled_images(&watermarked, &clean).unwrap();
+    assert_eq!(images.len(), 6);
+    let scores: Vec<_> = engine
+        .score_images(&images, &ProcessOptions::default())
+        .into_iter()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    let eval = evaluation::evaluate(&scores).unwrap();
+    assert!((eval.roc_auc - 1.0).abs() < 1e-12);
+    let point = eval.threshold_for_fpr(0.0).unwrap();
+    assert!((point.true_positive_rate - 1.0).abs() < 1e-12);
+
+    std::fs::remove_dir_all(&dir).ok();
+}